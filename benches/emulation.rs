@@ -0,0 +1,319 @@
+//! Emulation speed on fixed workloads, reported against the real 2 MHz
+//! 8080.
+//!
+//! Each workload runs four times: calling `Cpu::step` once per instruction,
+//! which measures raw opcode dispatch, then through `Cpu::run` stepping
+//! instruction by instruction, from the block cache, and from the block
+//! cache with lazy flags.
+//!
+//! Run with `cargo bench --bench emulation [-- <workload>...]`. Every run
+//! appends one JSON line per workload to `target/bench/emulation.jsonl`, or
+//! to `$BENCH_RESULTS` when set, so results can be compared across commits.
+//!
+//! The exerciser workload runs a CP/M exerciser such as TST8080.COM or
+//! 8080EXER.COM from `ROM/exerciser.com`, or from `$EXERCISER` when set.
+//! It is skipped when neither file exists.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    process::Command,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use intel8080::cpu::{Cpu, RunExit};
+
+const ROM_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/ROM");
+const RESULTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/target/bench/emulation.jsonl");
+const REAL_MHZ: f64 = 2.0;
+const HALF_FRAME: u64 = 16_667;
+const ROUNDS: usize = 5;
+
+/// ADD/ADC/SUB/SBB/ANA/XRA/ORA/CMP, rotates, INR/DCR, DAA and immediates
+/// in a tight loop.
+#[rustfmt::skip]
+const ALU_LOOP: &[u8] = &[
+    0x0e, 0x37,       // MVI C,37
+    0x16, 0x5a,       // MVI D,5a
+    0x1e, 0x91,       // MVI E,91
+    0x26, 0xc3,       // MVI H,c3
+    0x2e, 0x0f,       // MVI L,0f
+    0x81, 0x8a, 0x93, 0x9c, 0xa5, 0xa8, 0xb1, 0xba,
+    0x07, 0x1f, 0x0c, 0x15, 0x27,
+    0xc6, 0x11,       // ADI 11
+    0xde, 0x05,       // SBI 05
+    0xc3, 0x0a, 0x00, // JMP 000a
+];
+
+/// Copies 4 KiB from 2000 to 3000 a byte at a time, with a PUSH/POP per
+/// byte, then starts over.
+#[rustfmt::skip]
+const MEMORY_LOOP: &[u8] = &[
+    0x31, 0x00, 0x40, // LXI SP,4000
+    0x21, 0x00, 0x20, // LXI H,2000
+    0x11, 0x00, 0x30, // LXI D,3000
+    0x01, 0x00, 0x10, // LXI B,1000
+    0x7e,             // MOV A,M
+    0x12,             // STAX D
+    0x23,             // INX H
+    0x13,             // INX D
+    0xc5,             // PUSH B
+    0xc1,             // POP B
+    0x0b,             // DCX B
+    0x78,             // MOV A,B
+    0xb1,             // ORA C
+    0xc2, 0x0c, 0x00, // JNZ 000c
+    0xc3, 0x03, 0x00, // JMP 0003
+];
+
+/// Minimal CP/M page zero. The first visit to 0000 jumps to the program at
+/// 0100 and the warm boot it ends with halts. BDOS calls return without
+/// doing anything.
+#[rustfmt::skip]
+const CPM_PAGE_ZERO: &[u8] = &[
+    0xc3, 0x40, 0x00, // 0000: JMP 0040
+    0x00, 0x00,
+    0xc9,             // 0005: RET (BDOS)
+    0x00, 0xf0,       // 0006: top of the TPA, read by some programs for SP
+];
+
+#[rustfmt::skip]
+const CPM_BOOT: &[u8] = &[
+    0x21, 0x4d, 0x00, // 0040: LXI H,004d
+    0x7e,             // MOV A,M
+    0xb7,             // ORA A
+    0xc2, 0x4e, 0x00, // JNZ 004e
+    0x34,             // INR M
+    0xc3, 0x00, 0x01, // JMP 0100
+    0x00,             // 004c: unused
+    0x00,             // 004d: booted flag
+    0x76,             // 004e: HLT
+];
+
+struct Workload {
+    name: &'static str,
+    image: Vec<u8>,
+    /// Cycles per round; runs stop earlier if the CPU halts.
+    cycles: u64,
+    /// Fire RST 1 and RST 2 alternately every half frame, as the Invaders
+    /// video hardware does.
+    screen_interrupts: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Mode {
+    name: &'static str,
+    /// Call `Cpu::step` from here instead of `Cpu::run`.
+    single_step: bool,
+    blocks: bool,
+    lazy_flags: bool,
+}
+
+const MODES: [Mode; 4] = [
+    Mode {
+        name: "dispatch",
+        single_step: true,
+        blocks: false,
+        lazy_flags: false,
+    },
+    Mode {
+        name: "step",
+        single_step: false,
+        blocks: false,
+        lazy_flags: false,
+    },
+    Mode {
+        name: "block",
+        single_step: false,
+        blocks: true,
+        lazy_flags: false,
+    },
+    Mode {
+        name: "lazy",
+        single_step: false,
+        blocks: true,
+        lazy_flags: true,
+    },
+];
+
+struct Measurement {
+    instructions: u64,
+    cycles: u64,
+    seconds: f64,
+}
+
+fn run(workload: &Workload, mode: Mode) -> Measurement {
+    let mut cpu = Cpu::new();
+    if mode.blocks {
+        cpu.enable_block_cache();
+    }
+    if mode.lazy_flags {
+        cpu.enable_lazy_flags();
+    }
+    cpu.load_rom(&workload.image);
+    let mut next_interrupt = HALF_FRAME;
+    let mut vector = 1;
+    let start = Instant::now();
+    while cpu.cycles() < workload.cycles {
+        let until = match workload.screen_interrupts {
+            true => next_interrupt.min(workload.cycles),
+            false => workload.cycles,
+        };
+        if mode.single_step {
+            while cpu.cycles() < until && !cpu.halted() {
+                cpu.step()
+                    .unwrap_or_else(|error| panic!("{}: {}", workload.name, error));
+            }
+            if cpu.halted() {
+                break;
+            }
+        } else {
+            let exit = cpu
+                .run(until, &[])
+                .unwrap_or_else(|error| panic!("{}: {}", workload.name, error));
+            if exit == RunExit::Halted {
+                break;
+            }
+        }
+        if workload.screen_interrupts && cpu.cycles() >= next_interrupt {
+            cpu.interrupt(vector);
+            vector ^= 3;
+            next_interrupt += HALF_FRAME;
+        }
+    }
+    Measurement {
+        instructions: cpu.instructions(),
+        cycles: cpu.cycles(),
+        seconds: start.elapsed().as_secs_f64(),
+    }
+}
+
+fn cpm_image(program: &[u8]) -> Vec<u8> {
+    let mut image = vec![0; 0x100 + program.len()];
+    image[..CPM_PAGE_ZERO.len()].copy_from_slice(CPM_PAGE_ZERO);
+    image[0x40..0x40 + CPM_BOOT.len()].copy_from_slice(CPM_BOOT);
+    image[0x100..].copy_from_slice(program);
+    image
+}
+
+fn workloads() -> Vec<Workload> {
+    let mut workloads = Vec::new();
+    let invaders = format!("{}/invaders.concatenated", ROM_DIR);
+    match fs::read(&invaders) {
+        Ok(image) => workloads.push(Workload {
+            name: "invaders",
+            image,
+            cycles: 150_000_000,
+            screen_interrupts: true,
+        }),
+        Err(error) => eprintln!("invaders: skipped, {}: {}", invaders, error),
+    }
+    let exerciser = std::env::var("EXERCISER").unwrap_or(format!("{}/exerciser.com", ROM_DIR));
+    match fs::read(&exerciser) {
+        Ok(program) => workloads.push(Workload {
+            name: "exerciser",
+            image: cpm_image(&program),
+            cycles: 1_500_000_000,
+            screen_interrupts: false,
+        }),
+        Err(error) => eprintln!("exerciser: skipped, {}: {}", exerciser, error),
+    }
+    workloads.push(Workload {
+        name: "alu",
+        image: ALU_LOOP.to_vec(),
+        cycles: 100_000_000,
+        screen_interrupts: false,
+    });
+    workloads.push(Workload {
+        name: "memory",
+        image: MEMORY_LOOP.to_vec(),
+        cycles: 150_000_000,
+        screen_interrupts: false,
+    });
+    workloads
+}
+
+fn git_revision() -> String {
+    Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_default()
+}
+
+fn main() {
+    // cargo passes `--bench`; anything else selects workloads by name.
+    let filters = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .collect::<Vec<_>>();
+    let path = std::env::var("BENCH_RESULTS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(RESULTS));
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .unwrap_or_else(|error| panic!("{}: {}", parent.display(), error));
+    }
+    let mut results = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+    let revision = git_revision();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+
+    println!(
+        "{:<10} {:<8} {:>12} {:>9} {:>12} {:>10} {:>8}",
+        "workload", "mode", "instructions", "seconds", "M instr/s", "MHz", "vs 2MHz"
+    );
+    for workload in workloads() {
+        if !filters.is_empty()
+            && !filters
+                .iter()
+                .any(|filter| workload.name.contains(filter.as_str()))
+        {
+            continue;
+        }
+        for mode in MODES {
+            let best = (0..ROUNDS)
+                .map(|_| run(&workload, mode))
+                .min_by(|a, b| a.seconds.total_cmp(&b.seconds))
+                .unwrap();
+            let instructions_per_second = best.instructions as f64 / best.seconds;
+            let mhz = best.cycles as f64 / best.seconds / 1e6;
+            println!(
+                "{:<10} {:<8} {:>12} {:>9.3} {:>12.1} {:>10.1} {:>7.1}x",
+                workload.name,
+                mode.name,
+                best.instructions,
+                best.seconds,
+                instructions_per_second / 1e6,
+                mhz,
+                mhz / REAL_MHZ
+            );
+            writeln!(
+                results,
+                "{{\"timestamp\":{},\"revision\":\"{}\",\"workload\":\"{}\",\"mode\":\"{}\",\"instructions\":{},\"cycles\":{},\"seconds\":{:.6},\"instructions_per_second\":{:.0},\"mhz\":{:.3},\"speedup\":{:.3}}}",
+                timestamp,
+                revision,
+                workload.name,
+                mode.name,
+                best.instructions,
+                best.cycles,
+                best.seconds,
+                instructions_per_second,
+                mhz,
+                mhz / REAL_MHZ
+            )
+            .unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+        }
+    }
+    println!("results appended to {}", path.display());
+}
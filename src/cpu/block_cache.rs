@@ -0,0 +1,205 @@
+use std::rc::Rc;
+
+use super::dispatch::{Entry, Table};
+
+/// Longest run of instructions decoded into one block.
+pub const MAX_BLOCK_INSTRUCTIONS: usize = 32;
+const MAX_BLOCK_BYTES: usize = MAX_BLOCK_INSTRUCTIONS * 3;
+
+/// One pre-decoded instruction of a block.
+#[derive(Clone, Copy)]
+pub struct Op {
+    pub entry: Entry,
+    pub operands: [u8; 2],
+}
+
+#[derive(Clone)]
+pub(super) struct Block {
+    pub ops: Rc<[Op]>,
+    bytes: u8,
+    /// Ends in a jump back to its own start and neither stores, does I/O
+    /// nor touches the interrupt state, so a pass that leaves the registers
+    /// as they were will repeat identically until something external
+    /// happens.
+    pub idle_candidate: bool,
+}
+
+/// Straight-line runs of decoded instructions keyed by start address.
+///
+/// A block ends after the first jump, call, return, RST, PCHL or HLT, before
+/// an unknown opcode, or after `MAX_BLOCK_INSTRUCTIONS`. Writing any byte a
+/// block was decoded from drops the block.
+pub struct BlockCache {
+    blocks: Vec<Option<Block>>,
+    /// Number of blocks decoded from each address.
+    covered: Vec<u8>,
+    len: usize,
+    compiled: u64,
+    invalidations: u64,
+    idle_cycles: u64,
+    written: bool,
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Instructions after which execution may continue somewhere other than
+/// the next address.
+fn ends_block(opcode: u8) -> bool {
+    matches!(opcode, 0x76 | 0xc3 | 0xc9 | 0xcd | 0xe9)
+        || opcode >= 0xc0 && matches!(opcode & 0x07, 0 | 2 | 4 | 7)
+}
+
+/// Instructions whose effect can differ between two passes over the same
+/// registers and memory, or that change memory themselves.
+fn has_side_effects(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        0x02 | 0x12 | 0x22 | 0x32 | 0x34 | 0x35 | 0x36 // stores, INR M, DCR M
+            | 0x70..=0x75 | 0x77 // MOV M,r
+            | 0xc5 | 0xd5 | 0xe5 | 0xf5 | 0xe3 // PUSH, XTHL
+            | 0xd3 | 0xdb // OUT, IN
+            | 0x76 | 0xf3 | 0xfb // HLT, DI, EI
+    ) || opcode >= 0xc0 && matches!(opcode & 0x07, 4 | 7) // calls, RST
+        || opcode == 0xcd
+}
+
+impl BlockCache {
+    pub fn new() -> BlockCache {
+        BlockCache {
+            blocks: vec![None; 0x10000],
+            covered: vec![0; 0x10000],
+            len: 0,
+            compiled: 0,
+            invalidations: 0,
+            idle_cycles: 0,
+            written: false,
+        }
+    }
+
+    /// The block starting at `address`, decoding it on first use. Empty
+    /// when the opcode at `address` is unknown; those are not cached.
+    pub(super) fn get(&mut self, address: u16, memory: &[u8], table: &Table) -> Block {
+        self.written = false;
+        if let Some(block) = &self.blocks[address as usize] {
+            return block.clone();
+        }
+        let mut ops = Vec::new();
+        let mut bytes = 0;
+        let mut side_effects = false;
+        let mut loops = false;
+        while ops.len() < MAX_BLOCK_INSTRUCTIONS {
+            let pc = address.wrapping_add(bytes as u16);
+            let opcode = memory[pc as usize];
+            let Some(entry) = table[opcode as usize] else {
+                break;
+            };
+            ops.push(Op {
+                entry,
+                operands: [
+                    memory[pc.wrapping_add(1) as usize],
+                    memory[pc.wrapping_add(2) as usize],
+                ],
+            });
+            bytes += entry.size;
+            side_effects |= has_side_effects(opcode);
+            if ends_block(opcode) {
+                // JMP or a conditional jump back to the start
+                let target = u16::from_le_bytes([
+                    memory[pc.wrapping_add(1) as usize],
+                    memory[pc.wrapping_add(2) as usize],
+                ]);
+                loops = (opcode == 0xc3 || opcode & 0xc7 == 0xc2) && target == address;
+                break;
+            }
+        }
+        let block = Block {
+            ops: ops.into(),
+            bytes,
+            idle_candidate: loops && !side_effects,
+        };
+        if !block.ops.is_empty() {
+            for offset in 0..bytes as u16 {
+                self.covered[address.wrapping_add(offset) as usize] += 1;
+            }
+            self.blocks[address as usize] = Some(block.clone());
+            self.len += 1;
+            self.compiled += 1;
+        }
+        block
+    }
+
+    /// Counts cycles skipped over an idle loop.
+    pub(super) fn skip_idle(&mut self, cycles: u64) {
+        self.idle_cycles += cycles;
+    }
+
+    /// Drops every block decoded from `address`.
+    pub(super) fn write(&mut self, address: u16) {
+        if self.covered[address as usize] == 0 {
+            return;
+        }
+        for back in 0..MAX_BLOCK_BYTES as u16 {
+            let start = address.wrapping_sub(back);
+            let hit = self.blocks[start as usize]
+                .as_ref()
+                .is_some_and(|block| back < block.bytes as u16);
+            if hit {
+                self.remove(start);
+            }
+        }
+        self.written = true;
+    }
+
+    fn remove(&mut self, start: u16) {
+        if let Some(block) = self.blocks[start as usize].take() {
+            for offset in 0..block.bytes as u16 {
+                self.covered[start.wrapping_add(offset) as usize] -= 1;
+            }
+            self.len -= 1;
+            self.invalidations += 1;
+        }
+    }
+
+    /// Whether a block was dropped since the last `get` or call to this.
+    /// The block being run may be stale past the current instruction when
+    /// this is set.
+    pub(super) fn take_written(&mut self) -> bool {
+        std::mem::take(&mut self.written)
+    }
+
+    /// Drops everything, for when memory is replaced wholesale.
+    pub fn clear(&mut self) {
+        self.blocks.fill(None);
+        self.covered.fill(0);
+        self.len = 0;
+        self.written = true;
+    }
+
+    /// Blocks currently cached.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Blocks decoded so far, including ones since dropped.
+    pub fn compiled(&self) -> u64 {
+        self.compiled
+    }
+
+    /// Blocks dropped because code they were decoded from was written.
+    pub fn invalidations(&self) -> u64 {
+        self.invalidations
+    }
+
+    /// Cycles fast-forwarded over idle loops instead of being run.
+    pub fn idle_cycles(&self) -> u64 {
+        self.idle_cycles
+    }
+}
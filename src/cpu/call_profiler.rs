@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use super::symbols::Symbols;
+
+/// Entry address used for code running outside of any tracked call.
+pub const ROOT: u16 = 0xffff;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FunctionStats {
+    pub calls: u64,
+    /// Cycles spent in the function and everything it called.
+    pub inclusive: u64,
+    /// Cycles spent in the function's own instructions.
+    pub exclusive: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EdgeStats {
+    pub calls: u64,
+    pub inclusive: u64,
+}
+
+struct Frame {
+    entry: u16,
+    /// SP right after the return address was pushed.
+    sp: u16,
+    start_cycles: u64,
+    node: usize,
+}
+
+/// A distinct call path, stored as a tree so the current path never has
+/// to be copied per instruction.
+struct Node {
+    entry: u16,
+    parent: usize,
+    children: HashMap<u16, usize>,
+    cycles: u64,
+}
+
+pub enum Transfer {
+    Call,
+    Return,
+    Other,
+}
+
+impl Transfer {
+    /// Classifies an executed opcode by whether it pushed or popped a
+    /// return address. Conditional forms only count when they were taken,
+    /// which the caller detects from the SP change.
+    pub fn classify(opcode: u8, sp_before: u16, sp_after: u16) -> Transfer {
+        let is_call = opcode == 0xcd || opcode & 0xc7 == 0xc4 || opcode & 0xc7 == 0xc7;
+        let is_return = opcode == 0xc9 || opcode & 0xc7 == 0xc0;
+        if is_call && sp_after == sp_before.wrapping_sub(2) {
+            Transfer::Call
+        } else if is_return && sp_after == sp_before.wrapping_add(2) {
+            Transfer::Return
+        } else {
+            Transfer::Other
+        }
+    }
+}
+
+/// Shadow call stack that attributes cycles to subroutines.
+pub struct CallProfiler {
+    stack: Vec<Frame>,
+    nodes: Vec<Node>,
+    functions: HashMap<u16, FunctionStats>,
+    edges: HashMap<(u16, u16), EdgeStats>,
+    now: u64,
+}
+
+impl Default for CallProfiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CallProfiler {
+    pub fn new() -> CallProfiler {
+        CallProfiler {
+            stack: Vec::new(),
+            nodes: vec![Node {
+                entry: ROOT,
+                parent: 0,
+                children: HashMap::new(),
+                cycles: 0,
+            }],
+            functions: HashMap::new(),
+            edges: HashMap::new(),
+            now: 0,
+        }
+    }
+
+    fn current(&self) -> (u16, usize) {
+        self.stack
+            .last()
+            .map(|frame| (frame.entry, frame.node))
+            .unwrap_or((ROOT, 0))
+    }
+
+    /// Called after every instruction with the cycles it took and the
+    /// cycle counter once it finished.
+    pub fn record(&mut self, transfer: Transfer, target: u16, sp: u16, cycles: u64, now: u64) {
+        self.now = now;
+        let (entry, node) = self.current();
+        self.functions.entry(entry).or_default().exclusive += cycles;
+        self.nodes[node].cycles += cycles;
+        match transfer {
+            Transfer::Call => {
+                let child = match self.nodes[node].children.get(&target) {
+                    Some(child) => *child,
+                    None => {
+                        self.nodes.push(Node {
+                            entry: target,
+                            parent: node,
+                            children: HashMap::new(),
+                            cycles: 0,
+                        });
+                        let child = self.nodes.len() - 1;
+                        self.nodes[node].children.insert(target, child);
+                        child
+                    }
+                };
+                self.functions.entry(target).or_default().calls += 1;
+                self.edges.entry((entry, target)).or_default().calls += 1;
+                self.stack.push(Frame {
+                    entry: target,
+                    sp,
+                    start_cycles: now - cycles,
+                    node: child,
+                });
+            }
+            Transfer::Return => {
+                // Frames whose return address sits below the popped slot are
+                // gone too; this keeps the stack sane when code discards
+                // return addresses or returns through a pushed address.
+                let popped_slot = sp.wrapping_sub(2);
+                while self
+                    .stack
+                    .last()
+                    .is_some_and(|frame| frame.sp <= popped_slot)
+                {
+                    let frame = self.stack.pop().unwrap();
+                    let inclusive = now - frame.start_cycles;
+                    if !self.stack.iter().any(|outer| outer.entry == frame.entry) {
+                        self.functions.entry(frame.entry).or_default().inclusive += inclusive;
+                    }
+                    let (caller, _) = self.current();
+                    self.edges
+                        .entry((caller, frame.entry))
+                        .or_default()
+                        .inclusive += inclusive;
+                }
+            }
+            Transfer::Other => {}
+        }
+    }
+
+    /// Per-function totals. Calls still in progress are counted up to the
+    /// last recorded instruction.
+    pub fn functions(&self) -> HashMap<u16, FunctionStats> {
+        let mut functions = self.functions.clone();
+        functions.entry(ROOT).or_default().inclusive = self.now;
+        for (depth, frame) in self.stack.iter().enumerate() {
+            if !self.stack[..depth]
+                .iter()
+                .any(|outer| outer.entry == frame.entry)
+            {
+                functions.entry(frame.entry).or_default().inclusive +=
+                    self.now - frame.start_cycles;
+            }
+        }
+        functions
+    }
+
+    /// Caller to callee totals, with calls in progress counted as in
+    /// `functions`.
+    pub fn edges(&self) -> HashMap<(u16, u16), EdgeStats> {
+        let mut edges = self.edges.clone();
+        for (depth, frame) in self.stack.iter().enumerate() {
+            let caller = depth
+                .checked_sub(1)
+                .map(|outer| self.stack[outer].entry)
+                .unwrap_or(ROOT);
+            edges.entry((caller, frame.entry)).or_default().inclusive +=
+                self.now - frame.start_cycles;
+        }
+        edges
+    }
+
+    /// Entry addresses of the calls in progress, outermost first.
+    pub fn call_stack(&self) -> Vec<u16> {
+        self.stack.iter().map(|frame| frame.entry).collect()
+    }
+
+    fn name(entry: u16, symbols: Option<&Symbols>) -> String {
+        if entry == ROOT {
+            return "<root>".to_string();
+        }
+        symbols
+            .and_then(|symbols| symbols.get(entry))
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("sub_{:04x}", entry))
+    }
+
+    /// One `caller;callee;... cycles` line per call path, the folded-stack
+    /// format read by flamegraph tools.
+    pub fn write_folded(&self, out: &mut impl Write, symbols: Option<&Symbols>) -> io::Result<()> {
+        for (index, node) in self.nodes.iter().enumerate() {
+            if node.cycles == 0 {
+                continue;
+            }
+            let mut path = vec![Self::name(node.entry, symbols)];
+            let mut current = index;
+            while current != 0 {
+                current = self.nodes[current].parent;
+                path.push(Self::name(self.nodes[current].entry, symbols));
+            }
+            path.reverse();
+            writeln!(out, "{} {}", path.join(";"), node.cycles)?;
+        }
+        Ok(())
+    }
+
+    /// Functions sorted by inclusive cycles, each followed by its callees.
+    pub fn report(&self, out: &mut impl Write, symbols: Option<&Symbols>) -> io::Result<()> {
+        let functions = self.functions();
+        let edges = self.edges();
+        let mut functions = functions.iter().collect::<Vec<_>>();
+        functions.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(a.0.cmp(b.0)));
+        writeln!(
+            out,
+            "{:<24}{:>10}{:>14}{:>14}",
+            "function", "calls", "inclusive", "exclusive"
+        )?;
+        for (entry, stats) in functions {
+            writeln!(
+                out,
+                "{:<24}{:>10}{:>14}{:>14}",
+                Self::name(*entry, symbols),
+                stats.calls,
+                stats.inclusive,
+                stats.exclusive
+            )?;
+            let mut callees = edges
+                .iter()
+                .filter(|((caller, _), _)| caller == entry)
+                .collect::<Vec<_>>();
+            callees.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(a.0.cmp(b.0)));
+            for ((_, callee), edge) in callees {
+                writeln!(
+                    out,
+                    "  -> {:<20}{:>10}{:>14}",
+                    Self::name(*callee, symbols),
+                    edge.calls,
+                    edge.inclusive
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
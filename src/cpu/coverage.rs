@@ -0,0 +1,206 @@
+use std::io::{self, Write};
+use std::ops::Range;
+
+use super::{symbols::Symbols, Instruction};
+
+/// Ways a memory byte can be touched. A byte accumulates every kind it has
+/// seen.
+pub mod access {
+    pub const OPCODE: u8 = 1 << 0;
+    pub const OPERAND: u8 = 1 << 1;
+    pub const READ: u8 = 1 << 2;
+    pub const WRITE: u8 = 1 << 3;
+}
+
+/// Per-byte record of how memory has been used since coverage started.
+pub struct Coverage {
+    flags: Vec<u8>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage {
+            flags: vec![0; 0x10000],
+        }
+    }
+
+    pub fn mark(&mut self, address: u16, kind: u8) {
+        self.flags[address as usize] |= kind;
+    }
+
+    pub fn flags(&self, address: u16) -> u8 {
+        self.flags[address as usize]
+    }
+
+    /// True when `address` was fetched as the first byte of an instruction.
+    pub fn is_code(&self, address: u16) -> bool {
+        self.flags(address) & access::OPCODE != 0
+    }
+
+    /// Addresses known to be executed, either as opcodes or operands.
+    pub fn code_addresses(&self) -> impl Iterator<Item = u16> + '_ {
+        self.flags
+            .iter()
+            .enumerate()
+            .filter(|(_, flags)| *flags & (access::OPCODE | access::OPERAND) != 0)
+            .map(|(address, _)| address as u16)
+    }
+
+    fn describe(flags: u8) -> String {
+        [
+            (access::OPCODE, 'X'),
+            (access::OPERAND, 'o'),
+            (access::READ, 'R'),
+            (access::WRITE, 'W'),
+        ]
+        .iter()
+        .map(|(kind, mark)| if flags & kind != 0 { *mark } else { '-' })
+        .collect()
+    }
+
+    /// Instruction starts in `range` found by executed opcodes plus a linear
+    /// sweep over bytes that were never touched, with whether each ran. The
+    /// sweep resyncs at every executed opcode, so code entered through an
+    /// operand byte gets a line of its own.
+    fn lines(&self, memory: &[u8], range: &Range<usize>) -> Vec<(u16, Option<Instruction>)> {
+        let mut lines = Vec::new();
+        let mut address = range.start;
+        while address < range.end {
+            let flags = self.flags[address];
+            if flags & access::OPCODE == 0 && flags & (access::READ | access::WRITE) != 0 {
+                lines.push((address as u16, None));
+                address += 1;
+                continue;
+            }
+            let instruction = Instruction::decode(memory, address as u16);
+            lines.push((address as u16, Some(instruction)));
+            let end = (address + instruction.size as usize).min(range.end);
+            address = (address + 1..end)
+                .find(|next| self.flags[*next] & access::OPCODE != 0)
+                .unwrap_or(address + instruction.size as usize);
+        }
+        lines
+    }
+
+    /// Disassembly of `range` with the access kinds of each line. Bytes only
+    /// ever used as data are listed as `db`; untouched bytes are decoded as
+    /// code so unreached paths stand out.
+    pub fn write_listing(
+        &self,
+        out: &mut impl Write,
+        memory: &[u8],
+        range: Range<usize>,
+        symbols: Option<&Symbols>,
+    ) -> io::Result<()> {
+        for (address, instruction) in self.lines(memory, &range) {
+            if let Some(label) = symbols.and_then(|symbols| symbols.get(address)) {
+                writeln!(out, "{}:", label)?;
+            }
+            let flags = Self::describe(self.flags(address));
+            match instruction {
+                Some(instruction) => writeln!(
+                    out,
+                    "{:04x}  {}  {}",
+                    address,
+                    flags,
+                    instruction.to_string().trim_end()
+                )?,
+                None => writeln!(
+                    out,
+                    "{:04x}  {}  db {:02x}",
+                    address, flags, memory[address as usize]
+                )?,
+            }
+        }
+        Ok(())
+    }
+
+    /// LCOV tracefile for `range`, one function record per label. Each
+    /// instruction line is reported under its address.
+    pub fn write_lcov(
+        &self,
+        out: &mut impl Write,
+        memory: &[u8],
+        range: Range<usize>,
+        symbols: &Symbols,
+        source: &str,
+    ) -> io::Result<()> {
+        let lines = self.lines(memory, &range);
+        let labels = symbols
+            .iter()
+            .filter(|(address, _)| range.contains(&(*address as usize)))
+            .collect::<Vec<_>>();
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{}", source)?;
+        for (address, name) in &labels {
+            writeln!(out, "FN:{},{}", address, name)?;
+        }
+        let mut functions_hit = 0;
+        for (address, name) in &labels {
+            let hit = self.is_code(*address) as u8;
+            functions_hit += hit as usize;
+            writeln!(out, "FNDA:{},{}", hit, name)?;
+        }
+        writeln!(out, "FNF:{}", labels.len())?;
+        writeln!(out, "FNH:{}", functions_hit)?;
+        let mut lines_hit = 0;
+        let mut lines_found = 0;
+        for (address, instruction) in &lines {
+            if instruction.is_none() {
+                continue;
+            }
+            let hit = self.is_code(*address) as u8;
+            lines_found += 1;
+            lines_hit += hit as usize;
+            writeln!(out, "DA:{},{}", address, hit)?;
+        }
+        writeln!(out, "LF:{}", lines_found)?;
+        writeln!(out, "LH:{}", lines_hit)?;
+        writeln!(out, "end_of_record")
+    }
+
+    /// Executed versus total instructions per label, as a plain-text table.
+    pub fn write_summary(
+        &self,
+        out: &mut impl Write,
+        memory: &[u8],
+        range: Range<usize>,
+        symbols: &Symbols,
+    ) -> io::Result<()> {
+        let lines = self.lines(memory, &range);
+        let mut labels = symbols
+            .iter()
+            .filter(|(address, _)| range.contains(&(*address as usize)))
+            .peekable();
+        writeln!(out, "{:<24}{:>8}{:>8}{:>8}", "label", "hit", "total", "%")?;
+        while let Some((start, name)) = labels.next() {
+            let end = labels
+                .peek()
+                .map(|(next, _)| *next as usize)
+                .unwrap_or(range.end);
+            let (hit, total) = lines
+                .iter()
+                .filter(|(address, instruction)| {
+                    instruction.is_some() && (start as usize..end).contains(&(*address as usize))
+                })
+                .fold((0, 0), |(hit, total), (address, _)| {
+                    (hit + self.is_code(*address) as usize, total + 1)
+                });
+            writeln!(
+                out,
+                "{:<24}{:>8}{:>8}{:>7.1}%",
+                name,
+                hit,
+                total,
+                100.0 * hit as f64 / total.max(1) as f64
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,324 @@
+use std::sync::OnceLock;
+
+use super::{
+    opcodes::{self, Opcodes},
+    ConditionCodes, Cpu, Registers,
+};
+
+/// Executes one instruction. PC and the cycle counter have already been
+/// advanced past it when this runs.
+pub type Handler = fn(&mut Cpu, [u8; 2]);
+
+/// Everything `Cpu::step` needs about an opcode, resolved once up front.
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub handler: Handler,
+    pub cycles: u8,
+    pub size: u8,
+}
+
+/// One entry per opcode byte, `None` for the unused ones.
+pub type Table = [Option<Entry>; 256];
+
+pub fn table() -> &'static Table {
+    static TABLE: OnceLock<Table> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        std::array::from_fn(|opcode| {
+            Opcodes::from_hex(opcode as u8).map(|decoded| {
+                let def = decoded.get_instruction_def();
+                Entry {
+                    handler: handler(decoded),
+                    cycles: def.cycles,
+                    size: def.size,
+                }
+            })
+        })
+    })
+}
+
+#[rustfmt::skip]
+fn handler(opcode: Opcodes) -> Handler {
+    match opcode {
+        // Data transfer
+        Opcodes::MOV_A_A => |cpu, _| opcodes::mov_r_r(cpu, Registers::A, Registers::A),
+        Opcodes::MOV_A_B => |cpu, _| opcodes::mov_r_r(cpu, Registers::A, Registers::B),
+        Opcodes::MOV_A_C => |cpu, _| opcodes::mov_r_r(cpu, Registers::A, Registers::C),
+        Opcodes::MOV_A_D => |cpu, _| opcodes::mov_r_r(cpu, Registers::A, Registers::D),
+        Opcodes::MOV_A_E => |cpu, _| opcodes::mov_r_r(cpu, Registers::A, Registers::E),
+        Opcodes::MOV_A_H => |cpu, _| opcodes::mov_r_r(cpu, Registers::A, Registers::H),
+        Opcodes::MOV_A_L => |cpu, _| opcodes::mov_r_r(cpu, Registers::A, Registers::L),
+        Opcodes::MOV_A_M => |cpu, _| opcodes::mov_r_m(cpu, Registers::A),
+
+        Opcodes::MOV_B_A => |cpu, _| opcodes::mov_r_r(cpu, Registers::B, Registers::A),
+        Opcodes::MOV_B_B => |cpu, _| opcodes::mov_r_r(cpu, Registers::B, Registers::B),
+        Opcodes::MOV_B_C => |cpu, _| opcodes::mov_r_r(cpu, Registers::B, Registers::C),
+        Opcodes::MOV_B_D => |cpu, _| opcodes::mov_r_r(cpu, Registers::B, Registers::D),
+        Opcodes::MOV_B_E => |cpu, _| opcodes::mov_r_r(cpu, Registers::B, Registers::E),
+        Opcodes::MOV_B_H => |cpu, _| opcodes::mov_r_r(cpu, Registers::B, Registers::H),
+        Opcodes::MOV_B_L => |cpu, _| opcodes::mov_r_r(cpu, Registers::B, Registers::L),
+        Opcodes::MOV_B_M => |cpu, _| opcodes::mov_r_m(cpu, Registers::B),
+
+        Opcodes::MOV_C_A => |cpu, _| opcodes::mov_r_r(cpu, Registers::C, Registers::A),
+        Opcodes::MOV_C_B => |cpu, _| opcodes::mov_r_r(cpu, Registers::C, Registers::B),
+        Opcodes::MOV_C_C => |cpu, _| opcodes::mov_r_r(cpu, Registers::C, Registers::C),
+        Opcodes::MOV_C_D => |cpu, _| opcodes::mov_r_r(cpu, Registers::C, Registers::D),
+        Opcodes::MOV_C_E => |cpu, _| opcodes::mov_r_r(cpu, Registers::C, Registers::E),
+        Opcodes::MOV_C_H => |cpu, _| opcodes::mov_r_r(cpu, Registers::C, Registers::H),
+        Opcodes::MOV_C_L => |cpu, _| opcodes::mov_r_r(cpu, Registers::C, Registers::L),
+        Opcodes::MOV_C_M => |cpu, _| opcodes::mov_r_m(cpu, Registers::C),
+
+        Opcodes::MOV_D_A => |cpu, _| opcodes::mov_r_r(cpu, Registers::D, Registers::A),
+        Opcodes::MOV_D_B => |cpu, _| opcodes::mov_r_r(cpu, Registers::D, Registers::B),
+        Opcodes::MOV_D_C => |cpu, _| opcodes::mov_r_r(cpu, Registers::D, Registers::C),
+        Opcodes::MOV_D_D => |cpu, _| opcodes::mov_r_r(cpu, Registers::D, Registers::D),
+        Opcodes::MOV_D_E => |cpu, _| opcodes::mov_r_r(cpu, Registers::D, Registers::E),
+        Opcodes::MOV_D_H => |cpu, _| opcodes::mov_r_r(cpu, Registers::D, Registers::H),
+        Opcodes::MOV_D_L => |cpu, _| opcodes::mov_r_r(cpu, Registers::D, Registers::L),
+        Opcodes::MOV_D_M => |cpu, _| opcodes::mov_r_m(cpu, Registers::D),
+
+        Opcodes::MOV_E_A => |cpu, _| opcodes::mov_r_r(cpu, Registers::E, Registers::A),
+        Opcodes::MOV_E_B => |cpu, _| opcodes::mov_r_r(cpu, Registers::E, Registers::B),
+        Opcodes::MOV_E_C => |cpu, _| opcodes::mov_r_r(cpu, Registers::E, Registers::C),
+        Opcodes::MOV_E_D => |cpu, _| opcodes::mov_r_r(cpu, Registers::E, Registers::D),
+        Opcodes::MOV_E_E => |cpu, _| opcodes::mov_r_r(cpu, Registers::E, Registers::E),
+        Opcodes::MOV_E_H => |cpu, _| opcodes::mov_r_r(cpu, Registers::E, Registers::H),
+        Opcodes::MOV_E_L => |cpu, _| opcodes::mov_r_r(cpu, Registers::E, Registers::L),
+        Opcodes::MOV_E_M => |cpu, _| opcodes::mov_r_m(cpu, Registers::E),
+
+        Opcodes::MOV_H_A => |cpu, _| opcodes::mov_r_r(cpu, Registers::H, Registers::A),
+        Opcodes::MOV_H_B => |cpu, _| opcodes::mov_r_r(cpu, Registers::H, Registers::B),
+        Opcodes::MOV_H_C => |cpu, _| opcodes::mov_r_r(cpu, Registers::H, Registers::C),
+        Opcodes::MOV_H_D => |cpu, _| opcodes::mov_r_r(cpu, Registers::H, Registers::D),
+        Opcodes::MOV_H_E => |cpu, _| opcodes::mov_r_r(cpu, Registers::H, Registers::E),
+        Opcodes::MOV_H_H => |cpu, _| opcodes::mov_r_r(cpu, Registers::H, Registers::H),
+        Opcodes::MOV_H_L => |cpu, _| opcodes::mov_r_r(cpu, Registers::H, Registers::L),
+        Opcodes::MOV_H_M => |cpu, _| opcodes::mov_r_m(cpu, Registers::H),
+
+        Opcodes::MOV_L_A => |cpu, _| opcodes::mov_r_r(cpu, Registers::L, Registers::A),
+        Opcodes::MOV_L_B => |cpu, _| opcodes::mov_r_r(cpu, Registers::L, Registers::B),
+        Opcodes::MOV_L_C => |cpu, _| opcodes::mov_r_r(cpu, Registers::L, Registers::C),
+        Opcodes::MOV_L_D => |cpu, _| opcodes::mov_r_r(cpu, Registers::L, Registers::D),
+        Opcodes::MOV_L_E => |cpu, _| opcodes::mov_r_r(cpu, Registers::L, Registers::E),
+        Opcodes::MOV_L_H => |cpu, _| opcodes::mov_r_r(cpu, Registers::L, Registers::H),
+        Opcodes::MOV_L_L => |cpu, _| opcodes::mov_r_r(cpu, Registers::L, Registers::L),
+        Opcodes::MOV_L_M => |cpu, _| opcodes::mov_r_m(cpu, Registers::L),
+
+        Opcodes::MOV_M_A => |cpu, _| opcodes::mov_m_r(cpu, Registers::A),
+        Opcodes::MOV_M_B => |cpu, _| opcodes::mov_m_r(cpu, Registers::B),
+        Opcodes::MOV_M_C => |cpu, _| opcodes::mov_m_r(cpu, Registers::C),
+        Opcodes::MOV_M_D => |cpu, _| opcodes::mov_m_r(cpu, Registers::D),
+        Opcodes::MOV_M_E => |cpu, _| opcodes::mov_m_r(cpu, Registers::E),
+        Opcodes::MOV_M_H => |cpu, _| opcodes::mov_m_r(cpu, Registers::H),
+        Opcodes::MOV_M_L => |cpu, _| opcodes::mov_m_r(cpu, Registers::L),
+
+        Opcodes::MVI_A => |cpu, operands| opcodes::mvi_r(cpu, Registers::A, operands[0]),
+        Opcodes::MVI_B => |cpu, operands| opcodes::mvi_r(cpu, Registers::B, operands[0]),
+        Opcodes::MVI_C => |cpu, operands| opcodes::mvi_r(cpu, Registers::C, operands[0]),
+        Opcodes::MVI_D => |cpu, operands| opcodes::mvi_r(cpu, Registers::D, operands[0]),
+        Opcodes::MVI_E => |cpu, operands| opcodes::mvi_r(cpu, Registers::E, operands[0]),
+        Opcodes::MVI_H => |cpu, operands| opcodes::mvi_r(cpu, Registers::H, operands[0]),
+        Opcodes::MVI_L => |cpu, operands| opcodes::mvi_r(cpu, Registers::L, operands[0]),
+        Opcodes::MVI_M => |cpu, operands| opcodes::mvi_m(cpu, operands[0]),
+
+        Opcodes::LXI_B => |cpu, operands| opcodes::lxi_r(cpu, Registers::B, operands),
+        Opcodes::LXI_D => |cpu, operands| opcodes::lxi_r(cpu, Registers::D, operands),
+        Opcodes::LXI_H => |cpu, operands| opcodes::lxi_r(cpu, Registers::H, operands),
+        Opcodes::LXI_SP => |cpu, operands| opcodes::lxi_sp(cpu, operands),
+
+        Opcodes::LDA => |cpu, operands| opcodes::lda(cpu, operands),
+        Opcodes::STA => |cpu, operands| opcodes::sta(cpu, operands),
+        Opcodes::LHLD => |cpu, operands| opcodes::lhld(cpu, operands),
+        Opcodes::SHLD => |cpu, operands| opcodes::shld(cpu, operands),
+
+        Opcodes::LDAX_B => |cpu, _| opcodes::ldax(cpu, Registers::B),
+        Opcodes::LDAX_D => |cpu, _| opcodes::ldax(cpu, Registers::D),
+        Opcodes::STAX_B => |cpu, _| opcodes::stax(cpu, Registers::B),
+        Opcodes::STAX_D => |cpu, _| opcodes::stax(cpu, Registers::D),
+        Opcodes::XCHG => |cpu, _| opcodes::xchg(cpu),
+
+        // arithmetic
+        Opcodes::ADD_A => |cpu, _| opcodes::add_r(cpu, Registers::A),
+        Opcodes::ADD_B => |cpu, _| opcodes::add_r(cpu, Registers::B),
+        Opcodes::ADD_C => |cpu, _| opcodes::add_r(cpu, Registers::C),
+        Opcodes::ADD_D => |cpu, _| opcodes::add_r(cpu, Registers::D),
+        Opcodes::ADD_E => |cpu, _| opcodes::add_r(cpu, Registers::E),
+        Opcodes::ADD_H => |cpu, _| opcodes::add_r(cpu, Registers::H),
+        Opcodes::ADD_L => |cpu, _| opcodes::add_r(cpu, Registers::L),
+        Opcodes::ADD_M => |cpu, _| opcodes::add_m(cpu),
+
+        Opcodes::ADC_A => |cpu, _| opcodes::adc_r(cpu, Registers::A),
+        Opcodes::ADC_B => |cpu, _| opcodes::adc_r(cpu, Registers::B),
+        Opcodes::ADC_C => |cpu, _| opcodes::adc_r(cpu, Registers::C),
+        Opcodes::ADC_D => |cpu, _| opcodes::adc_r(cpu, Registers::D),
+        Opcodes::ADC_E => |cpu, _| opcodes::adc_r(cpu, Registers::E),
+        Opcodes::ADC_H => |cpu, _| opcodes::adc_r(cpu, Registers::H),
+        Opcodes::ADC_L => |cpu, _| opcodes::adc_r(cpu, Registers::L),
+        Opcodes::ADC_M => |cpu, _| opcodes::adc_m(cpu),
+
+        Opcodes::ADI => |cpu, operands| opcodes::adi(cpu, operands[0]),
+        Opcodes::SBI => |cpu, operands| opcodes::sbi(cpu, operands[0]),
+        Opcodes::ACI => |cpu, operands| opcodes::aci(cpu, operands[0]),
+
+        Opcodes::SUB_A => |cpu, _| opcodes::sub_r(cpu, Registers::A),
+        Opcodes::SUB_B => |cpu, _| opcodes::sub_r(cpu, Registers::B),
+        Opcodes::SUB_C => |cpu, _| opcodes::sub_r(cpu, Registers::C),
+        Opcodes::SUB_D => |cpu, _| opcodes::sub_r(cpu, Registers::D),
+        Opcodes::SUB_E => |cpu, _| opcodes::sub_r(cpu, Registers::E),
+        Opcodes::SUB_H => |cpu, _| opcodes::sub_r(cpu, Registers::H),
+        Opcodes::SUB_L => |cpu, _| opcodes::sub_r(cpu, Registers::L),
+        Opcodes::SUB_M => |cpu, _| opcodes::sub_m(cpu),
+        Opcodes::SUI => |cpu, operands| opcodes::sui(cpu, operands[0]),
+
+        Opcodes::SBB_A => |cpu, _| opcodes::sbb_r(cpu, Registers::A),
+        Opcodes::SBB_B => |cpu, _| opcodes::sbb_r(cpu, Registers::B),
+        Opcodes::SBB_C => |cpu, _| opcodes::sbb_r(cpu, Registers::C),
+        Opcodes::SBB_D => |cpu, _| opcodes::sbb_r(cpu, Registers::D),
+        Opcodes::SBB_E => |cpu, _| opcodes::sbb_r(cpu, Registers::E),
+        Opcodes::SBB_H => |cpu, _| opcodes::sbb_r(cpu, Registers::H),
+        Opcodes::SBB_L => |cpu, _| opcodes::sbb_r(cpu, Registers::L),
+        Opcodes::SBB_M => |cpu, _| opcodes::sbb_m(cpu),
+
+        Opcodes::INR_A => |cpu, _| opcodes::inr_r(cpu, Registers::A),
+        Opcodes::INR_B => |cpu, _| opcodes::inr_r(cpu, Registers::B),
+        Opcodes::INR_C => |cpu, _| opcodes::inr_r(cpu, Registers::C),
+        Opcodes::INR_D => |cpu, _| opcodes::inr_r(cpu, Registers::D),
+        Opcodes::INR_E => |cpu, _| opcodes::inr_r(cpu, Registers::E),
+        Opcodes::INR_H => |cpu, _| opcodes::inr_r(cpu, Registers::H),
+        Opcodes::INR_L => |cpu, _| opcodes::inr_r(cpu, Registers::L),
+        Opcodes::INR_M => |cpu, _| opcodes::inr_m(cpu),
+
+        Opcodes::DCR_A => |cpu, _| opcodes::dcr_r(cpu, Registers::A),
+        Opcodes::DCR_B => |cpu, _| opcodes::dcr_r(cpu, Registers::B),
+        Opcodes::DCR_C => |cpu, _| opcodes::dcr_r(cpu, Registers::C),
+        Opcodes::DCR_D => |cpu, _| opcodes::dcr_r(cpu, Registers::D),
+        Opcodes::DCR_E => |cpu, _| opcodes::dcr_r(cpu, Registers::E),
+        Opcodes::DCR_H => |cpu, _| opcodes::dcr_r(cpu, Registers::H),
+        Opcodes::DCR_L => |cpu, _| opcodes::dcr_r(cpu, Registers::L),
+        Opcodes::DCR_M => |cpu, _| opcodes::dcr_m(cpu),
+
+        Opcodes::INX_B => |cpu, _| opcodes::inx_rp(cpu, Registers::B),
+        Opcodes::INX_D => |cpu, _| opcodes::inx_rp(cpu, Registers::D),
+        Opcodes::INX_H => |cpu, _| opcodes::inx_rp(cpu, Registers::H),
+        Opcodes::INX_SP => |cpu, _| opcodes::inx_sp(cpu),
+
+        Opcodes::DCX_B => |cpu, _| opcodes::dcx_rp(cpu, Registers::B),
+        Opcodes::DCX_D => |cpu, _| opcodes::dcx_rp(cpu, Registers::D),
+        Opcodes::DCX_H => |cpu, _| opcodes::dcx_rp(cpu, Registers::H),
+        Opcodes::DCX_SP => |cpu, _| opcodes::dcx_sp(cpu),
+
+        Opcodes::DAD_B => |cpu, _| opcodes::dad_rp(cpu, Registers::B),
+        Opcodes::DAD_D => |cpu, _| opcodes::dad_rp(cpu, Registers::D),
+        Opcodes::DAD_H => |cpu, _| opcodes::dad_rp(cpu, Registers::H),
+        Opcodes::DAD_SP => |cpu, _| opcodes::dad_sp(cpu),
+        Opcodes::DAA => |cpu, _| opcodes::daa(cpu),
+
+        // Logical Groups
+        Opcodes::ANA_A => |cpu, _| opcodes::ana_r(cpu, Registers::A),
+        Opcodes::ANA_B => |cpu, _| opcodes::ana_r(cpu, Registers::B),
+        Opcodes::ANA_C => |cpu, _| opcodes::ana_r(cpu, Registers::C),
+        Opcodes::ANA_D => |cpu, _| opcodes::ana_r(cpu, Registers::D),
+        Opcodes::ANA_E => |cpu, _| opcodes::ana_r(cpu, Registers::E),
+        Opcodes::ANA_H => |cpu, _| opcodes::ana_r(cpu, Registers::H),
+        Opcodes::ANA_L => |cpu, _| opcodes::ana_r(cpu, Registers::L),
+        Opcodes::ANA_M => |cpu, _| opcodes::ana_m(cpu),
+        Opcodes::ANI => |cpu, operands| opcodes::ani(cpu, operands[0]),
+
+        Opcodes::XRA_A => |cpu, _| opcodes::xra_r(cpu, Registers::A),
+        Opcodes::XRA_B => |cpu, _| opcodes::xra_r(cpu, Registers::B),
+        Opcodes::XRA_C => |cpu, _| opcodes::xra_r(cpu, Registers::C),
+        Opcodes::XRA_D => |cpu, _| opcodes::xra_r(cpu, Registers::D),
+        Opcodes::XRA_E => |cpu, _| opcodes::xra_r(cpu, Registers::E),
+        Opcodes::XRA_H => |cpu, _| opcodes::xra_r(cpu, Registers::H),
+        Opcodes::XRA_L => |cpu, _| opcodes::xra_r(cpu, Registers::L),
+        Opcodes::XRA_M => |cpu, _| opcodes::xra_m(cpu),
+        Opcodes::XRI => |cpu, operands| opcodes::xri(cpu, operands[0]),
+        Opcodes::ORA_A => |cpu, _| opcodes::ora_r(cpu, Registers::A),
+        Opcodes::ORA_B => |cpu, _| opcodes::ora_r(cpu, Registers::B),
+        Opcodes::ORA_C => |cpu, _| opcodes::ora_r(cpu, Registers::C),
+        Opcodes::ORA_D => |cpu, _| opcodes::ora_r(cpu, Registers::D),
+        Opcodes::ORA_E => |cpu, _| opcodes::ora_r(cpu, Registers::E),
+        Opcodes::ORA_H => |cpu, _| opcodes::ora_r(cpu, Registers::H),
+        Opcodes::ORA_L => |cpu, _| opcodes::ora_r(cpu, Registers::L),
+        Opcodes::ORA_M => |cpu, _| opcodes::ora_m(cpu),
+        Opcodes::ORI => |cpu, operands| opcodes::ori(cpu, operands[0]),
+        Opcodes::CMP_A => |cpu, _| opcodes::cmp_r(cpu, Registers::A),
+        Opcodes::CMP_B => |cpu, _| opcodes::cmp_r(cpu, Registers::B),
+        Opcodes::CMP_C => |cpu, _| opcodes::cmp_r(cpu, Registers::C),
+        Opcodes::CMP_D => |cpu, _| opcodes::cmp_r(cpu, Registers::D),
+        Opcodes::CMP_E => |cpu, _| opcodes::cmp_r(cpu, Registers::E),
+        Opcodes::CMP_H => |cpu, _| opcodes::cmp_r(cpu, Registers::H),
+        Opcodes::CMP_L => |cpu, _| opcodes::cmp_r(cpu, Registers::L),
+        Opcodes::CMP_M => |cpu, _| opcodes::cmp_m(cpu),
+
+        Opcodes::CPI => |cpu, operands| opcodes::cpi(cpu, operands[0]),
+        Opcodes::RLC => |cpu, _| opcodes::rlc(cpu),
+        Opcodes::RRC => |cpu, _| opcodes::rrc(cpu),
+        Opcodes::RAL => |cpu, _| opcodes::ral(cpu),
+        Opcodes::RAR => |cpu, _| opcodes::rar(cpu),
+        Opcodes::CMA => |cpu, _| opcodes::cma(cpu),
+        Opcodes::CMC => |cpu, _| opcodes::cmc(cpu),
+        Opcodes::STC => |cpu, _| opcodes::stc(cpu),
+
+        // Branch group
+        Opcodes::JMP => |cpu, operands| opcodes::jmp(cpu, operands),
+        Opcodes::JNZ => |cpu, operands| opcodes::jcc(cpu, ConditionCodes::Z, false, operands),
+        Opcodes::JZ => |cpu, operands| opcodes::jcc(cpu, ConditionCodes::Z, true, operands),
+        Opcodes::JNC => |cpu, operands| opcodes::jcc(cpu, ConditionCodes::CY, false, operands),
+        Opcodes::JC => |cpu, operands| opcodes::jcc(cpu, ConditionCodes::CY, true, operands),
+        Opcodes::JPO => |cpu, operands| opcodes::jcc(cpu, ConditionCodes::P, false, operands),
+        Opcodes::JPE => |cpu, operands| opcodes::jcc(cpu, ConditionCodes::P, true, operands),
+        Opcodes::JP => |cpu, operands| opcodes::jcc(cpu, ConditionCodes::S, false, operands),
+        Opcodes::JM => |cpu, operands| opcodes::jcc(cpu, ConditionCodes::S, true, operands),
+        Opcodes::CALL => |cpu, operands| opcodes::call(cpu, operands),
+        Opcodes::CNZ => |cpu, operands| opcodes::ccc(cpu, ConditionCodes::Z, false, operands),
+        Opcodes::CZ => |cpu, operands| opcodes::ccc(cpu, ConditionCodes::Z, true, operands),
+        Opcodes::CNC => |cpu, operands| opcodes::ccc(cpu, ConditionCodes::CY, false, operands),
+        Opcodes::CC => |cpu, operands| opcodes::ccc(cpu, ConditionCodes::CY, true, operands),
+        Opcodes::CPO => |cpu, operands| opcodes::ccc(cpu, ConditionCodes::P, false, operands),
+        Opcodes::CPE => |cpu, operands| opcodes::ccc(cpu, ConditionCodes::P, true, operands),
+        Opcodes::CP => |cpu, operands| opcodes::ccc(cpu, ConditionCodes::S, false, operands),
+        Opcodes::CM => |cpu, operands| opcodes::ccc(cpu, ConditionCodes::S, true, operands),
+
+        Opcodes::RET => |cpu, _| opcodes::ret(cpu),
+        Opcodes::RNZ => |cpu, _| opcodes::rcc(cpu, ConditionCodes::Z, false),
+        Opcodes::RZ => |cpu, _| opcodes::rcc(cpu, ConditionCodes::Z, true),
+        Opcodes::RNC => |cpu, _| opcodes::rcc(cpu, ConditionCodes::CY, false),
+        Opcodes::RC => |cpu, _| opcodes::rcc(cpu, ConditionCodes::CY, true),
+        Opcodes::RPO => |cpu, _| opcodes::rcc(cpu, ConditionCodes::P, false),
+        Opcodes::RPE => |cpu, _| opcodes::rcc(cpu, ConditionCodes::P, true),
+        Opcodes::RP => |cpu, _| opcodes::rcc(cpu, ConditionCodes::S, false),
+        Opcodes::RM => |cpu, _| opcodes::rcc(cpu, ConditionCodes::S, true),
+
+        Opcodes::RST_0 => |cpu, _| opcodes::rst_n(cpu, 0),
+        Opcodes::RST_1 => |cpu, _| opcodes::rst_n(cpu, 1),
+        Opcodes::RST_2 => |cpu, _| opcodes::rst_n(cpu, 2),
+        Opcodes::RST_3 => |cpu, _| opcodes::rst_n(cpu, 3),
+        Opcodes::RST_4 => |cpu, _| opcodes::rst_n(cpu, 4),
+        Opcodes::RST_5 => |cpu, _| opcodes::rst_n(cpu, 5),
+        Opcodes::RST_6 => |cpu, _| opcodes::rst_n(cpu, 6),
+        Opcodes::RST_7 => |cpu, _| opcodes::rst_n(cpu, 7),
+        Opcodes::PCHL => |cpu, _| opcodes::pchl(cpu),
+
+        // Stack, I/O
+        Opcodes::PUSH_B => |cpu, _| opcodes::push_rp(cpu, Registers::B),
+        Opcodes::PUSH_D => |cpu, _| opcodes::push_rp(cpu, Registers::D),
+        Opcodes::PUSH_H => |cpu, _| opcodes::push_rp(cpu, Registers::H),
+        Opcodes::PUSH_PSW => |cpu, _| opcodes::push_psw(cpu),
+        Opcodes::POP_B => |cpu, _| opcodes::pop_rp(cpu, Registers::B),
+        Opcodes::POP_D => |cpu, _| opcodes::pop_rp(cpu, Registers::D),
+        Opcodes::POP_H => |cpu, _| opcodes::pop_rp(cpu, Registers::H),
+        Opcodes::POP_PSW => |cpu, _| opcodes::pop_psw(cpu),
+
+        Opcodes::XTHL => |cpu, _| opcodes::xthl(cpu),
+        Opcodes::SPHL => |cpu, _| opcodes::sphl(cpu),
+
+        Opcodes::EI => |cpu, _| opcodes::ei(cpu),
+        Opcodes::DI => |cpu, _| opcodes::di(cpu),
+        Opcodes::HLT => |cpu, _| opcodes::hlt(cpu),
+
+        Opcodes::IN => |cpu, operands| opcodes::in_port(cpu, operands),
+        Opcodes::OUT => |cpu, operands| opcodes::out_port(cpu, operands),
+
+        Opcodes::NOP => |_, _| opcodes::nop(),
+    }
+}
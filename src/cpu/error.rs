@@ -0,0 +1,52 @@
+use std::fmt;
+
+use super::{
+    history::HistoryEntry, input_log::Desync, memory_map::Violation, self_modify::CodeWrite,
+    stack_guard::StackAlert,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnknownOpcode(u8),
+    Stack(StackAlert),
+    Protection(Violation),
+    SelfModify(CodeWrite),
+    Replay(Desync),
+}
+
+/// Returned by `Cpu::step` instead of panicking. Carries the contents of the
+/// execution history at the time of the fault when history is enabled.
+#[derive(Debug, Clone)]
+pub struct CpuError {
+    pub kind: ErrorKind,
+    pub pc: u16,
+    pub history: Vec<HistoryEntry>,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::UnknownOpcode(opcode) => write!(f, "unknown opcode 0x{:02x}", opcode),
+            ErrorKind::Stack(alert) => write!(f, "{}", alert),
+            ErrorKind::Protection(violation) => write!(f, "{}", violation),
+            ErrorKind::SelfModify(write) => write!(f, "{}", write),
+            ErrorKind::Replay(desync) => write!(f, "{}", desync),
+        }
+    }
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {:04x}", self.kind, self.pc)?;
+        if !self.history.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "last {} instructions:", self.history.len())?;
+            for entry in &self.history {
+                writeln!(f, "{}", entry)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CpuError {}
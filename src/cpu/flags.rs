@@ -0,0 +1,131 @@
+//! Lookup tables for the condition flags, laid out as the F register:
+//! S Z 0 AC 0 P 1 CY.
+
+pub const S: u8 = 0x80;
+pub const Z: u8 = 0x40;
+pub const AC: u8 = 0x10;
+pub const P: u8 = 0x04;
+pub const CY: u8 = 0x01;
+/// Bit 1 of F always reads as 1.
+pub const ALWAYS_SET: u8 = 0x02;
+
+/// S, Z and P for every result byte, with the fixed bit set.
+pub static SZP: [u8; 256] = szp_table();
+
+/// Carry out of one bit of an addition. The index packs that bit of both
+/// operands and of the result as `a | b << 1 | result << 2`; the carry in
+/// is implied by the three.
+static AUX_CARRY: [u8; 8] = [0, AC, AC, AC, 0, 0, 0, AC];
+static CARRY: [u8; 8] = [0, CY, CY, CY, 0, 0, 0, CY];
+
+const fn szp_table() -> [u8; 256] {
+    let mut table = [0; 256];
+    let mut value = 0;
+    while value < 256 {
+        let byte = value as u8;
+        let mut flags = ALWAYS_SET | (byte & S);
+        if byte == 0 {
+            flags |= Z;
+        }
+        if byte.count_ones().is_multiple_of(2) {
+            flags |= P;
+        }
+        table[value] = flags;
+        value += 1;
+    }
+    table
+}
+
+fn index(a: u8, b: u8, result: u8, bit: u8) -> usize {
+    ((a >> bit & 1) | (b >> bit & 1) << 1 | (result >> bit & 1) << 2) as usize
+}
+
+/// `a + b + carry` and all five flags.
+pub fn add(a: u8, b: u8, carry: u8) -> (u8, u8) {
+    let result = a.wrapping_add(b).wrapping_add(carry);
+    let flags =
+        SZP[result as usize] | AUX_CARRY[index(a, b, result, 3)] | CARRY[index(a, b, result, 7)];
+    (result, flags)
+}
+
+/// `a - b - borrow` and all five flags. The 8080 subtracts by adding the
+/// complement, so AC is the carry out of bit 3 of that addition and CY is
+/// the inverted carry out of bit 7.
+pub fn sub(a: u8, b: u8, borrow: u8) -> (u8, u8) {
+    let result = a.wrapping_sub(b).wrapping_sub(borrow);
+    let flags = SZP[result as usize]
+        | AUX_CARRY[index(a, !b, result, 3)]
+        | (CARRY[index(a, !b, result, 7)] ^ CY);
+    (result, flags)
+}
+
+/// The ALU operation behind a set of flags not worked out yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// `a + b + carry`
+    Add,
+    /// `a - b - carry`
+    Sub,
+    /// `a & b`
+    And,
+    /// ORA or XRA; `a` is the result.
+    Logic,
+}
+
+/// Enough of the last ALU operation to produce its flags on demand, for
+/// lazy flag evaluation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pending {
+    pub operation: Operation,
+    pub a: u8,
+    pub b: u8,
+    pub carry: u8,
+    /// CY to report instead of the computed one, for INR and DCR.
+    pub kept_carry: Option<u8>,
+}
+
+impl Pending {
+    /// CY alone, without working out the other flags.
+    pub fn carry_out(&self) -> u8 {
+        if let Some(carry) = self.kept_carry {
+            return carry;
+        }
+        let (a, b, carry) = (self.a as u16, self.b as u16, self.carry as u16);
+        match self.operation {
+            Operation::Add => (a + b + carry > 0xff) as u8,
+            Operation::Sub => (a < b + carry) as u8,
+            Operation::And | Operation::Logic => 0,
+        }
+    }
+
+    /// One of S, Z, P or CY alone, without working out the other flags.
+    pub fn flag(&self, flag: u8) -> u8 {
+        match flag {
+            CY => self.carry_out(),
+            S | Z | P => SZP[self.result() as usize] & flag,
+            _ => self.resolve() & flag,
+        }
+    }
+
+    fn result(&self) -> u8 {
+        match self.operation {
+            Operation::Add => self.a.wrapping_add(self.b).wrapping_add(self.carry),
+            Operation::Sub => self.a.wrapping_sub(self.b).wrapping_sub(self.carry),
+            Operation::And => self.a & self.b,
+            Operation::Logic => self.a,
+        }
+    }
+
+    pub fn resolve(&self) -> u8 {
+        let flags = match self.operation {
+            Operation::Add => add(self.a, self.b, self.carry).1,
+            Operation::Sub => sub(self.a, self.b, self.carry).1,
+            Operation::And => SZP[(self.a & self.b) as usize] | ((self.a | self.b) & 0x08) << 1,
+            Operation::Logic => SZP[self.a as usize],
+        };
+        match self.kept_carry {
+            Some(carry) => flags & !CY | carry,
+            None => flags,
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::fmt;
+
+use crate::checksum::crc32;
+
+pub const MAGIC: &[u8; 4] = b"I80R";
+pub const VERSION: u16 = 1;
+
+/// Something from outside the CPU that influenced execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    /// An IN instruction read `value` from `port`.
+    In { port: u8, value: u8 },
+    /// An interrupt was accepted and ran `RST vector`.
+    Interrupt { vector: u8 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    /// Cycle counter when the event happened. For IN it already includes
+    /// the instruction's own cycles; for interrupts it is taken before the
+    /// RST runs.
+    pub cycle: u64,
+    pub kind: InputKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    UnknownEvent(u8),
+    /// The log was recorded against a different ROM.
+    RomMismatch {
+        expected: u32,
+        actual: u32,
+    },
+}
+
+/// Replay went somewhere the recording did not.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Desync {
+    pub cycle: u64,
+    /// What the CPU did instead: an IN from this port.
+    pub port: u8,
+    /// The event the log expected next.
+    pub expected: InputEvent,
+}
+
+/// Every IN value and accepted interrupt of a session, tagged with the
+/// CRC-32 of the ROM it ran.
+///
+/// Layout: `I80R` magic, `u16` version, `u32` ROM CRC, `u32` event count,
+/// then per event a `u64` cycle, a kind byte (0 = IN, 1 = interrupt) and
+/// two data bytes (port and value, or vector and 0). All little-endian.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputLog {
+    pub rom_crc: u32,
+    pub events: Vec<InputEvent>,
+}
+
+impl InputLog {
+    pub fn new(rom: &[u8]) -> InputLog {
+        InputLog {
+            rom_crc: crc32(rom),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, cycle: u64, kind: InputKind) {
+        self.events.push(InputEvent { cycle, kind });
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(14 + self.events.len() * 11);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&self.rom_crc.to_le_bytes());
+        out.extend_from_slice(&(self.events.len() as u32).to_le_bytes());
+        for event in &self.events {
+            out.extend_from_slice(&event.cycle.to_le_bytes());
+            out.extend_from_slice(&match event.kind {
+                InputKind::In { port, value } => [0, port, value],
+                InputKind::Interrupt { vector } => [1, vector, 0],
+            });
+        }
+        out
+    }
+
+    pub fn parse(data: &[u8]) -> Result<InputLog, LogError> {
+        if data.len() < 14 {
+            return Err(LogError::Truncated);
+        }
+        if &data[..4] != MAGIC {
+            return Err(LogError::BadMagic);
+        }
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version != VERSION {
+            return Err(LogError::UnsupportedVersion(version));
+        }
+        let rom_crc = u32::from_le_bytes(data[6..10].try_into().unwrap());
+        let count = u32::from_le_bytes(data[10..14].try_into().unwrap()) as usize;
+        let records = &data[14..];
+        if records.len() < count * 11 {
+            return Err(LogError::Truncated);
+        }
+        let events = records
+            .chunks_exact(11)
+            .take(count)
+            .map(|record| {
+                let cycle = u64::from_le_bytes(record[..8].try_into().unwrap());
+                let kind = match record[8] {
+                    0 => InputKind::In {
+                        port: record[9],
+                        value: record[10],
+                    },
+                    1 => InputKind::Interrupt { vector: record[9] },
+                    kind => return Err(LogError::UnknownEvent(kind)),
+                };
+                Ok(InputEvent { cycle, kind })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(InputLog { rom_crc, events })
+    }
+
+    /// Fails when the log was not recorded against `rom`.
+    pub fn check_rom(&self, rom: &[u8]) -> Result<(), LogError> {
+        let actual = crc32(rom);
+        if actual != self.rom_crc {
+            return Err(LogError::RomMismatch {
+                expected: self.rom_crc,
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Feeds a recorded log back into the CPU in place of the live ports and
+/// interrupt source.
+pub struct Replayer {
+    log: InputLog,
+    position: usize,
+    desync: Option<Desync>,
+}
+
+impl Replayer {
+    pub fn new(log: InputLog) -> Replayer {
+        Replayer {
+            log,
+            position: 0,
+            desync: None,
+        }
+    }
+
+    /// All recorded events were consumed.
+    pub fn finished(&self) -> bool {
+        self.position >= self.log.events.len()
+    }
+
+    /// The recorded value for an IN from `port` at `cycle`. None when the
+    /// log is used up; a mismatching event is reported through `take_desync`
+    /// and still consumed so the CPU gets a value.
+    pub fn input(&mut self, cycle: u64, port: u8) -> Option<u8> {
+        let event = *self.log.events.get(self.position)?;
+        self.position += 1;
+        match event.kind {
+            InputKind::In {
+                port: recorded,
+                value,
+            } if recorded == port && event.cycle == cycle => Some(value),
+            InputKind::In { value, .. } => {
+                self.desync.get_or_insert(Desync {
+                    cycle,
+                    port,
+                    expected: event,
+                });
+                Some(value)
+            }
+            InputKind::Interrupt { .. } => {
+                self.desync.get_or_insert(Desync {
+                    cycle,
+                    port,
+                    expected: event,
+                });
+                None
+            }
+        }
+    }
+
+    /// The vector of a recorded interrupt due at or before `cycle`.
+    pub fn interrupt(&mut self, cycle: u64) -> Option<u8> {
+        match self.log.events.get(self.position)? {
+            InputEvent {
+                cycle: due,
+                kind: InputKind::Interrupt { vector },
+            } if *due <= cycle => {
+                self.position += 1;
+                Some(*vector)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn take_desync(&mut self) -> Option<Desync> {
+        self.desync.take()
+    }
+}
+
+impl fmt::Display for InputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            InputKind::In { port, value } => {
+                write!(f, "IN {:02x} = {:02x} at cycle {}", port, value, self.cycle)
+            }
+            InputKind::Interrupt { vector } => {
+                write!(f, "RST {} at cycle {}", vector, self.cycle)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Desync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "replay desync: IN {:02x} at cycle {}, recording has {}",
+            self.port, self.cycle, self.expected
+        )
+    }
+}
+
+impl fmt::Display for LogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogError::BadMagic => write!(f, "not an input log"),
+            LogError::UnsupportedVersion(version) => {
+                write!(f, "unsupported input log version {}", version)
+            }
+            LogError::Truncated => write!(f, "input log is truncated"),
+            LogError::UnknownEvent(kind) => write!(f, "unknown input log event {}", kind),
+            LogError::RomMismatch { expected, actual } => write!(
+                f,
+                "input log was recorded with ROM {:08x}, this ROM is {:08x}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LogError {}
@@ -0,0 +1,18 @@
+/// Hardware behind the IN and OUT instructions.
+pub trait Ports {
+    fn input(&mut self, port: u8) -> u8;
+    fn output(&mut self, port: u8, value: u8);
+}
+
+/// Reads 0 from every port and ignores writes. Used when no ports are
+/// attached.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullPorts;
+
+impl Ports for NullPorts {
+    fn input(&mut self, _port: u8) -> u8 {
+        0
+    }
+
+    fn output(&mut self, _port: u8, _value: u8) {}
+}
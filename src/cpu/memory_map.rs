@@ -0,0 +1,222 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Permission bits of a memory region.
+pub mod permissions {
+    pub const READ: u8 = 1 << 0;
+    pub const WRITE: u8 = 1 << 1;
+    pub const EXECUTE: u8 = 1 << 2;
+    pub const ALL: u8 = READ | WRITE | EXECUTE;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl Access {
+    fn permission(self) -> u8 {
+        match self {
+            Access::Read => permissions::READ,
+            Access::Write => permissions::WRITE,
+            Access::Execute => permissions::EXECUTE,
+        }
+    }
+}
+
+/// What to do when an access is not permitted. Writes to a region without
+/// write permission are dropped under every policy, the way ROM behaves;
+/// the policy only decides how loudly that is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    Ignore,
+    /// Record a `Violation` and keep running.
+    Log,
+    /// Record a `Violation` and stop with a `CpuError`.
+    Trap,
+}
+
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub name: String,
+    pub range: RangeInclusive<u16>,
+    pub permissions: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Address of the instruction that made the access.
+    pub pc: u16,
+    pub address: u16,
+    pub access: Access,
+    /// Name of the region the address belongs to.
+    pub region: String,
+}
+
+/// Named regions with read/write/execute attributes. Addresses outside
+/// every region allow everything.
+///
+/// Mirrors make a range of addresses an alias of another. Loads and stores
+/// are translated before anything else sees them; instruction fetches are
+/// not.
+pub struct MemoryMap {
+    regions: Vec<Region>,
+    /// Index into `regions` + 1 for every address, 0 when unmapped.
+    lookup: Vec<u8>,
+    /// Where every address really goes, once a mirror was added.
+    translation: Option<Vec<u16>>,
+    policies: [Policy; 3],
+    pending: Vec<(u16, Access)>,
+    violations: Vec<Violation>,
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryMap {
+    pub fn new() -> MemoryMap {
+        MemoryMap {
+            regions: Vec::new(),
+            lookup: vec![0; 0x10000],
+            translation: None,
+            policies: [Policy::Log; 3],
+            pending: Vec::new(),
+            violations: Vec::new(),
+        }
+    }
+
+    /// Adds a region. Later regions win where they overlap earlier ones.
+    pub fn add_region(&mut self, name: &str, range: RangeInclusive<u16>, permissions: u8) {
+        assert!(
+            self.regions.len() < u8::MAX as usize,
+            "too many memory regions"
+        );
+        self.regions.push(Region {
+            name: name.to_string(),
+            range: range.clone(),
+            permissions,
+        });
+        let index = self.regions.len() as u8;
+        for address in range {
+            self.lookup[address as usize] = index;
+        }
+    }
+
+    /// Maps `range` onto `target`, repeating `target` as often as needed to
+    /// fill `range`.
+    pub fn add_mirror(&mut self, range: RangeInclusive<u16>, target: RangeInclusive<u16>) {
+        let length = *target.end() as u32 - *target.start() as u32 + 1;
+        let translation = self
+            .translation
+            .get_or_insert_with(|| (0..=u16::MAX).collect());
+        for address in range.clone() {
+            let offset = (address - range.start()) as u32 % length;
+            translation[address as usize] = target.start() + offset as u16;
+        }
+    }
+
+    /// The address a load or store of `address` really reaches.
+    pub fn translate(&self, address: u16) -> u16 {
+        match &self.translation {
+            Some(translation) => translation[address as usize],
+            None => address,
+        }
+    }
+
+    pub fn set_policy(&mut self, access: Access, policy: Policy) {
+        self.policies[access as usize] = policy;
+    }
+
+    pub fn policy(&self, access: Access) -> Policy {
+        self.policies[access as usize]
+    }
+
+    /// Whether any policy records violations. A map that only drops
+    /// writes to read-only regions does not need to see every instruction.
+    pub fn reports_violations(&self) -> bool {
+        self.policies.iter().any(|policy| *policy != Policy::Ignore)
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    pub fn region(&self, address: u16) -> Option<&Region> {
+        match self.lookup[address as usize] {
+            0 => None,
+            index => Some(&self.regions[index as usize - 1]),
+        }
+    }
+
+    pub fn allows(&self, address: u16, access: Access) -> bool {
+        self.region(address)
+            .is_none_or(|region| region.permissions & access.permission() != 0)
+    }
+
+    /// Checks one access and queues a violation according to the policy.
+    /// Returns whether the access is permitted.
+    pub fn check(&mut self, address: u16, access: Access) -> bool {
+        if self.allows(address, access) {
+            return true;
+        }
+        if self.policy(access) != Policy::Ignore {
+            self.pending.push((address, access));
+        }
+        false
+    }
+
+    /// Moves the violations queued by the instruction at `pc` to the log.
+    /// Returns the first one whose policy is `Trap`.
+    pub fn commit(&mut self, pc: u16) -> Option<Violation> {
+        let mut trap = None;
+        for (address, access) in std::mem::take(&mut self.pending) {
+            let violation = Violation {
+                pc,
+                address,
+                access,
+                region: self
+                    .region(address)
+                    .map(|region| region.name.clone())
+                    .unwrap_or_default(),
+            };
+            if trap.is_none() && self.policy(access) == Policy::Trap {
+                trap = Some(violation.clone());
+            }
+            self.violations.push(violation);
+        }
+        trap
+    }
+
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    pub fn take_violations(&mut self) -> Vec<Violation> {
+        std::mem::take(&mut self.violations)
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+            Access::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04x}: {} of {:04x} not permitted in {}",
+            self.pc, self.access, self.address, self.region
+        )
+    }
+}
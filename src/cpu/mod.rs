@@ -1,379 +1,1003 @@
-use core::panic;
+use block_cache::BlockCache;
+use call_profiler::{CallProfiler, Transfer};
+use coverage::{access, Coverage};
+use dispatch::Table;
+use history::{History, HistoryEntry};
+use input_log::{InputKind, InputLog, Replayer};
+use io::{NullPorts, Ports};
+use memory_map::{Access, MemoryMap};
 use opcodes::Opcodes;
+use profiler::Profiler;
 use registers::Registers;
-use std::collections::HashMap;
+use rewind::{Rewind, RewindConfig, RewindError, Stop};
+use save_state::{DeviceState, StateError, StateWriter};
+use self_modify::SelfModifyDetector;
+use stack_guard::{StackGuard, StackGuardConfig};
+use std::fmt;
+use trace::{TraceRecord, Tracer};
+use uninit::{InitTracker, PowerOnRng};
+pub mod block_cache;
+pub mod call_profiler;
+pub mod coverage;
+mod dispatch;
+mod error;
+mod flags;
+pub mod history;
+pub mod input_log;
+pub mod io;
+pub mod memory_map;
 mod opcodes;
+pub mod profiler;
 mod registers;
-
-#[derive(Hash, Debug, PartialEq, Eq)]
+pub mod rewind;
+pub mod save_state;
+pub mod self_modify;
+pub mod stack_guard;
+pub mod symbols;
+pub mod trace;
+pub mod uninit;
+
+pub use error::{CpuError, ErrorKind};
+
+/// Each code is its bit in the F register.
+#[derive(Hash, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConditionCodes {
-    Z,  // when the result == 0
-    S,  //sign
-    P,  //parity
-    CY, // carry
-    AC, // auxilliary carry
+    Z = flags::Z as isize,   // when the result == 0
+    S = flags::S as isize,   //sign
+    P = flags::P as isize,   //parity
+    CY = flags::CY as isize, // carry
+    AC = flags::AC as isize, // auxilliary carry
 }
 
 const REGISTERS_COUNT: usize = 7;
 const MEMORY_SIZE: usize = 0x10000;
 
+/// Why `Cpu::run` returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunExit {
+    /// The cycle counter reached the target.
+    Cycles,
+    /// The next instruction is at this breakpoint.
+    Breakpoint(u16),
+    /// The CPU is halted and only an interrupt resumes it.
+    Halted,
+}
+
+/// Programmer-visible registers at an instruction boundary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CpuState {
+    pub pc: u16,
+    pub sp: u16,
+    /// A, B, C, D, E, H, L in that order.
+    pub registers: [u8; REGISTERS_COUNT],
+    pub f: u8,
+    pub cycles: u64,
+}
+
+/// A decoded instruction: the opcode byte plus the operand bytes it uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub opcode: u8,
+    pub operands: [u8; 2],
+    pub size: u8,
+}
+
+impl Instruction {
+    /// Decodes the instruction starting at `address`. Unknown opcodes are
+    /// treated as one byte long.
+    pub fn decode(memory: &[u8], address: u16) -> Instruction {
+        let at = |offset: usize| memory[(address as usize + offset) % memory.len()];
+        let opcode = at(0);
+        Instruction {
+            opcode,
+            operands: [at(1), at(2)],
+            size: Opcodes::from_hex(opcode)
+                .map(|decoded| decoded.get_instruction_def().size)
+                .unwrap_or(1),
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.operands[..self.size.saturating_sub(1) as usize]
+    }
+
+    pub fn mnemonic(&self) -> String {
+        Opcodes::from_hex(self.opcode)
+            .map(|opcode| opcode.to_string())
+            .unwrap_or_else(|| "???".to_string())
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bytes = format!("{:02x}", self.opcode);
+        for operand in self.bytes() {
+            bytes.push_str(&format!(" {:02x}", operand));
+        }
+        write!(f, "{:<8}  {:<8}", bytes, self.mnemonic())
+    }
+}
+
+impl fmt::Display for CpuState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "A: {:02x} F: {:02x} B: {:02x} C: {:02x} D: {:02x} E: {:02x} H: {:02x} L: {:02x} SP: {:04x} CYC: {}",
+            self.registers[Registers::A as usize],
+            self.f,
+            self.registers[Registers::B as usize],
+            self.registers[Registers::C as usize],
+            self.registers[Registers::D as usize],
+            self.registers[Registers::E as usize],
+            self.registers[Registers::H as usize],
+            self.registers[Registers::L as usize],
+            self.sp,
+            self.cycles
+        )
+    }
+}
+
 pub struct Cpu {
+    dispatch: &'static Table,
     registers: [u8; REGISTERS_COUNT],
     sp: u16,
     pc: usize,
     memory: [u8; MEMORY_SIZE],
-    cc: HashMap<ConditionCodes, bool>,
+    /// S Z 0 AC 0 P 1 CY, as pushed by PUSH PSW. Stale while
+    /// `pending_flags` is set.
+    f: u8,
+    lazy_flags: bool,
+    pending_flags: Option<flags::Pending>,
+    cycles: u64,
+    instructions: u64,
+    interrupts_enabled: bool,
+    halted: bool,
+    history: Option<History>,
+    tracer: Option<Box<dyn Tracer>>,
+    profiler: Option<Profiler>,
+    call_profiler: Option<CallProfiler>,
+    coverage: Option<Coverage>,
+    stack_guard: Option<StackGuard>,
+    memory_map: Option<MemoryMap>,
+    init_tracker: Option<InitTracker>,
+    self_modify: Option<SelfModifyDetector>,
+    rewind: Option<Rewind>,
+    block_cache: Option<BlockCache>,
+    ports: Box<dyn Ports>,
+    recorder: Option<InputLog>,
+    replayer: Option<Replayer>,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Cpu {
     pub fn new() -> Cpu {
-        return Cpu {
+        Cpu {
+            dispatch: dispatch::table(),
             registers: [0; REGISTERS_COUNT],
             sp: 0,
             pc: 0,
             memory: [0; MEMORY_SIZE],
-            cc: HashMap::new(),
+            f: flags::ALWAYS_SET,
+            lazy_flags: false,
+            pending_flags: None,
+            cycles: 0,
+            instructions: 0,
+            interrupts_enabled: false,
+            halted: false,
+            history: None,
+            tracer: None,
+            profiler: None,
+            call_profiler: None,
+            coverage: None,
+            stack_guard: None,
+            memory_map: None,
+            init_tracker: None,
+            self_modify: None,
+            rewind: None,
+            block_cache: None,
+            ports: Box::new(NullPorts),
+            recorder: None,
+            replayer: None,
+        }
+    }
+
+    pub fn load_rom(&mut self, buffer: &[u8]) {
+        self.memory[..buffer.len()].clone_from_slice(buffer);
+        if let Some(cache) = self.block_cache.as_mut() {
+            cache.clear();
+        }
+        if let Some(tracker) = self.init_tracker.as_mut() {
+            for address in 0..buffer.len() {
+                tracker.mark_memory(address as u16);
+            }
+        }
+    }
+
+    /// Fills memory, registers, flags and SP with pseudo-random values
+    /// derived from `seed`, the way real hardware comes up with garbage
+    /// instead of zeroes. Call before `load_rom`.
+    pub fn power_on(&mut self, seed: u64) {
+        let mut rng = PowerOnRng::new(seed);
+        rng.fill(&mut self.memory);
+        if let Some(cache) = self.block_cache.as_mut() {
+            cache.clear();
+        }
+        rng.fill(&mut self.registers);
+        let [f, sp_high, sp_low, ..] = rng.next_u64().to_be_bytes();
+        self.sp = u16::from_be_bytes([sp_high, sp_low]);
+        self.write_f_reg(f);
+    }
+
+    /// Serializes the registers, flags, SP, PC, counters, interrupt and
+    /// halt state, all of memory and then each of `devices`.
+    pub fn save_state(&self, devices: &[&dyn DeviceState]) -> Vec<u8> {
+        let mut out = StateWriter::default();
+        out.bytes(&self.registers);
+        out.u8(self.read_f_reg());
+        out.u16(self.sp);
+        out.u16(self.pc as u16);
+        out.u64(self.cycles);
+        out.u64(self.instructions);
+        out.u8(self.interrupts_enabled as u8);
+        out.u8(self.halted as u8);
+        out.bytes(&self.memory);
+        save_state::write_devices(&mut out, devices);
+        out.finish()
+    }
+
+    /// Restores a state written by `save_state`. Nothing is changed unless
+    /// the whole state parses, every device has a section and every device
+    /// accepts it; devices that loaded before one refused are put back.
+    /// Sections of devices not passed in are ignored.
+    pub fn load_state(
+        &mut self,
+        state: &[u8],
+        devices: &mut [&mut dyn DeviceState],
+    ) -> Result<(), StateError> {
+        let (cpu, sections) = save_state::parse(state)?;
+        let mut device_data = Vec::with_capacity(devices.len());
+        for device in devices.iter() {
+            let data = sections
+                .iter()
+                .find(|(name, _)| *name == device.name().as_bytes())
+                .map(|(_, data)| *data)
+                .ok_or_else(|| StateError::MissingDevice(device.name().to_string()))?;
+            device_data.push(data);
+        }
+        let backups = devices
+            .iter()
+            .map(|device| {
+                let mut backup = Vec::new();
+                device.save_state(&mut backup);
+                backup
+            })
+            .collect::<Vec<_>>();
+        for index in 0..devices.len() {
+            if let Err(error) = devices[index].load_state(device_data[index]) {
+                for (device, backup) in devices.iter_mut().zip(&backups).take(index) {
+                    device.load_state(backup)?;
+                }
+                return Err(error);
+            }
+        }
+
+        self.registers = cpu.state.registers;
+        self.write_f_reg(cpu.state.f);
+        self.sp = cpu.state.sp;
+        self.pc = cpu.state.pc as usize;
+        self.cycles = cpu.state.cycles;
+        self.instructions = cpu.instructions;
+        self.interrupts_enabled = cpu.interrupts_enabled;
+        self.halted = cpu.halted;
+        self.memory.copy_from_slice(cpu.memory);
+        if let Some(cache) = self.block_cache.as_mut() {
+            cache.clear();
+        }
+        Ok(())
+    }
+
+    /// Reports reads of memory and registers that were never written.
+    /// Enable before `load_rom` so the ROM counts as initialized.
+    pub fn enable_init_tracker(&mut self) {
+        self.init_tracker = Some(InitTracker::new());
+    }
+
+    pub fn init_tracker(&self) -> Option<&InitTracker> {
+        self.init_tracker.as_ref()
+    }
+
+    /// Keeps the last `capacity` executed instructions so they can be dumped
+    /// alongside a `CpuError`. A capacity of 0 turns the history off again.
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history = if capacity == 0 {
+            None
+        } else {
+            Some(History::new(capacity))
+        };
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Installs a tracer that is called after every executed instruction.
+    /// Tracing is off by default.
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer>) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.tracer.take()
+    }
+
+    /// Starts counting executions and cycles per address and per opcode.
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Starts attributing cycles to subroutines by following CALL, RST
+    /// and RET.
+    pub fn enable_call_profiler(&mut self) {
+        self.call_profiler = Some(CallProfiler::new());
+    }
+
+    pub fn call_profiler(&self) -> Option<&CallProfiler> {
+        self.call_profiler.as_ref()
+    }
+
+    /// Starts recording, for every memory byte, whether it was fetched as
+    /// an opcode or operand, read as data or written.
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::new());
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Starts checking SP and stack writes against a declared stack region.
+    /// Pushes over executed code are only recognised with coverage enabled.
+    pub fn enable_stack_guard(&mut self, config: StackGuardConfig) {
+        self.stack_guard = Some(StackGuard::new(config));
+    }
+
+    pub fn stack_guard(&self) -> Option<&StackGuard> {
+        self.stack_guard.as_ref()
+    }
+
+    pub fn stack_guard_mut(&mut self) -> Option<&mut StackGuard> {
+        self.stack_guard.as_mut()
+    }
+
+    /// Enforces the read/write/execute attributes of `map` on every access.
+    pub fn set_memory_map(&mut self, map: MemoryMap) {
+        self.memory_map = Some(map);
+    }
+
+    pub fn memory_map(&self) -> Option<&MemoryMap> {
+        self.memory_map.as_ref()
+    }
+
+    pub fn memory_map_mut(&mut self) -> Option<&mut MemoryMap> {
+        self.memory_map.as_mut()
+    }
+
+    /// Reports stores into bytes that were executed before, optionally
+    /// stopping on the first one.
+    pub fn enable_self_modify_detector(&mut self, break_on_write: bool) {
+        self.self_modify = Some(SelfModifyDetector::new(break_on_write));
+    }
+
+    pub fn self_modify_detector(&self) -> Option<&SelfModifyDetector> {
+        self.self_modify.as_ref()
+    }
+
+    /// Takes a snapshot every `config.interval` instructions so execution
+    /// can be stepped backwards. Attached devices are not part of the
+    /// snapshots.
+    pub fn enable_rewind(&mut self, config: RewindConfig) {
+        self.rewind = Some(Rewind::new(config));
+    }
+
+    pub fn rewind(&self) -> Option<&Rewind> {
+        self.rewind.as_ref()
+    }
+
+    /// Returns to the point where `target` instructions had run, by loading
+    /// the nearest earlier snapshot and replaying forward with the inputs
+    /// logged since. Neither the tracer nor the input recorder see replayed
+    /// instructions; the other recorders do.
+    pub fn rewind_to(&mut self, target: u64) -> Result<(), RewindError> {
+        let mut rewind = self.rewind.take().ok_or(RewindError::Disabled)?;
+        let result = match rewind.restore(target) {
+            Some((start, state)) => {
+                rewind.truncate_after(start);
+                let result = self.replay(&rewind, &state, target, |_| {});
+                rewind.truncate_inputs(self.cycles);
+                result
+            }
+            None => Err(RewindError::OutOfHistory {
+                oldest: rewind.oldest(),
+            }),
+        };
+        self.rewind = Some(rewind);
+        result
+    }
+
+    /// Undoes the last instruction.
+    pub fn reverse_step(&mut self) -> Result<(), RewindError> {
+        match self.instructions.checked_sub(1) {
+            Some(target) => self.rewind_to(target),
+            None => Err(RewindError::OutOfHistory { oldest: Some(0) }),
+        }
+    }
+
+    /// Runs backwards to the most recent point where one of `stops` hits.
+    /// Returns false, positioned at the oldest snapshot, when none did.
+    pub fn reverse_continue(&mut self, stops: &[Stop]) -> Result<bool, RewindError> {
+        let rewind = self.rewind.take().ok_or(RewindError::Disabled)?;
+        let end = self.instructions;
+        let starts = rewind
+            .instructions()
+            .filter(|start| *start < end)
+            .collect::<Vec<_>>();
+        let mut segment_end = end;
+        let mut hit = None;
+        let mut result = Ok(());
+        for start in starts.into_iter().rev() {
+            let (_, state) = rewind.restore(start).unwrap();
+            let mut watched: Option<Vec<u8>> = None;
+            result = self.replay(&rewind, &state, segment_end, |cpu| {
+                let index = cpu.instructions;
+                let values = stops
+                    .iter()
+                    .filter_map(|stop| match stop {
+                        Stop::Watchpoint(address) => Some(cpu.memory[*address as usize]),
+                        Stop::Breakpoint(_) => None,
+                    })
+                    .collect::<Vec<_>>();
+                if watched.as_ref().is_some_and(|watched| *watched != values) {
+                    hit = Some(index - 1);
+                }
+                watched = Some(values);
+                if index < segment_end && stops.contains(&Stop::Breakpoint(cpu.pc as u16)) {
+                    hit = Some(index);
+                }
+            });
+            if result.is_err() || hit.is_some() {
+                break;
+            }
+            segment_end = start;
+        }
+        self.rewind = Some(rewind);
+        result?;
+        match hit {
+            Some(index) => self.rewind_to(index).map(|_| true),
+            None => self.rewind_to(segment_end).map(|_| false),
+        }
+    }
+
+    /// Loads `state` and steps until `target` instructions have run,
+    /// calling `visit` at every instruction boundary including both ends.
+    /// The inputs `rewind` logged are fed back in place of the ports and
+    /// interrupts, and a halted CPU idles until the next logged interrupt.
+    fn replay(
+        &mut self,
+        rewind: &Rewind,
+        state: &[u8],
+        target: u64,
+        mut visit: impl FnMut(&Cpu),
+    ) -> Result<(), RewindError> {
+        self.load_state(state, &mut [])?;
+        let inputs = InputLog {
+            rom_crc: 0,
+            events: rewind.inputs(self.cycles),
         };
+        let tracer = self.tracer.take();
+        let recorder = self.recorder.take();
+        let replayer = self.replayer.replace(Replayer::new(inputs));
+        let mut result = Ok(());
+        visit(self);
+        while self.instructions < target
+            && !(self.halted && self.replayer.as_ref().is_some_and(Replayer::finished))
+        {
+            if let Err(error) = self.step() {
+                result = Err(error.into());
+                break;
+            }
+            visit(self);
+        }
+        self.tracer = tracer;
+        self.recorder = recorder;
+        self.replayer = replayer;
+        result
     }
 
-    pub fn load_rom(&mut self, buffer: &Vec<u8>) {
-        self.memory[..buffer.len()].clone_from_slice(&buffer[..]);
+    /// Records the operands of each ALU operation instead of computing its
+    /// flags, and works them out only when something reads them all: DAA,
+    /// PUSH PSW, `state` or a save state. Conditions and instructions that
+    /// use or set CY work out that one flag. Results are the same as without.
+    pub fn enable_lazy_flags(&mut self) {
+        self.lazy_flags = true;
     }
 
-    pub fn read(&mut self) {
+    /// Lets `run` execute cached blocks of pre-decoded instructions instead
+    /// of decoding one instruction per step.
+    pub fn enable_block_cache(&mut self) {
+        self.block_cache = Some(BlockCache::new());
+    }
+
+    pub fn block_cache(&self) -> Option<&BlockCache> {
+        self.block_cache.as_ref()
+    }
+
+    /// Runs until the cycle counter reaches `until`, the next instruction is
+    /// at one of `breakpoints` or the CPU halts. The instruction the run
+    /// starts on is never treated as a breakpoint, so a run that stopped on
+    /// one can continue. Ends on the same instruction boundary as calling
+    /// `step` while `cycles() < until`.
+    ///
+    /// With the block cache enabled, whole blocks run without the
+    /// per-instruction bookkeeping of `step`. Breakpoints, the tracer and
+    /// the other recorders need that bookkeeping, so any of them being
+    /// active falls back to stepping.
+    ///
+    /// The block cache also recognises idle loops: a block that jumps back
+    /// to itself without storing, doing I/O or changing a register, such as
+    /// `JMP $` or polling a flag an interrupt handler sets. Nothing can
+    /// change before the next interrupt, so the run skips straight to the
+    /// last pass that starts before `until`, counting every skipped cycle
+    /// and instruction. Pass the time of the next interrupt or device event
+    /// as `until` to get the most out of it.
+    pub fn run(&mut self, until: u64, breakpoints: &[u16]) -> Result<RunExit, CpuError> {
+        if self.block_cache.is_some() && breakpoints.is_empty() && !self.instrumented() {
+            return self.run_blocks(until);
+        }
+        let mut first = true;
+        loop {
+            // A replayed log may still wake the CPU with an interrupt
+            if self.halted && self.replayer.is_none() {
+                return Ok(RunExit::Halted);
+            }
+            if self.cycles >= until {
+                return Ok(RunExit::Cycles);
+            }
+            if !first && breakpoints.contains(&(self.pc as u16)) {
+                return Ok(RunExit::Breakpoint(self.pc as u16));
+            }
+            first = false;
+            self.step()?;
+        }
+    }
+
+    fn run_blocks(&mut self, until: u64) -> Result<RunExit, CpuError> {
+        loop {
+            if self.halted {
+                return Ok(RunExit::Halted);
+            }
+            if self.cycles >= until {
+                return Ok(RunExit::Cycles);
+            }
+            let Some(cache) = self.block_cache.as_mut() else {
+                return Ok(RunExit::Cycles);
+            };
+            let block = cache.get(self.pc as u16, &self.memory, self.dispatch);
+            if block.ops.is_empty() {
+                // Reports the unknown opcode
+                self.step()?;
+                continue;
+            }
+            let start = self.pc;
+            let before = block
+                .idle_candidate
+                .then(|| (self.registers, self.read_f_reg(), self.sp, self.cycles));
+            let mut ran = 0;
+            for op in block.ops.iter() {
+                self.pc = (self.pc + op.entry.size as usize) % MEMORY_SIZE;
+                self.cycles += op.entry.cycles as u64;
+                (op.entry.handler)(self, op.operands);
+                self.instructions += 1;
+                ran += 1;
+                if self.cycles >= until
+                    || self
+                        .block_cache
+                        .as_mut()
+                        .is_some_and(BlockCache::take_written)
+                {
+                    break;
+                }
+            }
+            // A pass over an idle loop that changed nothing will repeat
+            // until `until`, so whole passes are skipped up to the last
+            // one that starts before it.
+            if let Some((registers, f, sp, cycles)) = before {
+                let idle = ran == block.ops.len()
+                    && self.pc == start
+                    && (self.registers, self.read_f_reg(), self.sp) == (registers, f, sp);
+                if idle && self.cycles < until {
+                    let pass = self.cycles - cycles;
+                    let passes = (until - self.cycles - 1) / pass;
+                    self.cycles += passes * pass;
+                    self.instructions += passes * ran as u64;
+                    if let Some(cache) = self.block_cache.as_mut() {
+                        cache.skip_idle(passes * pass);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether anything needs to see every instruction as `step` runs it.
+    fn instrumented(&self) -> bool {
+        self.history.is_some()
+            || self.tracer.is_some()
+            || self.profiler.is_some()
+            || self.call_profiler.is_some()
+            || self.coverage.is_some()
+            || self.stack_guard.is_some()
+            || self
+                .memory_map
+                .as_ref()
+                .is_some_and(MemoryMap::reports_violations)
+            || self.init_tracker.is_some()
+            || self.self_modify.is_some()
+            || self.rewind.is_some()
+            || self.replayer.is_some()
+    }
+
+    /// Attaches the hardware read by IN and written by OUT.
+    pub fn set_ports(&mut self, ports: Box<dyn Ports>) {
+        self.ports = ports;
+    }
+
+    pub fn take_ports(&mut self) -> Box<dyn Ports> {
+        std::mem::replace(&mut self.ports, Box::new(NullPorts))
+    }
+
+    /// Requests an interrupt that runs `RST vector`. Returns whether it was
+    /// accepted, which needs interrupts enabled. Ignored while replaying,
+    /// since the log supplies the interrupts then.
+    pub fn interrupt(&mut self, vector: u8) -> bool {
+        if !self.interrupts_enabled || self.replayer.is_some() {
+            return false;
+        }
+        self.accept_interrupt(vector);
+        true
+    }
+
+    fn accept_interrupt(&mut self, vector: u8) {
+        let interrupted = self.pc as u16;
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.push(self.cycles, InputKind::Interrupt { vector });
+        }
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.record(self.cycles, InputKind::Interrupt { vector });
+        }
+        self.interrupts_enabled = false;
+        self.halted = false;
+        opcodes::rst_n(self, vector & 7);
+        self.cycles += 11;
+        if let Some(guard) = self.stack_guard.as_mut() {
+            guard.commit_interrupt(interrupted, vector & 7, self.sp);
+        }
+        // The handler shows up in the call profile as if RST had been called
+        if let Some(call_profiler) = self.call_profiler.as_mut() {
+            call_profiler.record(Transfer::Call, self.pc as u16, self.sp, 11, self.cycles);
+        }
+    }
+
+    /// Logs every IN value and accepted interrupt from here on. `rom` is
+    /// hashed into the log so a replay against other code is refused.
+    pub fn start_recording(&mut self, rom: &[u8]) {
+        self.recorder = Some(InputLog::new(rom));
+    }
+
+    pub fn take_recording(&mut self) -> Option<InputLog> {
+        self.recorder.take()
+    }
+
+    /// Feeds `log` back in place of the ports and `interrupt` calls. Start
+    /// from the same state the recording started from.
+    pub fn start_replay(&mut self, log: InputLog) {
+        self.replayer = Some(Replayer::new(log));
+    }
+
+    pub fn replayer(&self) -> Option<&Replayer> {
+        self.replayer.as_ref()
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn interrupts_enabled(&self) -> bool {
+        self.interrupts_enabled
+    }
+
+    /// Set by HLT.
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Number of instructions executed since power-on.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            pc: self.pc as u16,
+            sp: self.sp,
+            registers: self.registers,
+            f: self.read_f_reg(),
+            cycles: self.cycles,
+        }
+    }
+
+    pub fn read(&mut self) -> Result<(), CpuError> {
         for _i in 0..80 {
-            let cmd = self.memory[self.pc];
-            println!("reading {} at command#: {}", cmd, _i);
-            println!("PC: {:04x} SP: {:04x}", self.pc, self.sp);
-
-            let opcode = self.memory[self.pc];
-            let operands: [u8; 2] = [self.memory[self.pc + 1], self.memory[self.pc + 2]];
-            let opcode = Opcodes::from_hex(opcode);
-            let instruction_def = opcode.get_instruction_def();
-            self.pc += instruction_def.size as usize;
-            // self.to_string();
-            match opcode {
-                // Data transfer
-                Opcodes::MOV_A_A => opcodes::mov_r_r(self, Registers::A, Registers::A),
-                Opcodes::MOV_A_B => opcodes::mov_r_r(self, Registers::A, Registers::B),
-                Opcodes::MOV_A_C => opcodes::mov_r_r(self, Registers::A, Registers::C),
-                Opcodes::MOV_A_D => opcodes::mov_r_r(self, Registers::A, Registers::D),
-                Opcodes::MOV_A_E => opcodes::mov_r_r(self, Registers::A, Registers::E),
-                Opcodes::MOV_A_H => opcodes::mov_r_r(self, Registers::A, Registers::H),
-                Opcodes::MOV_A_L => opcodes::mov_r_r(self, Registers::A, Registers::L),
-                Opcodes::MOV_A_M => opcodes::mov_r_m(self, Registers::A),
-
-                Opcodes::MOV_B_A => opcodes::mov_r_r(self, Registers::B, Registers::A),
-                Opcodes::MOV_B_B => opcodes::mov_r_r(self, Registers::B, Registers::B),
-                Opcodes::MOV_B_C => opcodes::mov_r_r(self, Registers::B, Registers::C),
-                Opcodes::MOV_B_D => opcodes::mov_r_r(self, Registers::B, Registers::D),
-                Opcodes::MOV_B_E => opcodes::mov_r_r(self, Registers::B, Registers::E),
-                Opcodes::MOV_B_H => opcodes::mov_r_r(self, Registers::B, Registers::H),
-                Opcodes::MOV_B_L => opcodes::mov_r_r(self, Registers::B, Registers::L),
-                Opcodes::MOV_B_M => opcodes::mov_r_m(self, Registers::B),
-
-                Opcodes::MOV_C_A => opcodes::mov_r_r(self, Registers::C, Registers::A),
-                Opcodes::MOV_C_B => opcodes::mov_r_r(self, Registers::C, Registers::B),
-                Opcodes::MOV_C_C => opcodes::mov_r_r(self, Registers::C, Registers::C),
-                Opcodes::MOV_C_D => opcodes::mov_r_r(self, Registers::C, Registers::D),
-                Opcodes::MOV_C_E => opcodes::mov_r_r(self, Registers::C, Registers::E),
-                Opcodes::MOV_C_H => opcodes::mov_r_r(self, Registers::C, Registers::H),
-                Opcodes::MOV_C_L => opcodes::mov_r_r(self, Registers::C, Registers::L),
-                Opcodes::MOV_C_M => opcodes::mov_r_m(self, Registers::C),
-
-                Opcodes::MOV_D_A => opcodes::mov_r_r(self, Registers::D, Registers::A),
-                Opcodes::MOV_D_B => opcodes::mov_r_r(self, Registers::D, Registers::B),
-                Opcodes::MOV_D_C => opcodes::mov_r_r(self, Registers::D, Registers::C),
-                Opcodes::MOV_D_D => opcodes::mov_r_r(self, Registers::D, Registers::D),
-                Opcodes::MOV_D_E => opcodes::mov_r_r(self, Registers::D, Registers::E),
-                Opcodes::MOV_D_H => opcodes::mov_r_r(self, Registers::D, Registers::H),
-                Opcodes::MOV_D_L => opcodes::mov_r_r(self, Registers::D, Registers::L),
-                Opcodes::MOV_D_M => opcodes::mov_r_m(self, Registers::D),
-
-                Opcodes::MOV_E_A => opcodes::mov_r_r(self, Registers::E, Registers::A),
-                Opcodes::MOV_E_B => opcodes::mov_r_r(self, Registers::E, Registers::B),
-                Opcodes::MOV_E_C => opcodes::mov_r_r(self, Registers::E, Registers::C),
-                Opcodes::MOV_E_D => opcodes::mov_r_r(self, Registers::E, Registers::D),
-                Opcodes::MOV_E_E => opcodes::mov_r_r(self, Registers::E, Registers::E),
-                Opcodes::MOV_E_H => opcodes::mov_r_r(self, Registers::E, Registers::H),
-                Opcodes::MOV_E_L => opcodes::mov_r_r(self, Registers::E, Registers::L),
-                Opcodes::MOV_E_M => opcodes::mov_r_m(self, Registers::E),
-
-                Opcodes::MOV_H_A => opcodes::mov_r_r(self, Registers::H, Registers::A),
-                Opcodes::MOV_H_B => opcodes::mov_r_r(self, Registers::H, Registers::B),
-                Opcodes::MOV_H_C => opcodes::mov_r_r(self, Registers::H, Registers::C),
-                Opcodes::MOV_H_D => opcodes::mov_r_r(self, Registers::H, Registers::D),
-                Opcodes::MOV_H_E => opcodes::mov_r_r(self, Registers::H, Registers::E),
-                Opcodes::MOV_H_H => opcodes::mov_r_r(self, Registers::H, Registers::H),
-                Opcodes::MOV_H_L => opcodes::mov_r_r(self, Registers::H, Registers::L),
-                Opcodes::MOV_H_M => opcodes::mov_r_m(self, Registers::H),
-
-                Opcodes::MOV_L_A => opcodes::mov_r_r(self, Registers::L, Registers::A),
-                Opcodes::MOV_L_B => opcodes::mov_r_r(self, Registers::L, Registers::B),
-                Opcodes::MOV_L_C => opcodes::mov_r_r(self, Registers::L, Registers::C),
-                Opcodes::MOV_L_D => opcodes::mov_r_r(self, Registers::L, Registers::D),
-                Opcodes::MOV_L_E => opcodes::mov_r_r(self, Registers::L, Registers::E),
-                Opcodes::MOV_L_H => opcodes::mov_r_r(self, Registers::L, Registers::H),
-                Opcodes::MOV_L_L => opcodes::mov_r_r(self, Registers::L, Registers::L),
-                Opcodes::MOV_L_M => opcodes::mov_r_m(self, Registers::L),
-
-                Opcodes::MOV_M_A => opcodes::mov_m_r(self, Registers::A),
-                Opcodes::MOV_M_B => opcodes::mov_m_r(self, Registers::B),
-                Opcodes::MOV_M_C => opcodes::mov_m_r(self, Registers::C),
-                Opcodes::MOV_M_D => opcodes::mov_m_r(self, Registers::D),
-                Opcodes::MOV_M_E => opcodes::mov_m_r(self, Registers::E),
-                Opcodes::MOV_M_H => opcodes::mov_m_r(self, Registers::H),
-                Opcodes::MOV_M_L => opcodes::mov_m_r(self, Registers::L),
-
-                Opcodes::MVI_A => opcodes::mvi_r(self, Registers::A, operands[0]),
-                Opcodes::MVI_B => opcodes::mvi_r(self, Registers::B, operands[0]),
-                Opcodes::MVI_C => opcodes::mvi_r(self, Registers::C, operands[0]),
-                Opcodes::MVI_D => opcodes::mvi_r(self, Registers::D, operands[0]),
-                Opcodes::MVI_E => opcodes::mvi_r(self, Registers::E, operands[0]),
-                Opcodes::MVI_H => opcodes::mvi_r(self, Registers::H, operands[0]),
-                Opcodes::MVI_L => opcodes::mvi_r(self, Registers::L, operands[0]),
-                Opcodes::MVI_M => opcodes::mvi_m(self, operands[0]),
-
-                Opcodes::LXI_B => opcodes::lxi_r(self, Registers::B, operands),
-                Opcodes::LXI_D => opcodes::lxi_r(self, Registers::D, operands),
-                Opcodes::LXI_H => opcodes::lxi_r(self, Registers::H, operands),
-                Opcodes::LXI_SP => opcodes::lxi_sp(self, operands),
-
-                Opcodes::LDA => opcodes::lda(self, operands),
-                Opcodes::STA => opcodes::sta(self, operands),
-                Opcodes::LHLD => opcodes::lhld(self, operands),
-                Opcodes::SHLD => opcodes::shld(self, operands),
-
-                Opcodes::LDAX_B => opcodes::ldax(self, Registers::B),
-                Opcodes::LDAX_D => opcodes::ldax(self, Registers::D),
-                Opcodes::STAX_B => opcodes::stax(self, Registers::B),
-                Opcodes::STAX_D => opcodes::stax(self, Registers::D),
-                Opcodes::XCHG => opcodes::xchg(self),
-
-                // arithmetic
-                Opcodes::ADD_A => opcodes::add_r(self, Registers::A),
-                Opcodes::ADD_B => opcodes::add_r(self, Registers::B),
-                Opcodes::ADD_C => opcodes::add_r(self, Registers::C),
-                Opcodes::ADD_D => opcodes::add_r(self, Registers::D),
-                Opcodes::ADD_E => opcodes::add_r(self, Registers::E),
-                Opcodes::ADD_H => opcodes::add_r(self, Registers::H),
-                Opcodes::ADD_L => opcodes::add_r(self, Registers::L),
-                Opcodes::ADD_M => opcodes::add_m(self),
-
-                Opcodes::ADC_A => opcodes::adc_r(self, Registers::A),
-                Opcodes::ADC_B => opcodes::adc_r(self, Registers::B),
-                Opcodes::ADC_C => opcodes::adc_r(self, Registers::C),
-                Opcodes::ADC_D => opcodes::adc_r(self, Registers::D),
-                Opcodes::ADC_E => opcodes::adc_r(self, Registers::E),
-                Opcodes::ADC_H => opcodes::adc_r(self, Registers::H),
-                Opcodes::ADC_L => opcodes::adc_r(self, Registers::L),
-                Opcodes::ADC_M => opcodes::adc_m(self),
-
-                Opcodes::ACI => opcodes::aci(self, operands[0]),
-
-                Opcodes::SUB_A => opcodes::sub_r(self, Registers::A),
-                Opcodes::SUB_B => opcodes::sub_r(self, Registers::B),
-                Opcodes::SUB_C => opcodes::sub_r(self, Registers::C),
-                Opcodes::SUB_D => opcodes::sub_r(self, Registers::D),
-                Opcodes::SUB_E => opcodes::sub_r(self, Registers::E),
-                Opcodes::SUB_H => opcodes::sub_r(self, Registers::H),
-                Opcodes::SUB_L => opcodes::sub_r(self, Registers::L),
-                Opcodes::SUB_M => opcodes::sub_m(self),
-                Opcodes::SUI => opcodes::sui(self, operands[0]),
-
-                Opcodes::SBB_A => opcodes::sbb_r(self, Registers::A),
-                Opcodes::SBB_B => opcodes::sbb_r(self, Registers::B),
-                Opcodes::SBB_C => opcodes::sbb_r(self, Registers::C),
-                Opcodes::SBB_D => opcodes::sbb_r(self, Registers::D),
-                Opcodes::SBB_E => opcodes::sbb_r(self, Registers::E),
-                Opcodes::SBB_H => opcodes::sbb_r(self, Registers::H),
-                Opcodes::SBB_L => opcodes::sbb_r(self, Registers::L),
-                Opcodes::SBB_M => opcodes::sbb_m(self),
-
-                Opcodes::INR_A => opcodes::inr_r(self, Registers::A),
-                Opcodes::INR_B => opcodes::inr_r(self, Registers::B),
-                Opcodes::INR_C => opcodes::inr_r(self, Registers::C),
-                Opcodes::INR_D => opcodes::inr_r(self, Registers::D),
-                Opcodes::INR_E => opcodes::inr_r(self, Registers::E),
-                Opcodes::INR_H => opcodes::inr_r(self, Registers::H),
-                Opcodes::INR_L => opcodes::inr_r(self, Registers::L),
-                Opcodes::INR_M => opcodes::inr_m(self),
-
-                Opcodes::DCR_A => opcodes::dcr_r(self, Registers::A),
-                Opcodes::DCR_B => opcodes::dcr_r(self, Registers::B),
-                Opcodes::DCR_C => opcodes::dcr_r(self, Registers::C),
-                Opcodes::DCR_D => opcodes::dcr_r(self, Registers::D),
-                Opcodes::DCR_E => opcodes::dcr_r(self, Registers::E),
-                Opcodes::DCR_H => opcodes::dcr_r(self, Registers::H),
-                Opcodes::DCR_L => opcodes::dcr_r(self, Registers::L),
-                Opcodes::DCR_M => opcodes::dcr_m(self),
-
-                Opcodes::INX_B => opcodes::inx_rp(self, Registers::B),
-                Opcodes::INX_D => opcodes::inx_rp(self, Registers::D),
-                Opcodes::INX_H => opcodes::inx_rp(self, Registers::H),
-                Opcodes::INX_SP => opcodes::inx_sp(self),
-
-                Opcodes::DCX_B => opcodes::dcx_rp(self, Registers::B),
-                Opcodes::DCX_D => opcodes::dcx_rp(self, Registers::D),
-                Opcodes::DCX_H => opcodes::dcx_rp(self, Registers::H),
-                Opcodes::DCX_SP => opcodes::dcx_sp(self),
-
-                Opcodes::DAD_B => opcodes::dad_rp(self, Registers::B),
-                Opcodes::DAD_D => opcodes::dad_rp(self, Registers::D),
-                Opcodes::DAD_H => opcodes::dad_rp(self, Registers::H),
-                Opcodes::DAD_SP => opcodes::dad_sp(self),
-                Opcodes::DAA => opcodes::daa(self),
-
-                // Logical Groups
-                Opcodes::ANA_A => opcodes::ana_r(self, Registers::A),
-                Opcodes::ANA_B => opcodes::ana_r(self, Registers::B),
-                Opcodes::ANA_C => opcodes::ana_r(self, Registers::C),
-                Opcodes::ANA_D => opcodes::ana_r(self, Registers::D),
-                Opcodes::ANA_E => opcodes::ana_r(self, Registers::E),
-                Opcodes::ANA_H => opcodes::ana_r(self, Registers::H),
-                Opcodes::ANA_L => opcodes::ana_r(self, Registers::L),
-                Opcodes::ANA_M => opcodes::ana_m(self),
-                Opcodes::ANI => opcodes::ani(self, operands[0]),
-
-                Opcodes::XRA_A => opcodes::xra_r(self, Registers::A),
-                Opcodes::XRA_B => opcodes::xra_r(self, Registers::B),
-                Opcodes::XRA_C => opcodes::xra_r(self, Registers::C),
-                Opcodes::XRA_D => opcodes::xra_r(self, Registers::D),
-                Opcodes::XRA_E => opcodes::xra_r(self, Registers::E),
-                Opcodes::XRA_H => opcodes::xra_r(self, Registers::H),
-                Opcodes::XRA_L => opcodes::xra_r(self, Registers::L),
-                Opcodes::XRA_M => opcodes::xra_m(self),
-                Opcodes::XRI => opcodes::xri(self, operands[0]),
-                Opcodes::ORA_A => opcodes::ora_r(self, Registers::A),
-                Opcodes::ORA_B => opcodes::ora_r(self, Registers::B),
-                Opcodes::ORA_C => opcodes::ora_r(self, Registers::C),
-                Opcodes::ORA_D => opcodes::ora_r(self, Registers::D),
-                Opcodes::ORA_E => opcodes::ora_r(self, Registers::E),
-                Opcodes::ORA_H => opcodes::ora_r(self, Registers::H),
-                Opcodes::ORA_L => opcodes::ora_r(self, Registers::L),
-                Opcodes::ORA_M => opcodes::ora_m(self),
-                Opcodes::ORI => opcodes::ori(self, operands[0]),
-                Opcodes::CMP_A => opcodes::cmp_r(self, Registers::A),
-                Opcodes::CMP_B => opcodes::cmp_r(self, Registers::B),
-                Opcodes::CMP_C => opcodes::cmp_r(self, Registers::C),
-                Opcodes::CMP_D => opcodes::cmp_r(self, Registers::D),
-                Opcodes::CMP_E => opcodes::cmp_r(self, Registers::E),
-                Opcodes::CMP_H => opcodes::cmp_r(self, Registers::H),
-                Opcodes::CMP_L => opcodes::cmp_r(self, Registers::L),
-                Opcodes::CMP_M => opcodes::cmp_m(self),
-
-                Opcodes::CPI => opcodes::cpi(self, operands[0]),
-                Opcodes::RLC => opcodes::rlc(self),
-                Opcodes::RRC => opcodes::rrc(self),
-                Opcodes::RAL => opcodes::ral(self),
-                Opcodes::RAR => opcodes::rar(self),
-                Opcodes::CMA => opcodes::cma(self),
-                Opcodes::CMC => opcodes::cmc(self),
-                Opcodes::STC => opcodes::stc(self),
-
-                // Branch group
-                Opcodes::JMP => opcodes::jmp(self, operands),
-                Opcodes::JNZ => opcodes::jcc(self, ConditionCodes::Z, false, operands),
-                Opcodes::JZ => opcodes::jcc(self, ConditionCodes::Z, true, operands),
-                Opcodes::JNC => opcodes::jcc(self, ConditionCodes::CY, false, operands),
-                Opcodes::JC => opcodes::jcc(self, ConditionCodes::CY, true, operands),
-                Opcodes::JPO => opcodes::jcc(self, ConditionCodes::P, false, operands),
-                Opcodes::JPE => opcodes::jcc(self, ConditionCodes::P, true, operands),
-                Opcodes::JP => opcodes::jcc(self, ConditionCodes::S, false, operands),
-                Opcodes::JM => opcodes::jcc(self, ConditionCodes::S, true, operands),
-                Opcodes::CALL => opcodes::call(self, operands),
-                Opcodes::CNZ => opcodes::ccc(self, ConditionCodes::Z, false, operands),
-                Opcodes::CZ => opcodes::ccc(self, ConditionCodes::Z, true, operands),
-                Opcodes::CNC => opcodes::ccc(self, ConditionCodes::CY, false, operands),
-                Opcodes::CC => opcodes::ccc(self, ConditionCodes::CY, true, operands),
-                Opcodes::CPO => opcodes::ccc(self, ConditionCodes::P, false, operands),
-                Opcodes::CPE => opcodes::ccc(self, ConditionCodes::P, true, operands),
-                Opcodes::CP => opcodes::ccc(self, ConditionCodes::S, false, operands),
-                Opcodes::CM => opcodes::ccc(self, ConditionCodes::S, true, operands),
-
-                Opcodes::RET => opcodes::ret(self),
-                Opcodes::RNZ => opcodes::rcc(self, ConditionCodes::Z, false),
-                Opcodes::RZ => opcodes::rcc(self, ConditionCodes::Z, true),
-                Opcodes::RNC => opcodes::rcc(self, ConditionCodes::CY, false),
-                Opcodes::RC => opcodes::rcc(self, ConditionCodes::CY, true),
-                Opcodes::RPO => opcodes::rcc(self, ConditionCodes::P, false),
-                Opcodes::RPE => opcodes::rcc(self, ConditionCodes::P, true),
-                Opcodes::RP => opcodes::rcc(self, ConditionCodes::S, false),
-                Opcodes::RM => opcodes::rcc(self, ConditionCodes::S, true),
-
-                Opcodes::RST_0 => opcodes::rst_n(self, 0),
-                Opcodes::RST_1 => opcodes::rst_n(self, 1),
-                Opcodes::RST_2 => opcodes::rst_n(self, 2),
-                Opcodes::RST_3 => opcodes::rst_n(self, 3),
-                Opcodes::RST_4 => opcodes::rst_n(self, 4),
-                Opcodes::RST_5 => opcodes::rst_n(self, 5),
-                Opcodes::RST_6 => opcodes::rst_n(self, 6),
-                Opcodes::RST_7 => opcodes::rst_n(self, 7),
-                Opcodes::PCHL => opcodes::pchl(self),
-
-                // Stack, I/O
-                Opcodes::PUSH_B => opcodes::push_rp(self, Registers::B),
-                Opcodes::PUSH_D => opcodes::push_rp(self, Registers::D),
-                Opcodes::PUSH_H => opcodes::push_rp(self, Registers::H),
-                Opcodes::PUSH_PSW => opcodes::push_psw(self),
-                Opcodes::POP_B => opcodes::pop_rp(self, Registers::B),
-                Opcodes::POP_D => opcodes::pop_rp(self, Registers::D),
-                Opcodes::POP_H => opcodes::pop_rp(self, Registers::H),
-                Opcodes::POP_PSW => opcodes::pop_psw(self),
-
-                Opcodes::XTHL => opcodes::xthl(self),
-                Opcodes::SPHL => opcodes::sphl(self),
-
-                Opcodes::NOP => opcodes::nop(),
-                _ => panic!("Opcode excecution not implemented {}", opcode),
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Fetches, decodes and executes a single instruction; a halted CPU
+    /// only burns four cycles.
+    pub fn step(&mut self) -> Result<(), CpuError> {
+        if let Some(vector) = self
+            .replayer
+            .as_mut()
+            .and_then(|replayer| replayer.interrupt(self.cycles))
+        {
+            self.accept_interrupt(vector);
+        }
+        if let Some(alert) = self
+            .stack_guard
+            .as_mut()
+            .and_then(StackGuard::take_interrupt_alert)
+        {
+            return Err(self.error(ErrorKind::Stack(alert)));
+        }
+        if self.halted {
+            self.cycles += 4;
+            return Ok(());
+        }
+        if self
+            .rewind
+            .as_ref()
+            .is_some_and(|rewind| rewind.due(self.instructions))
+        {
+            let state = self.save_state(&[]);
+            if let Some(rewind) = self.rewind.as_mut() {
+                rewind.push(self.instructions, self.cycles, state);
+            }
+        }
+        let opcode = self.memory[self.pc];
+        let operands: [u8; 2] = [
+            self.memory[(self.pc + 1) % MEMORY_SIZE],
+            self.memory[(self.pc + 2) % MEMORY_SIZE],
+        ];
+        let Some(entry) = self.dispatch[opcode as usize] else {
+            return Err(self.error(ErrorKind::UnknownOpcode(opcode)));
+        };
+        if let Some(map) = self.memory_map.as_mut() {
+            if !map.check(self.pc as u16, Access::Execute) {
+                if let Some(violation) = map.commit(self.pc as u16) {
+                    return Err(self.error(ErrorKind::Protection(violation)));
+                }
+            }
+        }
+        let instruction = Instruction {
+            opcode,
+            operands,
+            size: entry.size,
+        };
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.mark(self.pc as u16, access::OPCODE);
+            for offset in 1..entry.size as u16 {
+                coverage.mark((self.pc as u16).wrapping_add(offset), access::OPERAND);
+            }
+        }
+        if let Some(detector) = self.self_modify.as_mut() {
+            detector.execute(self.pc as u16, entry.size);
+        }
+        if let Some(tracker) = self.init_tracker.as_mut() {
+            for offset in 0..entry.size as u16 {
+                tracker.read_memory((self.pc as u16).wrapping_add(offset));
+            }
+            tracker.read_registers(uninit::register_usage(opcode).0);
+        }
+        let before = (self.history.is_some() || self.tracer.is_some()).then(|| self.state());
+        if let (Some(history), Some(state)) = (self.history.as_mut(), before) {
+            history.push(HistoryEntry { instruction, state });
+        }
+        let pc = self.pc as u16;
+        let sp = self.sp;
+        let start_cycles = self.cycles;
+        self.pc = (self.pc + entry.size as usize) % MEMORY_SIZE;
+        self.cycles += entry.cycles as u64;
+        (entry.handler)(self, operands);
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record(pc, opcode, self.cycles - start_cycles);
+        }
+        let stack_alert = self
+            .stack_guard
+            .as_mut()
+            .and_then(|guard| guard.commit(pc, self.sp));
+        let violation = self.memory_map.as_mut().and_then(|map| map.commit(pc));
+        if let Some(tracker) = self.init_tracker.as_mut() {
+            tracker.write_registers(uninit::register_usage(opcode).1);
+            tracker.commit(pc);
+        }
+        let code_write = self
+            .self_modify
+            .as_mut()
+            .and_then(|detector| detector.commit(pc));
+        let desync = self.replayer.as_mut().and_then(Replayer::take_desync);
+        if let Some(call_profiler) = self.call_profiler.as_mut() {
+            call_profiler.record(
+                Transfer::classify(opcode, sp, self.sp),
+                self.pc as u16,
+                self.sp,
+                self.cycles - start_cycles,
+                self.cycles,
+            );
+        }
+        if let Some(before) = before.filter(|_| self.tracer.is_some()) {
+            let record = TraceRecord {
+                index: self.instructions,
+                instruction,
+                before,
+                after: self.state(),
+            };
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.trace(&record);
             }
-            // self::opcodes::read_op_code(self);
+        }
+        self.instructions += 1;
+        let fault = stack_alert
+            .map(ErrorKind::Stack)
+            .or(violation.map(ErrorKind::Protection))
+            .or(code_write.map(ErrorKind::SelfModify))
+            .or(desync.map(ErrorKind::Replay));
+        match fault {
+            Some(kind) => Err(self.error(kind)),
+            None => Ok(()),
+        }
+    }
+
+    fn error(&self, kind: ErrorKind) -> CpuError {
+        CpuError {
+            kind,
+            pc: self.pc as u16,
+            history: self
+                .history
+                .as_ref()
+                .map(|history| history.entries().cloned().collect())
+                .unwrap_or_default(),
         }
     }
 
     fn read_f_reg(&self) -> u8 {
-        let psw: u8 = (*self.cc.get(&ConditionCodes::S).unwrap_or(&false) as u8) << 7
-            | (*self.cc.get(&ConditionCodes::Z).unwrap_or(&false) as u8) << 6
-            | 0 << 5
-            | (*self.cc.get(&ConditionCodes::AC).unwrap_or(&false) as u8) << 4
-            | 0 << 3
-            | (*self.cc.get(&ConditionCodes::P).unwrap_or(&false) as u8) << 2
-            | 0 << 1
-            | (*self.cc.get(&ConditionCodes::CY).unwrap_or(&false) as u8);
-        return psw;
-    }
-
-    fn to_string(&mut self) {
-        println!(
-            "A: {:02x} F: {:02x} B: {:02x} C: {:02x} D: {:02x} E: {:02x} H: {:02x} L: {:02x}",
-            self.registers[crate::cpu::Registers::A as usize],
-            self.read_f_reg(),
-            self.registers[Registers::B as usize],
-            self.registers[Registers::C as usize],
-            self.registers[Registers::D as usize],
-            self.registers[Registers::E as usize],
-            self.registers[Registers::H as usize],
-            self.registers[Registers::L as usize]
-        );
-        println!(
-            "Z: {} S: {} P: {} CY: {} AC: {}",
-            self.cc.get(&ConditionCodes::Z).unwrap_or(&false),
-            self.cc.get(&ConditionCodes::S).unwrap_or(&false),
-            self.cc.get(&ConditionCodes::P).unwrap_or(&false),
-            self.cc.get(&ConditionCodes::CY).unwrap_or(&false),
-            self.cc.get(&ConditionCodes::AC).unwrap_or(&false)
-        );
-        println!("----------------------------------------------------------------");
+        self.pending_flags
+            .as_ref()
+            .map_or(self.f, flags::Pending::resolve)
+    }
+
+    fn port_in(&mut self, port: u8) -> u8 {
+        let value = match self
+            .replayer
+            .as_mut()
+            .and_then(|replayer| replayer.input(self.cycles, port))
+        {
+            Some(value) => value,
+            None => self.ports.input(port),
+        };
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.push(self.cycles, InputKind::In { port, value });
+        }
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.record(self.cycles, InputKind::In { port, value });
+        }
+        value
+    }
+
+    fn port_out(&mut self, port: u8, value: u8) {
+        self.ports.output(port, value);
+    }
+
+    fn write_f_reg(&mut self, f: u8) {
+        self.pending_flags = None;
+        self.f = f & (flags::S | flags::Z | flags::AC | flags::P | flags::CY) | flags::ALWAYS_SET;
+    }
+
+    fn read_byte(&mut self, address: u16) -> u8 {
+        let address = self
+            .memory_map
+            .as_ref()
+            .map_or(address, |map| map.translate(address));
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.mark(address, access::READ);
+        }
+        if let Some(map) = self.memory_map.as_mut() {
+            map.check(address, Access::Read);
+        }
+        if let Some(tracker) = self.init_tracker.as_mut() {
+            tracker.read_memory(address);
+        }
+        self.memory[address as usize]
+    }
+
+    /// Writes into regions without write permission are dropped.
+    fn write_byte(&mut self, address: u16, value: u8) {
+        let address = self
+            .memory_map
+            .as_ref()
+            .map_or(address, |map| map.translate(address));
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.mark(address, access::WRITE);
+        }
+        if let Some(map) = self.memory_map.as_mut() {
+            if !map.check(address, Access::Write) {
+                return;
+            }
+        }
+        if let Some(tracker) = self.init_tracker.as_mut() {
+            tracker.mark_memory(address);
+        }
+        if let Some(detector) = self.self_modify.as_mut() {
+            detector.check_write(address, self.memory[address as usize], value);
+        }
+        self.memory[address as usize] = value;
+        if let Some(cache) = self.block_cache.as_mut() {
+            cache.write(address);
+        }
+    }
+
+    fn stack_push(&mut self, value: u16) {
+        let sp = self.sp.wrapping_sub(2);
+        if self.stack_guard.is_some() {
+            let over_code = self.coverage.as_ref().is_some_and(|coverage| {
+                coverage.is_code(sp) || coverage.is_code(sp.wrapping_add(1))
+            });
+            if let Some(guard) = self.stack_guard.as_mut() {
+                guard.check_push(sp, over_code);
+            }
+        }
+        self.write_byte(sp.wrapping_add(1), (value >> 8) as u8);
+        self.write_byte(sp, (value & 0xff) as u8);
+        self.sp = sp;
+    }
+
+    fn stack_pop(&mut self) -> u16 {
+        let value =
+            (self.read_byte(self.sp.wrapping_add(1)) as u16) << 8 | self.read_byte(self.sp) as u16;
+        self.sp = self.sp.wrapping_add(2);
+        if let Some(guard) = self.stack_guard.as_mut() {
+            guard.check_pop(self.sp);
+        }
+        value
     }
 
     fn get_register_pair(&self, r1: Registers, r2: Registers) -> u16 {
-        return (self.registers[r1 as usize] as u16) << 8 | self.registers[r2 as usize] as u16;
+        (self.registers[r1 as usize] as u16) << 8 | self.registers[r2 as usize] as u16
     }
 
     fn swap_register_pairs(&mut self, r1: Registers, r2: Registers) {
-        let temp = self.registers[r1.clone() as usize];
-        self.registers[r1 as usize] = self.registers[r2.clone() as usize];
-        self.registers[r2 as usize] = temp;
+        self.registers.swap(r1 as usize, r2 as usize);
     }
 
     fn set_register_pair(&mut self, r1: Registers, r2: Registers, value: u16) {
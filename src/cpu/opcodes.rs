@@ -1,12 +1,16 @@
 use core::fmt;
-use std::{any::Any, ffi::c_void, mem};
 
-use super::{registers, ConditionCodes, Cpu, Registers};
+use super::{
+    flags::{self, Operation, Pending},
+    ConditionCodes, Cpu, Registers,
+};
 
 const MAX_OPERANDS: usize = 2;
+/// Extra cycles a conditional call or return takes when the condition holds.
+const CONDITION_TAKEN_CYCLES: u64 = 6;
 
 pub struct InstructionDef {
-    cycles: u8,
+    pub cycles: u8,
     pub size: u8,
 }
 
@@ -16,7 +20,7 @@ impl fmt::Display for Opcodes {
     }
 }
 
-#[allow(nonstandard_style)]
+#[allow(nonstandard_style, clippy::upper_case_acronyms)]
 #[derive(Debug)]
 pub enum Opcodes {
     NOP,
@@ -256,281 +260,273 @@ pub enum Opcodes {
     PUSH_H,
     POP_D,
     POP_H,
+    EI,
+    DI,
+    HLT,
+    IN,
+    OUT,
+    ADI,
+    SBI,
 }
 impl Opcodes {
     #[rustfmt::skip]
-    pub fn from_hex(opcode: u8) -> Opcodes {
+    pub fn from_hex(opcode: u8) -> Option<Opcodes> {
         match opcode {
-            0x00 => Opcodes::NOP,
-            0x01 => Opcodes::LXI_B,
-            0x02 => Opcodes::STAX_B,
-            0x03 => Opcodes::INX_B,
-            0x04 => Opcodes::INR_B,
-            0x05 => Opcodes::DCR_B,
-            0x06 => Opcodes::MVI_B,
-            0x07 => Opcodes::RLC,
-            0x09 => Opcodes::DAD_B,
-            0x0a => Opcodes::LDAX_B,
-            0x0b => Opcodes::DCX_B,
-            0x0c => Opcodes::INR_C,
-            0x0d => Opcodes::DCR_C,
-            0x0e => Opcodes::MVI_C,
-            0x0f => Opcodes::RRC,
-            0x11 => Opcodes::LXI_D,
-            0x12 => Opcodes::STAX_D,
-            0x13 => Opcodes::INX_D,
-            0x14 => Opcodes::INR_D,
-            0x15 => Opcodes::DCR_D,
-            0x16 => Opcodes::MVI_D,
-            0x17 => Opcodes::RAL,
-            0x19 => Opcodes::DAD_D,
-            0x1a => Opcodes::LDAX_D,
-            0x1b => Opcodes::DCX_D,
-            0x1c => Opcodes::INR_E,
-            0x1d => Opcodes::DCR_E,
-            0x1e => Opcodes::MVI_E,
-            0x1f => Opcodes::RAR,
-            0x21 => Opcodes::LXI_H,
-            0x22 => Opcodes::SHLD,
-            0x23 => Opcodes::INX_H,
-            0x24 => Opcodes::INR_H,
-            0x25 => Opcodes::DCR_H,
-            0x26 => Opcodes::MVI_H,
-            0x27 => Opcodes::DAA,
-            0x29 => Opcodes::DAD_H,
-            0x2a => Opcodes::LHLD,
-            0x2b => Opcodes::DCX_H,
-            0x2c => Opcodes::INR_L,
-            0x2d => Opcodes::DCR_L,
-            0x2e => Opcodes::MVI_L,
-            0x2f => Opcodes::CMA,
-            0x31 => Opcodes::LXI_SP,
-            0x32 => Opcodes::STA,
-            0x33 => Opcodes::INX_SP,
-            0x34 => Opcodes::INR_M,
-            0x35 => Opcodes::DCR_M,
-            0x36 => Opcodes::MVI_M,
-            0x37 => Opcodes::STC,
-            0x39 => Opcodes::DAD_SP,
-            0x3a => Opcodes::LDA,
-            0x3b => Opcodes::DCX_SP,
-            0x3c => Opcodes::INR_A,
-            0x3d => Opcodes::DCR_A,
-            0x3e => Opcodes::MVI_A,
-            0x3f => Opcodes::CMC,
-            0x40 => Opcodes::MOV_B_B,
-            0x41 => Opcodes::MOV_B_C,
-            0x42 => Opcodes::MOV_B_D,
-            0x43 => Opcodes::MOV_B_E,
-            0x44 => Opcodes::MOV_B_H,
-            0x45 => Opcodes::MOV_B_L,
-            0x46 => Opcodes::MOV_B_M,
-            0x47 => Opcodes::MOV_B_A,
-            0x48 => Opcodes::MOV_C_B,
-            0x49 => Opcodes::MOV_C_C,
-            0x4a => Opcodes::MOV_C_D,
-            0x4b => Opcodes::MOV_C_E,
-            0x4c => Opcodes::MOV_C_H,
-            0x4d => Opcodes::MOV_C_L,
-            0x4e => Opcodes::MOV_C_M,
-            0x4f => Opcodes::MOV_C_A,
-            0x50 => Opcodes::MOV_D_B,
-            0x51 => Opcodes::MOV_D_C,
-            0x52 => Opcodes::MOV_D_D,
-            0x53 => Opcodes::MOV_D_E,
-            0x54 => Opcodes::MOV_D_H,
-            0x55 => Opcodes::MOV_D_L,
-            0x56 => Opcodes::MOV_D_M,
-            0x57 => Opcodes::MOV_D_A,
-            0x58 => Opcodes::MOV_E_B,
-            0x59 => Opcodes::MOV_E_C,
-            0x5a => Opcodes::MOV_E_D,
-            0x5b => Opcodes::MOV_E_E,
-            0x5c => Opcodes::MOV_E_H,
-            0x5d => Opcodes::MOV_E_L,
-            0x5e => Opcodes::MOV_E_M,
-            0x5f => Opcodes::MOV_E_A,
-            0x60 => Opcodes::MOV_H_B,
-            0x61 => Opcodes::MOV_H_C,
-            0x62 => Opcodes::MOV_H_D,
-            0x63 => Opcodes::MOV_H_E,
-            0x64 => Opcodes::MOV_H_H,
-            0x65 => Opcodes::MOV_H_L,
-            0x66 => Opcodes::MOV_H_M,
-            0x67 => Opcodes::MOV_H_A,
-            0x68 => Opcodes::MOV_L_B,
-            0x69 => Opcodes::MOV_L_C,
-            0x6a => Opcodes::MOV_L_D,
-            0x6b => Opcodes::MOV_L_E,
-            0x6c => Opcodes::MOV_L_H,
-            0x6d => Opcodes::MOV_L_L,
-            0x6e => Opcodes::MOV_L_M,
-            0x6f => Opcodes::MOV_L_A,
-            0x70 => Opcodes::MOV_M_B,
-            0x71 => Opcodes::MOV_M_C,
-            0x72 => Opcodes::MOV_M_D,
-            0x73 => Opcodes::MOV_M_E,
-            0x74 => Opcodes::MOV_M_H,
-            0x75 => Opcodes::MOV_M_L,
-            // theres a halt here
-            0x77 => Opcodes::MOV_M_A,
-            0x78 => Opcodes::MOV_A_B,
-            0x79 => Opcodes::MOV_A_C,
-            0x7a => Opcodes::MOV_A_D,
-            0x7b => Opcodes::MOV_A_E,
-            0x7c => Opcodes::MOV_A_H,
-            0x7d => Opcodes::MOV_A_L,
-            0x7e => Opcodes::MOV_A_M,
-            0x7f => Opcodes::MOV_A_A,
-
-            0x80 => Opcodes::ADD_B,
-            0x81 => Opcodes::ADD_C,
-            0x82 => Opcodes::ADD_D,
-            0x83 => Opcodes::ADD_E,
-            0x84 => Opcodes::ADD_H,
-            0x85 => Opcodes::ADD_L,
-            0x86 => Opcodes::ADD_M,
-            0x87 => Opcodes::ADD_A,
-
-            0x88 => Opcodes::ADC_B,
-            0x89 => Opcodes::ADC_C,
-            0x8a => Opcodes::ADC_D,
-            0x8b => Opcodes::ADC_E,
-            0x8c => Opcodes::ADC_H,
-            0x8d => Opcodes::ADC_L,
-            0x8e => Opcodes::ADC_M,
-            0x8f => Opcodes::ADC_A,
+            0x00 => Some(Opcodes::NOP),
+            0x01 => Some(Opcodes::LXI_B),
+            0x02 => Some(Opcodes::STAX_B),
+            0x03 => Some(Opcodes::INX_B),
+            0x04 => Some(Opcodes::INR_B),
+            0x05 => Some(Opcodes::DCR_B),
+            0x06 => Some(Opcodes::MVI_B),
+            0x07 => Some(Opcodes::RLC),
+            0x09 => Some(Opcodes::DAD_B),
+            0x0a => Some(Opcodes::LDAX_B),
+            0x0b => Some(Opcodes::DCX_B),
+            0x0c => Some(Opcodes::INR_C),
+            0x0d => Some(Opcodes::DCR_C),
+            0x0e => Some(Opcodes::MVI_C),
+            0x0f => Some(Opcodes::RRC),
+            0x11 => Some(Opcodes::LXI_D),
+            0x12 => Some(Opcodes::STAX_D),
+            0x13 => Some(Opcodes::INX_D),
+            0x14 => Some(Opcodes::INR_D),
+            0x15 => Some(Opcodes::DCR_D),
+            0x16 => Some(Opcodes::MVI_D),
+            0x17 => Some(Opcodes::RAL),
+            0x19 => Some(Opcodes::DAD_D),
+            0x1a => Some(Opcodes::LDAX_D),
+            0x1b => Some(Opcodes::DCX_D),
+            0x1c => Some(Opcodes::INR_E),
+            0x1d => Some(Opcodes::DCR_E),
+            0x1e => Some(Opcodes::MVI_E),
+            0x1f => Some(Opcodes::RAR),
+            0x21 => Some(Opcodes::LXI_H),
+            0x22 => Some(Opcodes::SHLD),
+            0x23 => Some(Opcodes::INX_H),
+            0x24 => Some(Opcodes::INR_H),
+            0x25 => Some(Opcodes::DCR_H),
+            0x26 => Some(Opcodes::MVI_H),
+            0x27 => Some(Opcodes::DAA),
+            0x29 => Some(Opcodes::DAD_H),
+            0x2a => Some(Opcodes::LHLD),
+            0x2b => Some(Opcodes::DCX_H),
+            0x2c => Some(Opcodes::INR_L),
+            0x2d => Some(Opcodes::DCR_L),
+            0x2e => Some(Opcodes::MVI_L),
+            0x2f => Some(Opcodes::CMA),
+            0x31 => Some(Opcodes::LXI_SP),
+            0x32 => Some(Opcodes::STA),
+            0x33 => Some(Opcodes::INX_SP),
+            0x34 => Some(Opcodes::INR_M),
+            0x35 => Some(Opcodes::DCR_M),
+            0x36 => Some(Opcodes::MVI_M),
+            0x37 => Some(Opcodes::STC),
+            0x39 => Some(Opcodes::DAD_SP),
+            0x3a => Some(Opcodes::LDA),
+            0x3b => Some(Opcodes::DCX_SP),
+            0x3c => Some(Opcodes::INR_A),
+            0x3d => Some(Opcodes::DCR_A),
+            0x3e => Some(Opcodes::MVI_A),
+            0x3f => Some(Opcodes::CMC),
+            0x40 => Some(Opcodes::MOV_B_B),
+            0x41 => Some(Opcodes::MOV_B_C),
+            0x42 => Some(Opcodes::MOV_B_D),
+            0x43 => Some(Opcodes::MOV_B_E),
+            0x44 => Some(Opcodes::MOV_B_H),
+            0x45 => Some(Opcodes::MOV_B_L),
+            0x46 => Some(Opcodes::MOV_B_M),
+            0x47 => Some(Opcodes::MOV_B_A),
+            0x48 => Some(Opcodes::MOV_C_B),
+            0x49 => Some(Opcodes::MOV_C_C),
+            0x4a => Some(Opcodes::MOV_C_D),
+            0x4b => Some(Opcodes::MOV_C_E),
+            0x4c => Some(Opcodes::MOV_C_H),
+            0x4d => Some(Opcodes::MOV_C_L),
+            0x4e => Some(Opcodes::MOV_C_M),
+            0x4f => Some(Opcodes::MOV_C_A),
+            0x50 => Some(Opcodes::MOV_D_B),
+            0x51 => Some(Opcodes::MOV_D_C),
+            0x52 => Some(Opcodes::MOV_D_D),
+            0x53 => Some(Opcodes::MOV_D_E),
+            0x54 => Some(Opcodes::MOV_D_H),
+            0x55 => Some(Opcodes::MOV_D_L),
+            0x56 => Some(Opcodes::MOV_D_M),
+            0x57 => Some(Opcodes::MOV_D_A),
+            0x58 => Some(Opcodes::MOV_E_B),
+            0x59 => Some(Opcodes::MOV_E_C),
+            0x5a => Some(Opcodes::MOV_E_D),
+            0x5b => Some(Opcodes::MOV_E_E),
+            0x5c => Some(Opcodes::MOV_E_H),
+            0x5d => Some(Opcodes::MOV_E_L),
+            0x5e => Some(Opcodes::MOV_E_M),
+            0x5f => Some(Opcodes::MOV_E_A),
+            0x60 => Some(Opcodes::MOV_H_B),
+            0x61 => Some(Opcodes::MOV_H_C),
+            0x62 => Some(Opcodes::MOV_H_D),
+            0x63 => Some(Opcodes::MOV_H_E),
+            0x64 => Some(Opcodes::MOV_H_H),
+            0x65 => Some(Opcodes::MOV_H_L),
+            0x66 => Some(Opcodes::MOV_H_M),
+            0x67 => Some(Opcodes::MOV_H_A),
+            0x68 => Some(Opcodes::MOV_L_B),
+            0x69 => Some(Opcodes::MOV_L_C),
+            0x6a => Some(Opcodes::MOV_L_D),
+            0x6b => Some(Opcodes::MOV_L_E),
+            0x6c => Some(Opcodes::MOV_L_H),
+            0x6d => Some(Opcodes::MOV_L_L),
+            0x6e => Some(Opcodes::MOV_L_M),
+            0x6f => Some(Opcodes::MOV_L_A),
+            0x70 => Some(Opcodes::MOV_M_B),
+            0x71 => Some(Opcodes::MOV_M_C),
+            0x72 => Some(Opcodes::MOV_M_D),
+            0x73 => Some(Opcodes::MOV_M_E),
+            0x74 => Some(Opcodes::MOV_M_H),
+            0x75 => Some(Opcodes::MOV_M_L),
+            0x76 => Some(Opcodes::HLT),
+            0x77 => Some(Opcodes::MOV_M_A),
+            0x78 => Some(Opcodes::MOV_A_B),
+            0x79 => Some(Opcodes::MOV_A_C),
+            0x7a => Some(Opcodes::MOV_A_D),
+            0x7b => Some(Opcodes::MOV_A_E),
+            0x7c => Some(Opcodes::MOV_A_H),
+            0x7d => Some(Opcodes::MOV_A_L),
+            0x7e => Some(Opcodes::MOV_A_M),
+            0x7f => Some(Opcodes::MOV_A_A),
+
+            0x80 => Some(Opcodes::ADD_B),
+            0x81 => Some(Opcodes::ADD_C),
+            0x82 => Some(Opcodes::ADD_D),
+            0x83 => Some(Opcodes::ADD_E),
+            0x84 => Some(Opcodes::ADD_H),
+            0x85 => Some(Opcodes::ADD_L),
+            0x86 => Some(Opcodes::ADD_M),
+            0x87 => Some(Opcodes::ADD_A),
+
+            0x88 => Some(Opcodes::ADC_B),
+            0x89 => Some(Opcodes::ADC_C),
+            0x8a => Some(Opcodes::ADC_D),
+            0x8b => Some(Opcodes::ADC_E),
+            0x8c => Some(Opcodes::ADC_H),
+            0x8d => Some(Opcodes::ADC_L),
+            0x8e => Some(Opcodes::ADC_M),
+            0x8f => Some(Opcodes::ADC_A),
             
-            0x90 => Opcodes::SUB_B,
-            0x91 => Opcodes::SUB_C,
-            0x92 => Opcodes::SUB_D,
-            0x93 => Opcodes::SUB_E,
-            0x94 => Opcodes::SUB_H,
-            0x95 => Opcodes::SUB_L,
-            0x96 => Opcodes::SUB_M,
-            0x97 => Opcodes::SUB_A,
-
-            0x98 => Opcodes::SBB_B,
-            0x99 => Opcodes::SBB_C,
-            0x9a => Opcodes::SBB_D,
-            0x9b => Opcodes::SBB_E,
-            0x9c => Opcodes::SBB_H,
-            0x9d => Opcodes::SBB_L,
-            0x9e => Opcodes::SBB_M,
-            0x9f => Opcodes::SBB_A,
+            0x90 => Some(Opcodes::SUB_B),
+            0x91 => Some(Opcodes::SUB_C),
+            0x92 => Some(Opcodes::SUB_D),
+            0x93 => Some(Opcodes::SUB_E),
+            0x94 => Some(Opcodes::SUB_H),
+            0x95 => Some(Opcodes::SUB_L),
+            0x96 => Some(Opcodes::SUB_M),
+            0x97 => Some(Opcodes::SUB_A),
+
+            0x98 => Some(Opcodes::SBB_B),
+            0x99 => Some(Opcodes::SBB_C),
+            0x9a => Some(Opcodes::SBB_D),
+            0x9b => Some(Opcodes::SBB_E),
+            0x9c => Some(Opcodes::SBB_H),
+            0x9d => Some(Opcodes::SBB_L),
+            0x9e => Some(Opcodes::SBB_M),
+            0x9f => Some(Opcodes::SBB_A),
     
-            0xa0 => Opcodes::ANA_B,
-            0xa1 => Opcodes::ANA_C,
-            0xa2 => Opcodes::ANA_D,
-            0xa3 => Opcodes::ANA_E,
-            0xa4 => Opcodes::ANA_H,
-            0xa5 => Opcodes::ANA_L,
-            0xa6 => Opcodes::ANA_M,
-            0xa7 => Opcodes::ANA_A,
-            0xa8 => Opcodes::XRA_B,
-            0xa9 => Opcodes::XRA_C,
-            0xaa => Opcodes::XRA_D,
-            0xab => Opcodes::XRA_E,
-            0xac => Opcodes::XRA_H,
-            0xad => Opcodes::XRA_L,
-            0xae => Opcodes::XRA_M,
-            0xaf => Opcodes::XRA_A,
-            0xb0 => Opcodes::ORA_B,
-            0xb1 => Opcodes::ORA_C,
-            0xb2 => Opcodes::ORA_D,
-            0xb3 => Opcodes::ORA_E,
-            0xb4 => Opcodes::ORA_H,
-            0xb5 => Opcodes::ORA_L,
-            0xb6 => Opcodes::ORA_M,
-            0xb7 => Opcodes::ORA_A,
-
-            0xb8 => Opcodes::CMP_B,
-            0xb9 => Opcodes::CMP_C,
-            0xba => Opcodes::CMP_D,
-            0xbb => Opcodes::CMP_E,
-            0xbc => Opcodes::CMP_H,
-            0xbd => Opcodes::CMP_L,
-            0xbe => Opcodes::CMP_M,
-            0xbf => Opcodes::CMP_A,
-            0xc0 => Opcodes::RNZ,
-            0xc1 => Opcodes::POP_B,
-            0xc2 => Opcodes::JNZ, 
-            0xc3 => Opcodes::JMP,
-            0xc4 => Opcodes::CNZ,
-            0xc5 => Opcodes::PUSH_B,
-            0xc7 => Opcodes::RST_0, 
-            0xc8 => Opcodes::RZ,
-            0xc9 => Opcodes::RET,
-            0xca => Opcodes::JZ, 
-            0xcc => Opcodes::CZ,
-            0xcd => Opcodes::CALL,
-            0xce => Opcodes::ACI,
-            0xcf => Opcodes::RST_1,     
-            0xd0 => Opcodes::RNC,   
-            0xd1 => Opcodes::POP_D,       
-            0xd2 => Opcodes::JNC, 
-            0xd4 => Opcodes::CNC,
-            0xd5 => Opcodes::PUSH_D,
-            0xd6 => Opcodes::SUI,
-            0xd7 => Opcodes::RST_2, 
-            0xd8 => Opcodes::RC,                      
-            0xda => Opcodes::JC,  
-            0xdc => Opcodes::CC,
-            0xdf => Opcodes::RST_3,                    
-            0xe0 => Opcodes::RPO,
-            0xe1 => Opcodes::POP_H,
-            0xe2 => Opcodes::JPO, 
-            0xe3 => Opcodes::XTHL,
-            0xe4 => Opcodes::CPO,
-            0xe5 => Opcodes::PUSH_H,
-            0xe6 => Opcodes::ANI,
-            0xe7 => Opcodes::RST_4, 
-            0xe8 => Opcodes::RPE,
-
-            0xe9 => Opcodes::PCHL,
-            0xea => Opcodes::JPE, 
-            0xeb => Opcodes::XCHG,
-            0xec => Opcodes::CPE,
-            0xee => Opcodes::XRI,
-            0xef => Opcodes::RST_5,  
-            0xf0 => Opcodes::RP,                 
-            0xf1 => Opcodes::POP_PSW,
-            0xf2 => Opcodes::JP, 
-            0xf4 => Opcodes::CP,
-            0xf5 => Opcodes::PUSH_PSW,
-            0xf6 => Opcodes::ORI,
-            0xf7 => Opcodes::RST_6, 
-            0xf8 => Opcodes::RM,
-            0xf9 => Opcodes::SPHL,
-            0xfa => Opcodes::JM,
-            0xfc => Opcodes::CM,
-            0xfe => Opcodes::CPI,
-            0xff => Opcodes::RST_7, 
-            _ => panic!("[from_hex]: Unknown opcode with hex origin: 0x{:02X}", opcode),
+            0xa0 => Some(Opcodes::ANA_B),
+            0xa1 => Some(Opcodes::ANA_C),
+            0xa2 => Some(Opcodes::ANA_D),
+            0xa3 => Some(Opcodes::ANA_E),
+            0xa4 => Some(Opcodes::ANA_H),
+            0xa5 => Some(Opcodes::ANA_L),
+            0xa6 => Some(Opcodes::ANA_M),
+            0xa7 => Some(Opcodes::ANA_A),
+            0xa8 => Some(Opcodes::XRA_B),
+            0xa9 => Some(Opcodes::XRA_C),
+            0xaa => Some(Opcodes::XRA_D),
+            0xab => Some(Opcodes::XRA_E),
+            0xac => Some(Opcodes::XRA_H),
+            0xad => Some(Opcodes::XRA_L),
+            0xae => Some(Opcodes::XRA_M),
+            0xaf => Some(Opcodes::XRA_A),
+            0xb0 => Some(Opcodes::ORA_B),
+            0xb1 => Some(Opcodes::ORA_C),
+            0xb2 => Some(Opcodes::ORA_D),
+            0xb3 => Some(Opcodes::ORA_E),
+            0xb4 => Some(Opcodes::ORA_H),
+            0xb5 => Some(Opcodes::ORA_L),
+            0xb6 => Some(Opcodes::ORA_M),
+            0xb7 => Some(Opcodes::ORA_A),
+
+            0xb8 => Some(Opcodes::CMP_B),
+            0xb9 => Some(Opcodes::CMP_C),
+            0xba => Some(Opcodes::CMP_D),
+            0xbb => Some(Opcodes::CMP_E),
+            0xbc => Some(Opcodes::CMP_H),
+            0xbd => Some(Opcodes::CMP_L),
+            0xbe => Some(Opcodes::CMP_M),
+            0xbf => Some(Opcodes::CMP_A),
+            0xc0 => Some(Opcodes::RNZ),
+            0xc1 => Some(Opcodes::POP_B),
+            0xc2 => Some(Opcodes::JNZ), 
+            0xc3 => Some(Opcodes::JMP),
+            0xc4 => Some(Opcodes::CNZ),
+            0xc5 => Some(Opcodes::PUSH_B),
+            0xc6 => Some(Opcodes::ADI),
+            0xc7 => Some(Opcodes::RST_0), 
+            0xc8 => Some(Opcodes::RZ),
+            0xc9 => Some(Opcodes::RET),
+            0xca => Some(Opcodes::JZ), 
+            0xcc => Some(Opcodes::CZ),
+            0xcd => Some(Opcodes::CALL),
+            0xce => Some(Opcodes::ACI),
+            0xcf => Some(Opcodes::RST_1),     
+            0xd0 => Some(Opcodes::RNC),   
+            0xd1 => Some(Opcodes::POP_D),       
+            0xd2 => Some(Opcodes::JNC), 
+            0xd3 => Some(Opcodes::OUT),
+            0xd4 => Some(Opcodes::CNC),
+            0xd5 => Some(Opcodes::PUSH_D),
+            0xd6 => Some(Opcodes::SUI),
+            0xd7 => Some(Opcodes::RST_2), 
+            0xd8 => Some(Opcodes::RC),                      
+            0xda => Some(Opcodes::JC),  
+            0xdb => Some(Opcodes::IN),
+            0xdc => Some(Opcodes::CC),
+            0xde => Some(Opcodes::SBI),
+            0xdf => Some(Opcodes::RST_3),                    
+            0xe0 => Some(Opcodes::RPO),
+            0xe1 => Some(Opcodes::POP_H),
+            0xe2 => Some(Opcodes::JPO), 
+            0xe3 => Some(Opcodes::XTHL),
+            0xe4 => Some(Opcodes::CPO),
+            0xe5 => Some(Opcodes::PUSH_H),
+            0xe6 => Some(Opcodes::ANI),
+            0xe7 => Some(Opcodes::RST_4), 
+            0xe8 => Some(Opcodes::RPE),
+
+            0xe9 => Some(Opcodes::PCHL),
+            0xea => Some(Opcodes::JPE), 
+            0xeb => Some(Opcodes::XCHG),
+            0xec => Some(Opcodes::CPE),
+            0xee => Some(Opcodes::XRI),
+            0xef => Some(Opcodes::RST_5),  
+            0xf0 => Some(Opcodes::RP),                 
+            0xf1 => Some(Opcodes::POP_PSW),
+            0xf2 => Some(Opcodes::JP), 
+            0xf4 => Some(Opcodes::CP),
+            0xf5 => Some(Opcodes::PUSH_PSW),
+            0xf3 => Some(Opcodes::DI),
+            0xf6 => Some(Opcodes::ORI),
+            0xf7 => Some(Opcodes::RST_6), 
+            0xf8 => Some(Opcodes::RM),
+            0xf9 => Some(Opcodes::SPHL),
+            0xfa => Some(Opcodes::JM),
+            0xfb => Some(Opcodes::EI),
+            0xfc => Some(Opcodes::CM),
+            0xfe => Some(Opcodes::CPI),
+            0xff => Some(Opcodes::RST_7), 
+            _ => None,
         }
     }
 
-    // pub fn get_instruction_def(&self) -> InstructionDef {
-    //     match self {
-    //         //LXI
-    //         Opcodes::LXI_B | Opcodes::LXI_D | Opcodes::LXI_H | Opcodes::LXI_SP => InstructionDef { cycles: 10, size: 3 },
-    //         Opcodes::NOP => InstructionDef { cycles : 4, size : 1 },
-    //         Opcodes::STAX_B => InstructionDef { cycles : 7, size : 1 },
-    //           Opcodes::MOV_A_A | Opcodes::MOV_A_B | Opcodes::MOV_A_C | Opcodes::MOV_A_D | Opcodes::MOV_A_E | Opcodes::MOV_A_H | Opcodes::MOV_A_L | Opcodes::MOV_A_M 
-    //         | Opcodes::MOV_B_A | Opcodes::MOV_B_B | Opcodes::MOV_B_C | Opcodes::MOV_B_D | Opcodes::MOV_B_E | Opcodes::MOV_B_H | Opcodes::MOV_B_L | Opcodes::MOV_B_M
-    //         | Opcodes::MOV_C_A | Opcodes::MOV_C_B | Opcodes::MOV_C_C | Opcodes::MOV_C_D | Opcodes::MOV_C_E | Opcodes::MOV_C_H | Opcodes::MOV_C_L | Opcodes::MOV_C_M
-    //         | Opcodes::MOV_D_A | Opcodes::MOV_D_B | Opcodes::MOV_D_C | Opcodes::MOV_D_D | Opcodes::MOV_D_E | Opcodes::MOV_D_H | Opcodes::MOV_D_L | Opcodes::MOV_D_M
-    //         | Opcodes::MOV_E_A | Opcodes::MOV_E_B | Opcodes::MOV_E_C | Opcodes::MOV_E_D | Opcodes::MOV_E_E | Opcodes::MOV_E_H | Opcodes::MOV_E_L | Opcodes::MOV_E_M
-    //         | Opcodes::MOV_H_A | Opcodes::MOV_H_B | Opcodes::MOV_H_C | Opcodes::MOV_H_D | Opcodes::MOV_H_E | Opcodes::MOV_H_H | Opcodes::MOV_H_L | Opcodes::MOV_H_M
-    //         | Opcodes::MOV_L_A | Opcodes::MOV_L_B | Opcodes::MOV_L_C | Opcodes::MOV_L_D | Opcodes::MOV_L_E | Opcodes::MOV_L_H | Opcodes::MOV_L_L | Opcodes::MOV_L_M
-    //         | Opcodes::MOV_M_A | Opcodes::MOV_M_B | Opcodes::MOV_M_C | Opcodes::MOV_M_D | Opcodes::MOV_M_E | Opcodes::MOV_M_H | Opcodes::MOV_M_L 
-    //         => InstructionDef { cycles : 5, size : 1 },
-    //         Opcodes::INX_B | Opcodes::INX_D | Opcodes::INX_H => InstructionDef { cycles : 5, size : 1 },
-
-    //         _ => panic!("[get_instruction_def]: Instruction not defined for opcode of type: {:?}", self),
-    //     }
-    // }
-
     pub fn get_instruction_def(&self) -> InstructionDef {
         match self {
             // NOP
@@ -546,16 +542,19 @@ impl Opcodes {
             Opcodes::INX_B | Opcodes::INX_D | Opcodes::INX_H | Opcodes::INX_SP => InstructionDef { cycles: 5, size: 1 },
     
             // INR
-            Opcodes::INR_A | Opcodes::INR_B | Opcodes::INR_C | Opcodes::INR_D | Opcodes::INR_E | Opcodes::INR_H | Opcodes::INR_L | Opcodes::INR_M => 
+            Opcodes::INR_A | Opcodes::INR_B | Opcodes::INR_C | Opcodes::INR_D | Opcodes::INR_E | Opcodes::INR_H | Opcodes::INR_L => 
                 InstructionDef { cycles: 5, size: 1 },
+            Opcodes::INR_M => InstructionDef { cycles: 10, size: 1 },
     
             // DCR
-            Opcodes::DCR_A | Opcodes::DCR_B | Opcodes::DCR_C | Opcodes::DCR_D | Opcodes::DCR_E | Opcodes::DCR_H | Opcodes::DCR_L | Opcodes::DCR_M => 
+            Opcodes::DCR_A | Opcodes::DCR_B | Opcodes::DCR_C | Opcodes::DCR_D | Opcodes::DCR_E | Opcodes::DCR_H | Opcodes::DCR_L => 
                 InstructionDef { cycles: 5, size: 1 },
+            Opcodes::DCR_M => InstructionDef { cycles: 10, size: 1 },
     
             // MVI
-            Opcodes::MVI_A | Opcodes::MVI_B | Opcodes::MVI_C | Opcodes::MVI_D | Opcodes::MVI_E | Opcodes::MVI_H | Opcodes::MVI_L | Opcodes::MVI_M => 
+            Opcodes::MVI_A | Opcodes::MVI_B | Opcodes::MVI_C | Opcodes::MVI_D | Opcodes::MVI_E | Opcodes::MVI_H | Opcodes::MVI_L => 
                 InstructionDef { cycles: 7, size: 2 },
+            Opcodes::MVI_M => InstructionDef { cycles: 10, size: 2 },
     
             // RLC, RRC, RAL, RAR
             Opcodes::RLC | Opcodes::RRC | Opcodes::RAL | Opcodes::RAR => InstructionDef { cycles: 4, size: 1 },
@@ -565,64 +564,86 @@ impl Opcodes {
     
             // LDAX
             Opcodes::LDAX_B | Opcodes::LDAX_D => InstructionDef { cycles: 7, size: 1 },
+
+            // LDA, STA
+            Opcodes::LDA | Opcodes::STA => InstructionDef { cycles: 13, size: 3 },
+
+            // LHLD, SHLD
+            Opcodes::LHLD | Opcodes::SHLD => InstructionDef { cycles: 16, size: 3 },
     
             // DCX
             Opcodes::DCX_B | Opcodes::DCX_D | Opcodes::DCX_H | Opcodes::DCX_SP => InstructionDef { cycles: 5, size: 1 },
     
             // MOV
-            Opcodes::MOV_A_A | Opcodes::MOV_A_B | Opcodes::MOV_A_C | Opcodes::MOV_A_D | Opcodes::MOV_A_E | Opcodes::MOV_A_H | Opcodes::MOV_A_L | Opcodes::MOV_A_M |
-            Opcodes::MOV_B_A | Opcodes::MOV_B_B | Opcodes::MOV_B_C | Opcodes::MOV_B_D | Opcodes::MOV_B_E | Opcodes::MOV_B_H | Opcodes::MOV_B_L | Opcodes::MOV_B_M |
-            Opcodes::MOV_C_A | Opcodes::MOV_C_B | Opcodes::MOV_C_C | Opcodes::MOV_C_D | Opcodes::MOV_C_E | Opcodes::MOV_C_H | Opcodes::MOV_C_L | Opcodes::MOV_C_M |
-            Opcodes::MOV_D_A | Opcodes::MOV_D_B | Opcodes::MOV_D_C | Opcodes::MOV_D_D | Opcodes::MOV_D_E | Opcodes::MOV_D_H | Opcodes::MOV_D_L | Opcodes::MOV_D_M |
-            Opcodes::MOV_E_A | Opcodes::MOV_E_B | Opcodes::MOV_E_C | Opcodes::MOV_E_D | Opcodes::MOV_E_E | Opcodes::MOV_E_H | Opcodes::MOV_E_L | Opcodes::MOV_E_M |
-            Opcodes::MOV_H_A | Opcodes::MOV_H_B | Opcodes::MOV_H_C | Opcodes::MOV_H_D | Opcodes::MOV_H_E | Opcodes::MOV_H_H | Opcodes::MOV_H_L | Opcodes::MOV_H_M |
-            Opcodes::MOV_L_A | Opcodes::MOV_L_B | Opcodes::MOV_L_C | Opcodes::MOV_L_D | Opcodes::MOV_L_E | Opcodes::MOV_L_H | Opcodes::MOV_L_L | Opcodes::MOV_L_M |
+            Opcodes::MOV_A_A | Opcodes::MOV_A_B | Opcodes::MOV_A_C | Opcodes::MOV_A_D | Opcodes::MOV_A_E | Opcodes::MOV_A_H | Opcodes::MOV_A_L |
+            Opcodes::MOV_B_A | Opcodes::MOV_B_B | Opcodes::MOV_B_C | Opcodes::MOV_B_D | Opcodes::MOV_B_E | Opcodes::MOV_B_H | Opcodes::MOV_B_L |
+            Opcodes::MOV_C_A | Opcodes::MOV_C_B | Opcodes::MOV_C_C | Opcodes::MOV_C_D | Opcodes::MOV_C_E | Opcodes::MOV_C_H | Opcodes::MOV_C_L |
+            Opcodes::MOV_D_A | Opcodes::MOV_D_B | Opcodes::MOV_D_C | Opcodes::MOV_D_D | Opcodes::MOV_D_E | Opcodes::MOV_D_H | Opcodes::MOV_D_L |
+            Opcodes::MOV_E_A | Opcodes::MOV_E_B | Opcodes::MOV_E_C | Opcodes::MOV_E_D | Opcodes::MOV_E_E | Opcodes::MOV_E_H | Opcodes::MOV_E_L |
+            Opcodes::MOV_H_A | Opcodes::MOV_H_B | Opcodes::MOV_H_C | Opcodes::MOV_H_D | Opcodes::MOV_H_E | Opcodes::MOV_H_H | Opcodes::MOV_H_L |
+            Opcodes::MOV_L_A | Opcodes::MOV_L_B | Opcodes::MOV_L_C | Opcodes::MOV_L_D | Opcodes::MOV_L_E | Opcodes::MOV_L_H | Opcodes::MOV_L_L => 
+                InstructionDef { cycles: 5, size: 1 },
+            Opcodes::MOV_A_M | Opcodes::MOV_B_M | Opcodes::MOV_C_M | Opcodes::MOV_D_M | Opcodes::MOV_E_M | Opcodes::MOV_H_M | Opcodes::MOV_L_M |
             Opcodes::MOV_M_A | Opcodes::MOV_M_B | Opcodes::MOV_M_C | Opcodes::MOV_M_D | Opcodes::MOV_M_E | Opcodes::MOV_M_H | Opcodes::MOV_M_L => 
                 InstructionDef { cycles: 7, size: 1 },
     
             // ADD
-            Opcodes::ADD_A | Opcodes::ADD_B | Opcodes::ADD_C | Opcodes::ADD_D | Opcodes::ADD_E | Opcodes::ADD_H | Opcodes::ADD_L | Opcodes::ADD_M => 
+            Opcodes::ADD_A | Opcodes::ADD_B | Opcodes::ADD_C | Opcodes::ADD_D | Opcodes::ADD_E | Opcodes::ADD_H | Opcodes::ADD_L => 
                 InstructionDef { cycles: 4, size: 1 },
+            Opcodes::ADD_M => InstructionDef { cycles: 7, size: 1 },
     
             // ADC
-            Opcodes::ADC_A | Opcodes::ADC_B | Opcodes::ADC_C | Opcodes::ADC_D | Opcodes::ADC_E | Opcodes::ADC_H | Opcodes::ADC_L | Opcodes::ADC_M => 
+            Opcodes::ADC_A | Opcodes::ADC_B | Opcodes::ADC_C | Opcodes::ADC_D | Opcodes::ADC_E | Opcodes::ADC_H | Opcodes::ADC_L => 
                 InstructionDef { cycles: 4, size: 1 },
+            Opcodes::ADC_M => InstructionDef { cycles: 7, size: 1 },
     
             // SUB
-            Opcodes::SUB_A | Opcodes::SUB_B | Opcodes::SUB_C | Opcodes::SUB_D | Opcodes::SUB_E | Opcodes::SUB_H | Opcodes::SUB_L | Opcodes::SUB_M => 
+            Opcodes::SUB_A | Opcodes::SUB_B | Opcodes::SUB_C | Opcodes::SUB_D | Opcodes::SUB_E | Opcodes::SUB_H | Opcodes::SUB_L => 
                 InstructionDef { cycles: 4, size: 1 },
+            Opcodes::SUB_M => InstructionDef { cycles: 7, size: 1 },
     
             // SBB
-            Opcodes::SBB_A | Opcodes::SBB_B | Opcodes::SBB_C | Opcodes::SBB_D | Opcodes::SBB_E | Opcodes::SBB_H | Opcodes::SBB_L | Opcodes::SBB_M => 
+            Opcodes::SBB_A | Opcodes::SBB_B | Opcodes::SBB_C | Opcodes::SBB_D | Opcodes::SBB_E | Opcodes::SBB_H | Opcodes::SBB_L => 
                 InstructionDef { cycles: 4, size: 1 },
+            Opcodes::SBB_M => InstructionDef { cycles: 7, size: 1 },
     
             // ANA
-            Opcodes::ANA_A | Opcodes::ANA_B | Opcodes::ANA_C | Opcodes::ANA_D | Opcodes::ANA_E | Opcodes::ANA_H | Opcodes::ANA_L | Opcodes::ANA_M => 
+            Opcodes::ANA_A | Opcodes::ANA_B | Opcodes::ANA_C | Opcodes::ANA_D | Opcodes::ANA_E | Opcodes::ANA_H | Opcodes::ANA_L => 
                 InstructionDef { cycles: 4, size: 1 },
+            Opcodes::ANA_M => InstructionDef { cycles: 7, size: 1 },
     
             // XRA
-            Opcodes::XRA_A | Opcodes::XRA_B | Opcodes::XRA_C | Opcodes::XRA_D | Opcodes::XRA_E | Opcodes::XRA_H | Opcodes::XRA_L | Opcodes::XRA_M => 
+            Opcodes::XRA_A | Opcodes::XRA_B | Opcodes::XRA_C | Opcodes::XRA_D | Opcodes::XRA_E | Opcodes::XRA_H | Opcodes::XRA_L => 
                 InstructionDef { cycles: 4, size: 1 },
+            Opcodes::XRA_M => InstructionDef { cycles: 7, size: 1 },
     
             // ORA
-            Opcodes::ORA_A | Opcodes::ORA_B | Opcodes::ORA_C | Opcodes::ORA_D | Opcodes::ORA_E | Opcodes::ORA_H | Opcodes::ORA_L | Opcodes::ORA_M => 
+            Opcodes::ORA_A | Opcodes::ORA_B | Opcodes::ORA_C | Opcodes::ORA_D | Opcodes::ORA_E | Opcodes::ORA_H | Opcodes::ORA_L => 
                 InstructionDef { cycles: 4, size: 1 },
+            Opcodes::ORA_M => InstructionDef { cycles: 7, size: 1 },
+    
+            // ACI, SUI, ANI, XRI, ORI, CPI
+            Opcodes::ADI | Opcodes::ACI | Opcodes::SUI | Opcodes::SBI | Opcodes::ANI | Opcodes::XRI | Opcodes::ORI | Opcodes::CPI => 
+                InstructionDef { cycles: 7, size: 2 },
     
             // CMP
-            Opcodes::CMP_A | Opcodes::CMP_B | Opcodes::CMP_C | Opcodes::CMP_D | Opcodes::CMP_E | Opcodes::CMP_H | Opcodes::CMP_L | Opcodes::CMP_M => 
+            Opcodes::CMP_A | Opcodes::CMP_B | Opcodes::CMP_C | Opcodes::CMP_D | Opcodes::CMP_E | Opcodes::CMP_H | Opcodes::CMP_L => 
                 InstructionDef { cycles: 4, size: 1 },
+            Opcodes::CMP_M => InstructionDef { cycles: 7, size: 1 },
     
             // JMP
             Opcodes::JMP | Opcodes::JC | Opcodes::JNC | Opcodes::JZ | Opcodes::JNZ | Opcodes::JM | Opcodes::JP | Opcodes::JPE | Opcodes::JPO => 
                 InstructionDef { cycles: 10, size: 3 },
     
-            // CALL
-            Opcodes::CALL | Opcodes::CC | Opcodes::CNC | Opcodes::CZ | Opcodes::CNZ | Opcodes::CM | Opcodes::CP | Opcodes::CPE | Opcodes::CPO => 
-                InstructionDef { cycles: 17, size: 3 },
+            // CALL; a conditional call taken costs 6 more
+            Opcodes::CALL => InstructionDef { cycles: 17, size: 3 },
+            Opcodes::CC | Opcodes::CNC | Opcodes::CZ | Opcodes::CNZ | Opcodes::CM | Opcodes::CP | Opcodes::CPE | Opcodes::CPO => 
+                InstructionDef { cycles: 11, size: 3 },
     
-            // RET
-            Opcodes::RET | Opcodes::RC | Opcodes::RNC | Opcodes::RZ | Opcodes::RNZ | Opcodes::RM | Opcodes::RP | Opcodes::RPE | Opcodes::RPO => 
-                InstructionDef { cycles: 10, size: 1 },
+            // RET; a conditional return taken costs 6 more
+            Opcodes::RET => InstructionDef { cycles: 10, size: 1 },
+            Opcodes::RC | Opcodes::RNC | Opcodes::RZ | Opcodes::RNZ | Opcodes::RM | Opcodes::RP | Opcodes::RPE | Opcodes::RPO => 
+                InstructionDef { cycles: 5, size: 1 },
     
             // RST
             Opcodes::RST_0 | Opcodes::RST_1 | Opcodes::RST_2 | Opcodes::RST_3 | Opcodes::RST_4 | Opcodes::RST_5 | Opcodes::RST_6 | Opcodes::RST_7 => 
@@ -647,13 +668,16 @@ impl Opcodes {
             Opcodes::SPHL => InstructionDef { cycles: 5, size: 1 },
     
             // EI
-            // Opcodes::EI => InstructionDef { cycles: 4, size: 1 },
+            Opcodes::EI => InstructionDef { cycles: 4, size: 1 },
     
             // DI
-            // Opcodes::DI => InstructionDef { cycles: 4, size: 1 },
+            Opcodes::DI => InstructionDef { cycles: 4, size: 1 },
     
             // HLT
-            // Opcodes::HLT => InstructionDef { cycles: 7, size: 1 },
+            Opcodes::HLT => InstructionDef { cycles: 7, size: 1 },
+    
+            // IN, OUT
+            Opcodes::IN | Opcodes::OUT => InstructionDef { cycles: 10, size: 2 },
     
             // CMA
             Opcodes::CMA => InstructionDef { cycles: 4, size: 1 },
@@ -666,29 +690,18 @@ impl Opcodes {
     
             // DAA
             Opcodes::DAA => InstructionDef { cycles: 4, size: 1 },
-        
-            _ => panic!("[get_instruction_def]: Instruction not defined for opcode of type: {:?}", self),
         }
     }
 }
 
 
-pub fn throw_unimplemented_instruction_error(state8080: &mut Cpu) {
-    panic!(
-        "Error: Unimplemented instruction: {:02x} at {}",
-        state8080.memory[state8080.pc], state8080.pc
-    );
-}
-
-pub fn nop() {
-    return;
-}
+pub fn nop() {}
 
 
 // data transfer 
 pub fn lxi_r(state: &mut Cpu, dest: Registers, operands: [u8; MAX_OPERANDS]) {
     let result = (operands[1] as u16) << 8 | operands[0] as u16;
-    state.set_register_pair(dest.clone(), dest.next(), result);
+    state.set_register_pair(dest, dest.next(), result);
 }
 
 pub fn lxi_sp(state: &mut Cpu, operands: [u8; MAX_OPERANDS]) {
@@ -702,12 +715,12 @@ pub fn mov_r_r(state: &mut Cpu,dest : Registers,  src: Registers) {
 
 pub fn mov_r_m(state: &mut Cpu, dest:Registers){
     let offset = state.get_register_pair(Registers::H, Registers::L);
-    state.registers[dest as usize] = state.memory[offset as usize];
+    state.registers[dest as usize] = state.read_byte(offset);
 }
 
 pub fn mov_m_r(state: &mut Cpu, src: Registers) {
     let offset = state.get_register_pair(Registers::H, Registers::L);
-    state.memory[offset as usize] = state.registers[src as usize];
+    state.write_byte(offset, state.registers[src as usize]);
 }
 
 pub fn mvi_r(state: &mut Cpu, dest: Registers, operand: u8) {
@@ -716,39 +729,39 @@ pub fn mvi_r(state: &mut Cpu, dest: Registers, operand: u8) {
 
 pub fn mvi_m(state: &mut Cpu, operand: u8){
     let offset = state.get_register_pair(Registers::H, Registers::L);
-    state.memory[offset as usize] = operand;
+    state.write_byte(offset, operand);
 }
 
 pub fn lda (state: &mut Cpu, operands: [u8; MAX_OPERANDS]){
     let offset = (operands[1] as u16) << 8 | operands[0] as u16;
-    state.registers[Registers::A as usize] = state.memory[offset as usize];
+    state.registers[Registers::A as usize] = state.read_byte(offset);
 }
 
 pub fn sta(state: &mut Cpu, operands: [u8; MAX_OPERANDS]){
     let offset = (operands[1] as u16) << 8 | operands[0] as u16;
-    state.memory[offset as usize] = state.registers[Registers::A as usize];
+    state.write_byte(offset, state.registers[Registers::A as usize]);
 }
 
 pub fn shld (state: &mut Cpu, operands: [u8; MAX_OPERANDS]){
     let offset = (operands[1] as u16) << 8 | operands[0] as u16;
-    state.memory[offset as usize] = state.registers[Registers::L as usize];
-    state.memory[offset.wrapping_add(1) as usize] = state.registers[Registers::H as usize];
+    state.write_byte(offset, state.registers[Registers::L as usize]);
+    state.write_byte(offset.wrapping_add(1), state.registers[Registers::H as usize]);
 }
 
 pub fn lhld(state: &mut Cpu, operands: [u8; MAX_OPERANDS]) {
-    let offset = operands[1].rotate_left(8) as u16 | operands[0] as u16;
-    state.registers[Registers::L as usize] = state.memory[offset as usize];
-    state.registers[Registers::H as usize] = state.memory[offset.wrapping_add(1) as usize]
+    let offset = u16::from_le_bytes([operands[0], operands[1]]);
+    state.registers[Registers::L as usize] = state.read_byte(offset);
+    state.registers[Registers::H as usize] = state.read_byte(offset.wrapping_add(1))
 }
 
 pub fn ldax(state: &mut Cpu, src: Registers) {
-    let offset = state.get_register_pair(src.clone(), src.next());
-    state.registers[Registers::A as usize] = state.memory[offset as usize];
+    let offset = state.get_register_pair(src, src.next());
+    state.registers[Registers::A as usize] = state.read_byte(offset);
 }
 
 pub fn stax(state: &mut Cpu, dest: Registers) {
-    let offset = state.get_register_pair(dest.clone(), dest.next());
-    state.memory[offset as usize] = state.registers[Registers::A as usize];
+    let offset = state.get_register_pair(dest, dest.next());
+    state.write_byte(offset, state.registers[Registers::A as usize]);
 }
 
 pub fn xchg (state: &mut Cpu) {
@@ -760,120 +773,95 @@ pub fn xchg (state: &mut Cpu) {
 
 // arithmetic
 pub fn add_r(state: &mut Cpu, dest:Registers){
-    let val = state.registers[Registers::A as usize];
-    update_conditions_add(state, val, state.registers[dest.clone() as usize], false);
-    let result = val.wrapping_add(state.registers[dest as usize]);
-    state.registers[Registers::A as usize] = result;
-
+    add_a(state, state.registers[dest as usize], 0);
 }
 
 pub fn add_m(state: &mut Cpu){
     let offset = state.get_register_pair(Registers::H, Registers::L);
-
-    let val = state.registers[Registers::A as usize];
-    let val2 = state.memory[offset as usize];
-    update_conditions_add(state, val, val2, false);
-    let result = val.wrapping_add(val2);
-    state.registers[Registers::A as usize] = result;
+    let val = state.read_byte(offset);
+    add_a(state, val, 0);
 }
 
 pub fn adc_r(state: &mut Cpu, dest:Registers){
-    let val = state.registers[Registers::A as usize];
-    update_conditions_add(state, val, state.registers[dest.clone() as usize], true);
-    let carry = state.cc[&ConditionCodes::CY] as u8;
-    let result = state.registers[dest as usize].wrapping_add(carry);
-    state.registers[Registers::A as usize] = val.wrapping_add(result);
+    let carry = carry(state);
+    add_a(state, state.registers[dest as usize], carry);
 }
 
 pub fn adc_m(state: &mut Cpu){
-    let val = state.registers[Registers::A as usize];
     let offset = state.get_register_pair(Registers::H, Registers::L);
-    let val2 = state.memory[offset as usize];
-    update_conditions_add(state, val, val2, true);
-    let carry = state.cc[&ConditionCodes::CY] as u8;
-    let result = val2.wrapping_add(carry);
-    state.registers[Registers::A as usize] = val.wrapping_add(result);
+    let val = state.read_byte(offset);
+    let carry = carry(state);
+    add_a(state, val, carry);
+}
+
+pub fn adi(state: &mut Cpu, operand: u8){
+    add_a(state, operand, 0);
 }
 
 pub fn aci(state: &mut Cpu, operand: u8){
-    let val = state.registers[Registers::A as usize];
-    update_conditions_add(state, val, operand, true);
-    let carry = state.cc[&ConditionCodes::CY] as u8;
-    let result = operand.wrapping_add(carry);
-    state.registers[Registers::A as usize] = val.wrapping_add(result);
+    let carry = carry(state);
+    add_a(state, operand, carry);
 }
 
 pub fn sub_r(state: &mut Cpu, dest:Registers){
-    let val = state.registers[Registers::A as usize];
-    update_conditions_sub(state, val, state.registers[dest.clone() as usize], false);
-    let result = val.wrapping_sub(state.registers[dest as usize]);
-    state.registers[Registers::A as usize] = result;
+    sub_a(state, state.registers[dest as usize], 0);
 }
 
 pub fn sub_m(state: &mut Cpu){
-    let val = state.registers[Registers::A as usize];
     let offset = state.get_register_pair(Registers::H, Registers::L);
-    let val2 = state.memory[offset as usize];
-    update_conditions_sub(state, val, val2, false);
-    let result = val.wrapping_sub(val2);
-    state.registers[Registers::A as usize] = result;
+    let val = state.read_byte(offset);
+    sub_a(state, val, 0);
 }
 
 pub fn sui(state: &mut Cpu, operand: u8){
-    let val = state.registers[Registers::A as usize];
-    update_conditions_sub(state, val, operand, false);
-    let result = val.wrapping_sub(operand);
-    state.registers[Registers::A as usize] = result;
+    sub_a(state, operand, 0);
+}
+
+pub fn sbi(state: &mut Cpu, operand: u8){
+    let carry = carry(state);
+    sub_a(state, operand, carry);
 }
 
 pub fn sbb_r(state: &mut Cpu, dest:Registers){
-    let val = state.registers[Registers::A as usize];
-    update_conditions_sub(state, val, state.registers[dest.clone() as usize], true);
-    let carry = state.cc[&ConditionCodes::CY] as u8;
-    let result = state.registers[dest as usize].wrapping_add(carry);
-    state.registers[Registers::A as usize] = val.wrapping_sub(result);
+    let carry = carry(state);
+    sub_a(state, state.registers[dest as usize], carry);
 }
 
 pub fn sbb_m(state: &mut Cpu){
-    let val = state.registers[Registers::A as usize];
     let offset = state.get_register_pair(Registers::H, Registers::L);
-    let val2 = state.memory[offset as usize];
-    update_conditions_sub(state, val, val2, true);
-    let carry = state.cc[&ConditionCodes::CY] as u8;
-    let result = val2.wrapping_add(carry);
-    state.registers[Registers::A as usize] = val.wrapping_sub(result);
+    let val = state.read_byte(offset);
+    let carry = carry(state);
+    sub_a(state, val, carry);
 }
 
 pub fn inr_r (state: &mut Cpu, dest:Registers){
-    let val = state.registers[dest.clone() as usize];
-    update_conditions_inc(state, val);
-    state.registers[dest as usize] = val.wrapping_add(1);
+    let result = increment(state, state.registers[dest as usize]);
+    state.registers[dest as usize] = result;
 }
 
 pub fn inr_m(state: &mut Cpu){
     let offset = state.get_register_pair(Registers::H, Registers::L);
-    let val = state.memory[offset as usize];
-    update_conditions_inc(state, val);
-    state.memory[offset as usize] = val.wrapping_add(1);
+    let val = state.read_byte(offset);
+    let result = increment(state, val);
+    state.write_byte(offset, result);
 }
 
 pub fn dcr_r(state: &mut Cpu, dest:Registers){
-    let val = state.registers[dest.clone() as usize];
-    update_conditions_dcr(state, val);
-    state.registers[dest as usize] = val.wrapping_sub(1);
+    let result = decrement(state, state.registers[dest as usize]);
+    state.registers[dest as usize] = result;
 }
 
 pub fn dcr_m(state: &mut Cpu){
     let offset = state.get_register_pair(Registers::H, Registers::L);
-    let val = state.memory[offset as usize];
-    update_conditions_dcr(state, val);
-    state.memory[offset as usize] = val.wrapping_sub(1);
+    let val = state.read_byte(offset);
+    let result = decrement(state, val);
+    state.write_byte(offset, result);
 }
 
 pub fn inx_rp(state: &mut Cpu, dest: Registers) {
-    let mut result = state.get_register_pair(dest.clone(), dest.clone().next());
+    let mut result = state.get_register_pair(dest, dest.next());
     result = result.wrapping_add(1);
-    state.set_register_pair(dest.clone(), dest.clone().next(), result);
+    state.set_register_pair(dest, dest.next(), result);
 }
 
 pub fn inx_sp (state: &mut Cpu){
@@ -881,9 +869,9 @@ pub fn inx_sp (state: &mut Cpu){
 }
 
 pub fn dcx_rp(state: &mut Cpu, dest: Registers) {
-    let mut result = state.get_register_pair(dest.clone(), dest.clone().next());
+    let mut result = state.get_register_pair(dest, dest.next());
     result = result.wrapping_sub(1);
-    state.set_register_pair(dest.clone(), dest.clone().next(), result);
+    state.set_register_pair(dest, dest.next(), result);
 }
 
 pub fn dcx_sp (state: &mut Cpu){
@@ -892,8 +880,8 @@ pub fn dcx_sp (state: &mut Cpu){
 
 pub fn dad_rp(state: &mut Cpu, dest: Registers) {
     let result = (state.get_register_pair(Registers::H, Registers::L) as u32)
-    .wrapping_add((state.get_register_pair(dest.clone(), dest.clone().next()) as u32));
-    set_state_condition_code(state, ConditionCodes::CY, result > 0xffff);
+    .wrapping_add(state.get_register_pair(dest, dest.next()) as u32);
+    set_carry(state, result > 0xffff);
     
     state.set_register_pair(Registers::H, Registers::L, result as u16);
 }
@@ -901,7 +889,7 @@ pub fn dad_rp(state: &mut Cpu, dest: Registers) {
 pub fn dad_sp(state: &mut Cpu) {
     let result = (state.get_register_pair(Registers::H, Registers::L) as u32)
     .wrapping_add(state.sp as u32);
-    set_state_condition_code(state, ConditionCodes::CY, result > 0xffff);
+    set_carry(state, result > 0xffff);
     
     state.set_register_pair(Registers::H, Registers::L, result as u16);
 }
@@ -910,137 +898,104 @@ pub fn daa (state: &mut Cpu){
  let mut val : u8 = 0;
  let msb = state.registers[Registers::A as usize] >> 4;
  let lsb = state.registers[Registers::A as usize] & 0xf;
+ let carry = carry(state) != 0 || msb > 9 || (msb >= 9 && lsb > 9);
 
-    if state.cc[&ConditionCodes::AC] || lsb > 9 {
+    if flags_of(state) & flags::AC != 0 || lsb > 9 {
         val = val.wrapping_add(0x06);
     }
 
-    if state.cc[&ConditionCodes::CY] || msb > 9 || (msb >= 9 && lsb > 9) {
+    if carry {
         val = val.wrapping_add(0x60);
-        set_state_condition_code(state, ConditionCodes::CY, true);
     }
 
-    let result = state.registers[Registers::A as usize].wrapping_add(val);
-    set_z_condition(state, result);
-    set_p_condition(state, result);
-    set_s_condition(state, result);
-    set_ac_condition_add(state, state.registers[Registers::A as usize], val, 0);
+    let (result, flags) = flags::add(state.registers[Registers::A as usize], val, 0);
+    state.f = flags & !flags::CY | carry as u8;
     state.registers[Registers::A as usize] = result;
 
 }
 
 pub fn ana_r(state: &mut Cpu, dest: Registers){
-    let val = state.registers[Registers::A as usize];
-    let val2 = state.registers[dest as usize];
-    let result = val & val2;
-    update_conditions_and(state, val, val2);
-    state.registers[Registers::A as usize] = result;
+    and_a(state, state.registers[dest as usize]);
 }
 
 pub fn ana_m (state: &mut Cpu){
-    let val = state.registers[Registers::A as usize];
     let offset = state.get_register_pair(Registers::H, Registers::L);
-    let val2 = state.memory[offset as usize];
-    update_conditions_and(state, val, val2);
-    let result = val & val2;
-    state.registers[Registers::A as usize] = result;
+    let val = state.read_byte(offset);
+    and_a(state, val);
 }
 
 pub fn ani (state: &mut Cpu, operand: u8){
-    let val = state.registers[Registers::A as usize];
-    let result = val & operand;
-    update_conditions_and(state, val, operand);
-    state.registers[Registers::A as usize] = result;
+    and_a(state, operand);
 }
 
 pub fn xra_r (state: &mut Cpu, dest: Registers){
-    let val = state.registers[Registers::A as usize];
-    let val2 = state.registers[dest as usize];
-    let result = val ^ val2;
-    update_conditions_or(state, result);
-    state.registers[Registers::A as usize] = result;
+    logic_a(state, state.registers[Registers::A as usize] ^ state.registers[dest as usize]);
 }
 
 pub fn xra_m (state: &mut Cpu){
-    let val = state.registers[Registers::A as usize];
     let offset = state.get_register_pair(Registers::H, Registers::L);
-    let val2 = state.memory[offset as usize];
-    let result = val ^ val2;
-    update_conditions_or(state, result);
-    state.registers[Registers::A as usize] = result;
+    let val = state.read_byte(offset);
+    logic_a(state, state.registers[Registers::A as usize] ^ val);
 }
 
 pub fn xri (state: &mut Cpu, operand: u8){
-    let val = state.registers[Registers::A as usize];
-    let result = val ^ operand;
-    update_conditions_or(state, result);
-    state.registers[Registers::A as usize] = result;
+    logic_a(state, state.registers[Registers::A as usize] ^ operand);
 }
 
 pub fn ora_r (state: &mut Cpu, dest: Registers){
-    let val = state.registers[Registers::A as usize];
-    let val2 = state.registers[dest as usize];
-    let result = val | val2;
-    update_conditions_or(state, result);
-    state.registers[Registers::A as usize] = result;
+    logic_a(state, state.registers[Registers::A as usize] | state.registers[dest as usize]);
 }
 
 pub fn ora_m (state: &mut Cpu){
-    let val = state.registers[Registers::A as usize];
     let offset = state.get_register_pair(Registers::H, Registers::L);
-    let val2 = state.memory[offset as usize];
-    let result = val | val2;
-    update_conditions_or(state, result);
-    state.registers[Registers::A as usize] = result;
+    let val = state.read_byte(offset);
+    logic_a(state, state.registers[Registers::A as usize] | val);
 }
 
 pub fn ori (state: &mut Cpu, operand: u8){
-    let val = state.registers[Registers::A as usize];
-    let result = val | operand;
-    update_conditions_or(state, result);
-    state.registers[Registers::A as usize] = result;
+    logic_a(state, state.registers[Registers::A as usize] | operand);
 }
 
 pub fn cmp_r (state: &mut Cpu, register: Registers){
-    update_conditions_cmp(state, state.registers[Registers::A as usize], state.registers[register as usize]);
+    compare_a(state, state.registers[register as usize]);
 }
 
 pub fn cmp_m (state: &mut Cpu){
     let offset = state.get_register_pair(Registers::H, Registers::L);
-    update_conditions_cmp(state, state.registers[Registers::A as usize], state.memory[offset as usize]);
+    let val2 = state.read_byte(offset);
+    compare_a(state, val2);
 }
 
 pub fn cpi (state: &mut Cpu, operand: u8){
-    update_conditions_cmp(state, state.registers[Registers::A as usize], operand);
+    compare_a(state, operand);
 }
 
 pub fn rlc (state: &mut Cpu){
     let val = state.registers[Registers::A as usize];
     let result = val.rotate_left(1);
-    set_state_condition_code(state, ConditionCodes::CY, val & 0x80 != 0);
+    set_carry(state, val & 0x80 != 0);
     state.registers[Registers::A as usize] = result;
 }
 
 pub fn rrc(state: &mut Cpu) {
     let val = state.registers[Registers::A as usize];
     let result = val.rotate_right(1);
-    set_state_condition_code(state, ConditionCodes::CY, val & 0x01 != 0);
+    set_carry(state, val & 0x01 != 0);
     state.registers[Registers::A as usize] = result;
 }
 
 pub fn ral(state: &mut Cpu) {
     let val = state.registers[Registers::A as usize];
-    let result = val.rotate_left(1);
-    let carry = state.cc[&ConditionCodes::CY] as u8;
-    set_state_condition_code(state, ConditionCodes::CY, val & 0x80 != 0);
-    state.registers[Registers::A as usize] = result.wrapping_add(carry);
+    let carry = carry(state);
+    set_carry(state, val & 0x80 != 0);
+    state.registers[Registers::A as usize] = val << 1 | carry;
 }
 
 pub fn rar(state: &mut Cpu) {
     let val = state.registers[Registers::A as usize];
     let mut result = val.rotate_right(1);
-    let carry = state.cc[&ConditionCodes::CY];
-    set_state_condition_code(state, ConditionCodes::CY, val & 0x01 != 0);
+    let carry = carry(state) != 0;
+    set_carry(state, val & 0x01 != 0);
 
     if carry {
         result |= 0x80;
@@ -1055,12 +1010,11 @@ pub fn cma(state: &mut Cpu) {
 }
 
 pub fn cmc(state: &mut Cpu) {
-    let carry = state.cc[&ConditionCodes::CY];
-    set_state_condition_code(state, ConditionCodes::CY, !carry);
+    set_carry(state, carry(state) == 0);
 }
 
 pub fn stc(state: &mut Cpu) {
-    set_state_condition_code(state, ConditionCodes::CY, true);
+    set_carry(state, true);
 }
 
 pub fn jmp(state: &mut Cpu, operands: [u8; MAX_OPERANDS]) {
@@ -1069,42 +1023,37 @@ pub fn jmp(state: &mut Cpu, operands: [u8; MAX_OPERANDS]) {
 }
 
 pub fn jcc(state: &mut Cpu, condition: ConditionCodes, comp: bool, operands: [u8; MAX_OPERANDS]) {
-    if state.cc[&condition] == comp {
+    if condition_met(state, condition) == comp {
         jmp(state, operands);
     }
 }
 
 pub fn call (state: &mut Cpu, operands: [u8; MAX_OPERANDS]){
-    state.memory[state.sp as usize - 1] = (state.pc >> 8) as u8;
-    state.memory[state.sp as usize - 2] = (state.pc & 0xff) as u8;
-    state.sp -= 2;
+    state.stack_push(state.pc as u16);
     jmp(state, operands);
 }
 
 pub fn ccc (state: &mut Cpu, condition: ConditionCodes, comp: bool, operands: [u8; MAX_OPERANDS]){
-    if state.cc[&condition] == comp {
+    if condition_met(state, condition) == comp {
         call(state, operands);
-        // TODO: INCREMENT CYCLES HERE
+        state.cycles += CONDITION_TAKEN_CYCLES;
     }
 }
 
 pub fn ret (state: &mut Cpu){
-    let offset = (state.memory[state.sp as usize] as u16) | ((state.memory[state.sp as usize + 1] as u16) << 8);
-    state.sp += 2;
+    let offset = state.stack_pop();
     state.pc = offset as usize;
 }
 
 pub fn rcc (state: &mut Cpu, condition: ConditionCodes, comp: bool){
-    if state.cc[&condition] == comp {
+    if condition_met(state, condition) == comp {
         ret(state);
-        // TODO: INCREMENT CYCLES HERE
+        state.cycles += CONDITION_TAKEN_CYCLES;
     }
 }
 
 pub fn rst_n(state: &mut Cpu, n: u8){
-    state.memory[state.sp as usize - 1] = (state.pc >> 8) as u8;
-    state.memory[state.sp as usize - 2] = (state.pc & 0xff) as u8;
-    state.sp -= 2;
+    state.stack_push(state.pc as u16);
     state.pc = (n * 8) as usize;
 }
 
@@ -1114,188 +1063,143 @@ pub fn pchl (state: &mut Cpu){
 }
 
 pub fn push_rp (state: &mut Cpu, src: Registers){
-    state.memory[state.sp as usize - 1] = state.registers[src.clone() as usize];
-    state.memory[state.sp as usize - 1] = state.registers[src.next() as usize];
-    state.sp -= 2;
+    let value = state.get_register_pair(src, src.next());
+    state.stack_push(value);
 }
 
 pub fn push_psw (state: &mut Cpu){
-    state.memory[state.sp as usize - 1] = state.registers[Registers::A as usize];
-    let psw = state_get_sw(state);
-    state.memory[state.sp as usize - 2] = psw;
-    state.sp -= 2;
+    let psw = state.read_f_reg();
+    state.stack_push((state.registers[Registers::A as usize] as u16) << 8 | psw as u16);
 }
 
 pub fn pop_rp(state: &mut Cpu, src: Registers){
-    state.registers[src.clone().next() as usize] = state.memory[state.sp as usize];
-    state.registers[src as usize] = state.memory[state.sp as usize + 1];
-    state.sp += 2;
+    let value = state.stack_pop();
+    state.set_register_pair(src, src.next(), value);
 }
 
 pub fn pop_psw(state: &mut Cpu){
-    let psw = state.memory[state.sp as usize];
-    set_state_condition_code(state, ConditionCodes::CY, (psw & 1) != 0);
-    set_state_condition_code(state, ConditionCodes::P, (psw & (1<<2))!=0);
-    set_state_condition_code(state, ConditionCodes::AC, (psw & (1<<4))!=0);
-    set_state_condition_code(state, ConditionCodes::Z, (psw & (1<<6))!=0);
-    set_state_condition_code(state, ConditionCodes::S, (psw & (1<<7))!=0);
-    state.registers[Registers::A as usize] = state.memory[state.sp as usize + 1];
-    state.sp += 2;
+    let [a, psw] = state.stack_pop().to_be_bytes();
+    state.write_f_reg(psw);
+    state.registers[Registers::A as usize] = a;
 }
 
 pub fn xthl(state: &mut Cpu){
-    let val = &mut state.registers[Registers::L as usize];
-    let val2 = &mut state.memory[state.sp as usize];
-    mem::swap(val, val2);
-    let val = &mut state.registers[Registers::H as usize];
-    let val2 = &mut state.memory[state.sp as usize  + 1];
-    mem::swap(val, val2);
+    let l = state.read_byte(state.sp);
+    let h = state.read_byte(state.sp.wrapping_add(1));
+    state.write_byte(state.sp, state.registers[Registers::L as usize]);
+    state.write_byte(state.sp.wrapping_add(1), state.registers[Registers::H as usize]);
+    state.registers[Registers::L as usize] = l;
+    state.registers[Registers::H as usize] = h;
 }
 
 pub fn sphl(state: &mut Cpu){
     state.sp = state.get_register_pair(Registers::H, Registers::L);
 }
 
-
-
-fn state_get_sw(state: &mut Cpu) -> u8{
-    let psw = 
-    (state.cc[&ConditionCodes::S] as u8) << 7
-    | (state.cc[&ConditionCodes::Z] as u8) << 6
-    | (state.cc[&ConditionCodes::AC] as u8) << 4
-    | (state.cc[&ConditionCodes::P] as u8) << 2
-    | (1<<1)
-    | (state.cc[&ConditionCodes::CY] as u8);
-    return psw;
+// machine control
+pub fn ei(state: &mut Cpu){
+    state.interrupts_enabled = true;
 }
 
-fn update_conditions_add(state: &mut Cpu, val1: u8, val2:u8, carry : bool){
-    let car = (carry && state.cc[&ConditionCodes::CY]) as u8;
-    let res = val1.wrapping_add(val2).wrapping_add(car);
-
-    set_z_condition(state, res);
-    set_p_condition(state, res);
-    set_s_condition(state, res);
-    set_ac_condition_add(state, val1, val2, car);
-    set_cy_condition_add(state, val1, val2, car)
+pub fn di(state: &mut Cpu){
+    state.interrupts_enabled = false;
 }
 
-fn update_conditions_sub(state: &mut Cpu, val1: u8, val2:u8, carry : bool){
-    let car = (carry && state.cc[&ConditionCodes::CY]) as u8;
-    let res = val1.wrapping_sub(val2).wrapping_sub(car);
-
-    set_z_condition(state, res);
-    set_p_condition(state, res);
-    set_s_condition(state, res);
-    set_ac_condition_sub(state, val1, val2, car==1);
-    set_cy_condition_sub(state, val1, val2, car==1)
+pub fn hlt(state: &mut Cpu){
+    state.halted = true;
 }
 
-fn update_conditions_inc(state: &mut Cpu, val: u8){
-    let res = val.wrapping_add(1);
-    set_z_condition(state, res);
-    set_p_condition(state, res);
-    set_s_condition(state, res);
-    set_ac_condition_add(state, val, 1, 0);
+// input/output
+pub fn in_port(state: &mut Cpu, operands: [u8; MAX_OPERANDS]){
+    state.registers[Registers::A as usize] = state.port_in(operands[0]);
 }
 
-fn update_conditions_dcr(state: &mut Cpu, val: u8){
-    let res = val.wrapping_sub(1);
-    set_z_condition(state, res);
-    set_p_condition(state, res);
-    set_s_condition(state, res);
-    set_ac_condition_add(state, val, 1, 0);
+pub fn out_port(state: &mut Cpu, operands: [u8; MAX_OPERANDS]){
+    state.port_out(operands[0], state.registers[Registers::A as usize]);
 }
 
-fn update_conditions_and(state: &mut Cpu, val1: u8, val2:u8){
-    set_z_condition(state, val1 & val2);
-    set_p_condition(state, val1 & val2);
-    set_s_condition(state, val1 & val2);
-    set_state_condition_code(state, ConditionCodes::CY, false);
-    set_state_condition_code(state, ConditionCodes::AC, ((val1 | val2) >> 3) & 1 > 0);
-}
 
-fn update_conditions_or(state: &mut Cpu, res: u8){
-    set_z_condition(state, res);
-    set_p_condition(state, res);
-    set_s_condition(state, res);
-    clear_cy_ac_conditions(state);
-}
 
-fn update_conditions_cmp (state: &mut Cpu, val1: u8, val2: u8){
-    let res = val1.wrapping_sub(val2);
-    set_z_condition(state, res);
-    set_p_condition(state, res);
-    set_s_condition(state, res);
-    set_ac_condition_sub(state, val1, val2, false);
-    set_cy_condition_sub(state, val1, val2, false);
+// With lazy flags on, the ALU helpers only record the operation in
+// `pending_flags` and F is worked out when something reads it.
+fn flags_of(state: &mut Cpu) -> u8 {
+    if let Some(pending) = state.pending_flags.take() {
+        state.f = pending.resolve();
+    }
+    state.f
 }
 
-
-fn set_state_condition_code(state: &mut Cpu, code: ConditionCodes, val: bool) {
-    state.cc.entry(code).and_modify(|v| *v = val).or_insert(val);
+fn record_flags(state: &mut Cpu, pending: Pending) {
+    if state.lazy_flags {
+        state.pending_flags = Some(pending);
+    } else {
+        state.f = pending.resolve();
+    }
 }
 
-fn set_z_condition(state: &mut Cpu, val: u8) {
-    return set_state_condition_code(state, ConditionCodes::Z, val == 0x00);
+// CY on its own is cheap to work out, so reading or replacing it leaves the
+// rest of the flags pending.
+fn carry(state: &Cpu) -> u8 {
+    match state.pending_flags {
+        Some(pending) => pending.carry_out(),
+        None => state.f & flags::CY,
+    }
 }
 
-fn set_s_condition(state: &mut Cpu, val: u8) {
-    return set_state_condition_code(state, ConditionCodes::S, val & 0x80 != 0);
+fn condition_met(state: &Cpu, code: ConditionCodes) -> bool {
+    match state.pending_flags {
+        Some(pending) => pending.flag(code as u8) != 0,
+        None => state.f & code as u8 != 0,
+    }
 }
 
-fn set_p_condition(state: &mut Cpu, val: u8) {
-    return set_state_condition_code(state, ConditionCodes::P, val % 2 == 0);
+fn set_carry(state: &mut Cpu, carry: bool) {
+    match state.pending_flags.as_mut() {
+        Some(pending) => pending.kept_carry = Some(carry as u8),
+        None => state.f = state.f & !flags::CY | carry as u8,
+    }
 }
 
-fn clear_cy_ac_conditions(state: &mut Cpu) {
-    set_state_condition_code(state, ConditionCodes::CY, false);
-    set_state_condition_code(state, ConditionCodes::AC, false);
+fn add_a(state: &mut Cpu, val: u8, carry: u8) {
+    let a = state.registers[Registers::A as usize];
+    state.registers[Registers::A as usize] = a.wrapping_add(val).wrapping_add(carry);
+    record_flags(state, Pending { operation: Operation::Add, a, b: val, carry, kept_carry: None });
 }
 
-fn set_ac_condition_add(state: &mut Cpu, val1: u8, val2: u8, add_carry : u8) {
-    let carry = (add_carry == 1 && state.cc[&ConditionCodes::CY]) as u8;
-    let ac_val = (val1 & 0xf) + (val2 & 0xf) + carry > 0xf;
-    return set_state_condition_code(state, ConditionCodes::AC, ac_val);
+fn sub_a(state: &mut Cpu, val: u8, borrow: u8) {
+    let a = state.registers[Registers::A as usize];
+    state.registers[Registers::A as usize] = a.wrapping_sub(val).wrapping_sub(borrow);
+    record_flags(state, Pending { operation: Operation::Sub, a, b: val, carry: borrow, kept_carry: None });
 }
 
-fn set_cy_condition_add(state: &mut Cpu, val1: u8, val2: u8, add_carry : u8) {
-    let carry = (add_carry == 1 && state.cc[&ConditionCodes::CY]) as u8;
-    let result = val1.wrapping_add(val2).wrapping_add(add_carry);
-    return set_state_condition_code(state, ConditionCodes::CY, result > 0xff);
+fn compare_a(state: &mut Cpu, val: u8) {
+    let a = state.registers[Registers::A as usize];
+    record_flags(state, Pending { operation: Operation::Sub, a, b: val, carry: 0, kept_carry: None });
 }
 
-fn set_ac_condition_sub(state: &mut Cpu, val1: u8, val2: u8, sub_carry : bool) {
-    let carry = (sub_carry && state.cc[&ConditionCodes::CY]) as u8;
-    let ac_val = (val1 & 0xf).wrapping_add(!val2 & 0xf).wrapping_add(!carry);
-    return set_state_condition_code(state, ConditionCodes::AC, ac_val>0xf);
+// ANA sets AC from bit 3 of the operands and always clears CY.
+fn and_a(state: &mut Cpu, val: u8) {
+    let a = state.registers[Registers::A as usize];
+    state.registers[Registers::A as usize] = a & val;
+    record_flags(state, Pending { operation: Operation::And, a, b: val, carry: 0, kept_carry: None });
 }
 
-fn set_cy_condition_sub(state: &mut Cpu, val1: u8, val2: u8, sub_carry : bool) {
-    let carry = (sub_carry && state.cc[&ConditionCodes::CY]) as u8;
-    let result = val1.wrapping_add(!val2).wrapping_add(!sub_carry as u8) ;
-    return set_state_condition_code(state, ConditionCodes::CY, result > 0xff);
+// ORA and XRA clear both CY and AC.
+fn logic_a(state: &mut Cpu, result: u8) {
+    state.registers[Registers::A as usize] = result;
+    record_flags(state, Pending { operation: Operation::Logic, a: result, b: 0, carry: 0, kept_carry: None });
 }
 
+// INR and DCR leave CY alone.
+fn increment(state: &mut Cpu, val: u8) -> u8 {
+    let kept_carry = Some(carry(state));
+    record_flags(state, Pending { operation: Operation::Add, a: val, b: 1, carry: 0, kept_carry });
+    val.wrapping_add(1)
+}
 
-// fn get_s_condition(val: u8) -> bool {
-//     return (val & 0x80) != 0;
-// }
-
-// fn get_p_condition(val: u8) -> bool {
-//     if val % 2 == 0 {
-//         true
-//     } else {
-//         false
-//     }
-// }
-
-// fn get_ac_condition_add(val1: u8, val2: u8) -> bool {
-//     let lower_nibble_overflow = (val1 & 0xf) + (val2 & 0xf);
-//     return lower_nibble_overflow > 0xf;
-// }
+fn decrement(state: &mut Cpu, val: u8) -> u8 {
+    let kept_carry = Some(carry(state));
+    record_flags(state, Pending { operation: Operation::Sub, a: val, b: 1, carry: 0, kept_carry });
+    val.wrapping_sub(1)
+}
 
-// fn get_ac_condition_sub(val1: u8, val2: u8) -> bool {
-//     let lower_nibble_underflow = (val1 & 0xf) < (val2 & 0xf);
-//     return lower_nibble_underflow;
-// }
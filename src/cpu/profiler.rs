@@ -0,0 +1,145 @@
+use std::io::{self, Write};
+
+use super::{symbols::Symbols, Instruction};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counter {
+    pub count: u64,
+    pub cycles: u64,
+}
+
+impl Counter {
+    fn add(&mut self, cycles: u64) {
+        self.count += 1;
+        self.cycles += cycles;
+    }
+}
+
+/// Execution counts and consumed cycles per PC address and per opcode.
+pub struct Profiler {
+    by_address: Vec<Counter>,
+    by_opcode: [Counter; 256],
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            by_address: vec![Counter::default(); 0x10000],
+            by_opcode: [Counter::default(); 256],
+        }
+    }
+
+    pub fn record(&mut self, pc: u16, opcode: u8, cycles: u64) {
+        self.by_address[pc as usize].add(cycles);
+        self.by_opcode[opcode as usize].add(cycles);
+    }
+
+    pub fn address(&self, pc: u16) -> Counter {
+        self.by_address[pc as usize]
+    }
+
+    pub fn opcode(&self, opcode: u8) -> Counter {
+        self.by_opcode[opcode as usize]
+    }
+
+    pub fn total(&self) -> Counter {
+        self.by_opcode
+            .iter()
+            .fold(Counter::default(), |total, counter| Counter {
+                count: total.count + counter.count,
+                cycles: total.cycles + counter.cycles,
+            })
+    }
+
+    /// Executed addresses, most cycles first.
+    pub fn hottest_addresses(&self) -> Vec<(u16, Counter)> {
+        let mut addresses = self
+            .by_address
+            .iter()
+            .enumerate()
+            .filter(|(_, counter)| counter.count > 0)
+            .map(|(address, counter)| (address as u16, *counter))
+            .collect::<Vec<_>>();
+        addresses.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(&b.0)));
+        addresses
+    }
+
+    /// Executed opcodes, most cycles first.
+    pub fn hottest_opcodes(&self) -> Vec<(u8, Counter)> {
+        let mut opcodes = self
+            .by_opcode
+            .iter()
+            .enumerate()
+            .filter(|(_, counter)| counter.count > 0)
+            .map(|(opcode, counter)| (opcode as u8, *counter))
+            .collect::<Vec<_>>();
+        opcodes.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(&b.0)));
+        opcodes
+    }
+
+    /// Writes both tables sorted by cycles. `limit` caps the number of
+    /// address rows; disassembly is taken from `memory` as it is now.
+    pub fn report(
+        &self,
+        out: &mut impl Write,
+        memory: &[u8],
+        symbols: Option<&Symbols>,
+        limit: usize,
+    ) -> io::Result<()> {
+        let total = self.total();
+        let percent = |cycles: u64| 100.0 * cycles as f64 / total.cycles.max(1) as f64;
+        writeln!(out, "{} instructions, {} cycles", total.count, total.cycles)?;
+        writeln!(out)?;
+        writeln!(out, "by address:")?;
+        writeln!(
+            out,
+            "{:<6}{:<24}{:>12}{:>14}{:>8}  disassembly",
+            "addr", "symbol", "count", "cycles", "%"
+        )?;
+        for (address, counter) in self.hottest_addresses().into_iter().take(limit) {
+            let symbol = symbols
+                .map(|symbols| symbols.describe(address))
+                .unwrap_or_default();
+            writeln!(
+                out,
+                "{:04x}  {:<24}{:>12}{:>14}{:>7.2}%  {}",
+                address,
+                symbol,
+                counter.count,
+                counter.cycles,
+                percent(counter.cycles),
+                Instruction::decode(memory, address).to_string().trim_end()
+            )?;
+        }
+        writeln!(out)?;
+        writeln!(out, "by opcode:")?;
+        writeln!(
+            out,
+            "{:<6}{:<10}{:>12}{:>14}{:>8}",
+            "op", "mnemonic", "count", "cycles", "%"
+        )?;
+        for (opcode, counter) in self.hottest_opcodes() {
+            let instruction = Instruction {
+                opcode,
+                operands: [0; 2],
+                size: 1,
+            };
+            writeln!(
+                out,
+                "{:02x}    {:<10}{:>12}{:>14}{:>7.2}%",
+                opcode,
+                instruction.mnemonic(),
+                counter.count,
+                counter.cycles,
+                percent(counter.cycles)
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use super::{
+    input_log::{InputEvent, InputKind},
+    save_state::StateError,
+    CpuError,
+};
+
+#[derive(Debug, Clone)]
+pub struct RewindConfig {
+    /// Instructions between snapshots. Stepping back replays at most this
+    /// many instructions.
+    pub interval: u64,
+    /// Upper bound in bytes for all stored snapshots. The oldest ones are
+    /// dropped first; the newest is always kept.
+    pub budget: usize,
+}
+
+/// Where `Cpu::reverse_continue` should stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// Before the instruction at this address runs.
+    Breakpoint(u16),
+    /// Before the instruction that changes the byte at this address runs.
+    Watchpoint(u16),
+}
+
+#[derive(Debug)]
+pub enum RewindError {
+    Disabled,
+    /// The target lies before the oldest snapshot still held.
+    OutOfHistory {
+        oldest: Option<u64>,
+    },
+    State(StateError),
+    /// Replaying towards the target failed.
+    Cpu(CpuError),
+}
+
+enum Data {
+    Full(Vec<u8>),
+    /// XOR against the next newer snapshot, run-length encoded.
+    Delta(Vec<u8>),
+}
+
+impl Data {
+    fn len(&self) -> usize {
+        match self {
+            Data::Full(data) | Data::Delta(data) => data.len(),
+        }
+    }
+}
+
+struct Snapshot {
+    /// Value of `Cpu::instructions` when it was taken.
+    instruction: u64,
+    /// Value of `Cpu::cycles` when it was taken.
+    cycle: u64,
+    data: Data,
+}
+
+/// Ring buffer of save states taken every `interval` instructions.
+///
+/// Only the newest snapshot is stored whole; each older one is kept as the
+/// XOR against its successor with runs of zeroes collapsed, so frames that
+/// touched little memory cost a few bytes.
+///
+/// IN values and accepted interrupts since the oldest snapshot are kept
+/// alongside, so replaying from a snapshot sees the same inputs as the
+/// original run did.
+pub struct Rewind {
+    config: RewindConfig,
+    snapshots: VecDeque<Snapshot>,
+    size: usize,
+    inputs: VecDeque<InputEvent>,
+}
+
+/// Appends `value` as LEB128.
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*position];
+        *position += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+/// `a XOR b` as a list of (zero run, literal length, literal bytes).
+fn encode_delta(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut position = 0;
+    while position < a.len() {
+        let zeroes = a[position..]
+            .iter()
+            .zip(&b[position..])
+            .take_while(|(x, y)| x == y)
+            .count();
+        position += zeroes;
+        let literal = a[position..]
+            .iter()
+            .zip(&b[position..])
+            .take_while(|(x, y)| x != y)
+            .count();
+        write_varint(&mut out, zeroes);
+        write_varint(&mut out, literal);
+        out.extend((position..position + literal).map(|index| a[index] ^ b[index]));
+        position += literal;
+    }
+    out
+}
+
+/// Applies a delta from `encode_delta` to `base` in place.
+fn apply_delta(base: &mut [u8], delta: &[u8]) {
+    let mut position = 0;
+    let mut input = 0;
+    while input < delta.len() {
+        position += read_varint(delta, &mut input);
+        let literal = read_varint(delta, &mut input);
+        for byte in &mut base[position..position + literal] {
+            *byte ^= delta[input];
+            input += 1;
+        }
+        position += literal;
+    }
+}
+
+impl Rewind {
+    pub fn new(config: RewindConfig) -> Rewind {
+        Rewind {
+            config,
+            snapshots: VecDeque::new(),
+            size: 0,
+            inputs: VecDeque::new(),
+        }
+    }
+
+    /// True when a snapshot should be taken before running instruction
+    /// number `instruction`.
+    pub fn due(&self, instruction: u64) -> bool {
+        instruction.is_multiple_of(self.config.interval.max(1))
+            && self
+                .snapshots
+                .back()
+                .is_none_or(|last| last.instruction < instruction)
+    }
+
+    pub fn push(&mut self, instruction: u64, cycle: u64, state: Vec<u8>) {
+        if let Some(last) = self.snapshots.back_mut() {
+            if let Data::Full(previous) = &last.data {
+                if previous.len() == state.len() {
+                    let delta = encode_delta(previous, &state);
+                    self.size -= previous.len();
+                    self.size += delta.len();
+                    last.data = Data::Delta(delta);
+                }
+            }
+        }
+        self.size += state.len();
+        self.snapshots.push_back(Snapshot {
+            instruction,
+            cycle,
+            data: Data::Full(state),
+        });
+        while self.size > self.config.budget && self.snapshots.len() > 1 {
+            let oldest = self.snapshots.pop_front().unwrap();
+            self.size -= oldest.data.len();
+        }
+        if let Some(oldest) = self.snapshots.front() {
+            while self
+                .inputs
+                .front()
+                .is_some_and(|event| event.cycle <= oldest.cycle)
+            {
+                self.inputs.pop_front();
+            }
+        }
+    }
+
+    /// Logs an IN value or accepted interrupt, as `InputLog::push` would.
+    pub fn record(&mut self, cycle: u64, kind: InputKind) {
+        if !self.snapshots.is_empty() {
+            self.inputs.push_back(InputEvent { cycle, kind });
+        }
+    }
+
+    /// The inputs a replay starting at `cycle` needs. A snapshot is taken
+    /// after any interrupt accepted on its cycle and before its instruction
+    /// runs, so only later events count.
+    pub fn inputs(&self, cycle: u64) -> Vec<InputEvent> {
+        self.inputs
+            .iter()
+            .filter(|event| event.cycle > cycle)
+            .copied()
+            .collect()
+    }
+
+    /// Number of inputs held.
+    pub fn input_count(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Instruction numbers of the held snapshots, oldest first.
+    pub fn instructions(&self) -> impl DoubleEndedIterator<Item = u64> + '_ {
+        self.snapshots.iter().map(|snapshot| snapshot.instruction)
+    }
+
+    pub fn oldest(&self) -> Option<u64> {
+        self.snapshots.front().map(|snapshot| snapshot.instruction)
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Bytes currently used by snapshot data.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn reconstruct(&self, index: usize) -> Vec<u8> {
+        let mut state = Vec::new();
+        for snapshot in self.snapshots.range(index..).rev() {
+            match &snapshot.data {
+                Data::Full(data) => state = data.clone(),
+                Data::Delta(delta) => apply_delta(&mut state, delta),
+            }
+        }
+        state
+    }
+
+    /// The newest snapshot taken at or before `instruction`, as its
+    /// instruction number and save state.
+    pub fn restore(&self, instruction: u64) -> Option<(u64, Vec<u8>)> {
+        let index = self
+            .snapshots
+            .iter()
+            .rposition(|snapshot| snapshot.instruction <= instruction)?;
+        Some((self.snapshots[index].instruction, self.reconstruct(index)))
+    }
+
+    /// Drops inputs that lie ahead of an instruction boundary at `cycle`,
+    /// for when execution continues from there. An interrupt on `cycle`
+    /// itself is yet to be accepted.
+    pub fn truncate_inputs(&mut self, cycle: u64) {
+        while self.inputs.back().is_some_and(|event| {
+            event.cycle > cycle
+                || event.cycle == cycle && matches!(event.kind, InputKind::Interrupt { .. })
+        }) {
+            self.inputs.pop_back();
+        }
+    }
+
+    /// Drops snapshots taken after `instruction`, for when execution
+    /// continues from an earlier point.
+    pub fn truncate_after(&mut self, instruction: u64) {
+        let keep = self
+            .snapshots
+            .iter()
+            .take_while(|snapshot| snapshot.instruction <= instruction)
+            .count();
+        if keep == self.snapshots.len() {
+            return;
+        }
+        if keep > 0 && matches!(self.snapshots[keep - 1].data, Data::Delta(_)) {
+            let state = self.reconstruct(keep - 1);
+            let newest = &mut self.snapshots[keep - 1];
+            self.size -= newest.data.len();
+            self.size += state.len();
+            newest.data = Data::Full(state);
+        }
+        for snapshot in self.snapshots.drain(keep..) {
+            self.size -= snapshot.data.len();
+        }
+    }
+}
+
+impl From<StateError> for RewindError {
+    fn from(error: StateError) -> Self {
+        RewindError::State(error)
+    }
+}
+
+impl From<CpuError> for RewindError {
+    fn from(error: CpuError) -> Self {
+        RewindError::Cpu(error)
+    }
+}
+
+impl fmt::Display for RewindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RewindError::Disabled => write!(f, "rewind is not enabled"),
+            RewindError::OutOfHistory {
+                oldest: Some(oldest),
+            } => {
+                write!(f, "cannot rewind past instruction {}", oldest)
+            }
+            RewindError::OutOfHistory { oldest: None } => write!(f, "no snapshots taken yet"),
+            RewindError::State(error) => write!(f, "{}", error),
+            RewindError::Cpu(error) => write!(f, "replay failed: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for RewindError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(budget: usize) -> RewindConfig {
+        RewindConfig {
+            interval: 10,
+            budget,
+        }
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let a = (0..1000).map(|index| (index * 7) as u8).collect::<Vec<_>>();
+        let mut cases = vec![a.clone(), vec![0; 1000]];
+        let mut sparse = a.clone();
+        sparse[0] ^= 1;
+        sparse[500] = 0xff;
+        sparse[501] = 0xfe;
+        sparse[999] ^= 0x80;
+        cases.push(sparse);
+        let mut long_run = a.clone();
+        long_run[200..400].fill(0x55);
+        cases.push(long_run);
+        for b in cases {
+            let delta = encode_delta(&a, &b);
+            let mut restored = b.clone();
+            apply_delta(&mut restored, &delta);
+            assert_eq!(restored, a);
+        }
+        assert!(encode_delta(&a, &a).len() <= 4);
+    }
+
+    #[test]
+    fn varint_round_trip() {
+        for value in [0, 1, 0x7f, 0x80, 0x3fff, 0x4000, 0xffff_ffff] {
+            let mut out = Vec::new();
+            write_varint(&mut out, value);
+            let mut position = 0;
+            assert_eq!(read_varint(&out, &mut position), value);
+            assert_eq!(position, out.len());
+        }
+    }
+
+    #[test]
+    fn older_snapshots_are_deltas() {
+        let mut rewind = Rewind::new(config(usize::MAX));
+        let mut state = vec![0; 4096];
+        for instruction in 0..5 {
+            state[instruction as usize] = 1;
+            rewind.push(instruction * 10, instruction * 40, state.clone());
+        }
+        assert!(rewind.size() < 4096 + 4 * 16);
+        let (start, restored) = rewind.restore(25).unwrap();
+        assert_eq!(start, 20);
+        assert_eq!(restored[..4], [1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn budget_evicts_the_oldest() {
+        let mut rewind = Rewind::new(config(3000));
+        for instruction in 0..10u64 {
+            let state = vec![instruction as u8; 1000];
+            rewind.push(instruction * 10, instruction * 40, state);
+            rewind.record(instruction * 40 + 1, InputKind::In { port: 1, value: 0 });
+        }
+        assert!(rewind.size() <= 3000);
+        assert_eq!(rewind.instructions().last(), Some(90));
+        let oldest = rewind.oldest().unwrap();
+        assert!(oldest > 0);
+        assert!(rewind.restore(oldest - 1).is_none());
+        assert_eq!(rewind.restore(90).unwrap().1, vec![9; 1000]);
+        assert_eq!(
+            rewind.restore(oldest).unwrap().1,
+            vec![(oldest / 10) as u8; 1000]
+        );
+        // Inputs before the oldest snapshot went with it
+        assert_eq!(rewind.input_count() as u64, 10 - oldest / 10);
+    }
+
+    #[test]
+    fn newest_snapshot_survives_a_small_budget() {
+        let mut rewind = Rewind::new(config(10));
+        rewind.push(0, 0, vec![1; 100]);
+        rewind.push(10, 40, vec![2; 100]);
+        assert_eq!(rewind.len(), 1);
+        assert_eq!(rewind.restore(10).unwrap().1, vec![2; 100]);
+    }
+
+    #[test]
+    fn truncates_inputs_ahead() {
+        let mut rewind = Rewind::new(config(usize::MAX));
+        rewind.push(0, 0, vec![0; 16]);
+        rewind.record(10, InputKind::In { port: 1, value: 1 });
+        rewind.record(20, InputKind::In { port: 1, value: 2 });
+        rewind.record(20, InputKind::Interrupt { vector: 1 });
+        rewind.record(30, InputKind::In { port: 1, value: 3 });
+        rewind.truncate_inputs(20);
+        assert_eq!(rewind.inputs(0).len(), 2);
+        assert_eq!(rewind.inputs(10).len(), 1);
+    }
+}
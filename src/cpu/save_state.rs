@@ -0,0 +1,224 @@
+use std::fmt;
+
+use crate::checksum::crc32;
+
+use super::{CpuState, MEMORY_SIZE, REGISTERS_COUNT};
+
+pub const MAGIC: &[u8; 4] = b"I80S";
+pub const VERSION: u16 = 1;
+/// Magic, version, payload length and payload CRC-32.
+pub const HEADER_SIZE: usize = 4 + 2 + 4 + 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    ChecksumMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    /// A device passed to `load_state` has no section in the state.
+    MissingDevice(String),
+    /// A device rejected its own section.
+    Device {
+        name: String,
+        message: String,
+    },
+}
+
+/// Hook for hardware attached to the CPU that has state of its own. Each
+/// device is stored as a section tagged with its name.
+pub trait DeviceState {
+    fn name(&self) -> &str;
+    fn save_state(&self, out: &mut Vec<u8>);
+    fn load_state(&mut self, data: &[u8]) -> Result<(), StateError>;
+}
+
+/// Little-endian writer for the payload.
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.data.extend_from_slice(value);
+    }
+
+    /// The finished state: header followed by the payload.
+    pub fn finish(self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_SIZE + self.data.len());
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&crc32(&self.data).to_le_bytes());
+        out.extend_from_slice(&self.data);
+        out
+    }
+}
+
+/// Little-endian reader over a payload whose header has been checked.
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    /// Validates magic, version, length and checksum, and returns a reader
+    /// over the payload.
+    pub fn new(state: &'a [u8]) -> Result<StateReader<'a>, StateError> {
+        if state.len() < HEADER_SIZE {
+            return Err(StateError::Truncated);
+        }
+        if &state[..4] != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = u16::from_le_bytes([state[4], state[5]]);
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let length = u32::from_le_bytes(state[6..10].try_into().unwrap()) as usize;
+        let expected = u32::from_le_bytes(state[10..14].try_into().unwrap());
+        let data = state
+            .get(HEADER_SIZE..HEADER_SIZE + length)
+            .ok_or(StateError::Truncated)?;
+        let actual = crc32(data);
+        if actual != expected {
+            return Err(StateError::ChecksumMismatch { expected, actual });
+        }
+        Ok(StateReader { data })
+    }
+
+    pub fn bytes(&mut self, count: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < count {
+            return Err(StateError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
+
+/// The CPU part of a save state.
+#[derive(Debug, Clone)]
+pub struct CpuSection<'a> {
+    pub state: CpuState,
+    pub instructions: u64,
+    pub interrupts_enabled: bool,
+    pub halted: bool,
+    pub memory: &'a [u8],
+}
+
+impl<'a> CpuSection<'a> {
+    pub fn read(input: &mut StateReader<'a>) -> Result<CpuSection<'a>, StateError> {
+        let registers = input.bytes(REGISTERS_COUNT)?.try_into().unwrap();
+        let f = input.u8()?;
+        let sp = input.u16()?;
+        let pc = input.u16()?;
+        let cycles = input.u64()?;
+        Ok(CpuSection {
+            state: CpuState {
+                pc,
+                sp,
+                registers,
+                f,
+                cycles,
+            },
+            instructions: input.u64()?,
+            interrupts_enabled: input.u8()? != 0,
+            halted: input.u8()? != 0,
+            memory: input.bytes(MEMORY_SIZE)?,
+        })
+    }
+}
+
+/// Validates and splits a save state without applying it.
+pub fn parse(state: &[u8]) -> Result<(CpuSection<'_>, Vec<Section<'_>>), StateError> {
+    let mut input = StateReader::new(state)?;
+    let cpu = CpuSection::read(&mut input)?;
+    Ok((cpu, read_devices(&mut input)?))
+}
+
+/// Device sections: a `u16` count, then per device a `u8` name length, the
+/// name, a `u32` data length and the data.
+pub(crate) fn write_devices(out: &mut StateWriter, devices: &[&dyn DeviceState]) {
+    out.u16(devices.len() as u16);
+    for device in devices {
+        let mut data = Vec::new();
+        device.save_state(&mut data);
+        out.u8(device.name().len() as u8);
+        out.bytes(device.name().as_bytes());
+        out.u32(data.len() as u32);
+        out.bytes(&data);
+    }
+}
+
+/// A device section as `(name, data)`.
+pub type Section<'a> = (&'a [u8], &'a [u8]);
+
+fn read_devices<'a>(input: &mut StateReader<'a>) -> Result<Vec<Section<'a>>, StateError> {
+    let count = input.u16()?;
+    let mut sections = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let length = input.u8()? as usize;
+        let name = input.bytes(length)?;
+        let length = input.u32()? as usize;
+        sections.push((name, input.bytes(length)?));
+    }
+    Ok(sections)
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {}", version)
+            }
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "save state checksum mismatch, expected {:08x}, got {:08x}",
+                expected, actual
+            ),
+            StateError::MissingDevice(name) => {
+                write!(f, "save state has no section for device {}", name)
+            }
+            StateError::Device { name, message } => write!(f, "device {}: {}", name, message),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
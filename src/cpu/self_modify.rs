@@ -0,0 +1,87 @@
+use std::fmt;
+
+/// A store into a byte that had already been executed as an opcode or
+/// operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeWrite {
+    /// Address of the instruction that made the write.
+    pub pc: u16,
+    pub address: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// Watches stores for self-modifying code. Anything that caches decoded
+/// instructions must drop what it holds for `CodeWrite::address`.
+pub struct SelfModifyDetector {
+    executed: Vec<bool>,
+    break_on_write: bool,
+    pending: Vec<CodeWrite>,
+    writes: Vec<CodeWrite>,
+}
+
+impl SelfModifyDetector {
+    /// With `break_on_write` set, the first code write of an instruction is
+    /// returned from `commit` so the CPU can stop on it.
+    pub fn new(break_on_write: bool) -> SelfModifyDetector {
+        SelfModifyDetector {
+            executed: vec![false; 0x10000],
+            break_on_write,
+            pending: Vec::new(),
+            writes: Vec::new(),
+        }
+    }
+
+    /// Marks the bytes of an instruction about to run.
+    pub fn execute(&mut self, address: u16, size: u8) {
+        for offset in 0..size as u16 {
+            self.executed[address.wrapping_add(offset) as usize] = true;
+        }
+    }
+
+    pub fn is_executed(&self, address: u16) -> bool {
+        self.executed[address as usize]
+    }
+
+    /// Called for every store that reaches memory, before it lands.
+    pub fn check_write(&mut self, address: u16, old: u8, new: u8) {
+        if self.executed[address as usize] {
+            self.pending.push(CodeWrite {
+                pc: 0,
+                address,
+                old,
+                new,
+            });
+        }
+    }
+
+    /// Files the writes of the instruction at `pc`. Returns the first one
+    /// when configured to break.
+    pub fn commit(&mut self, pc: u16) -> Option<CodeWrite> {
+        let first = self.pending.first().map(|write| CodeWrite { pc, ..*write });
+        self.writes.extend(
+            self.pending
+                .drain(..)
+                .map(|write| CodeWrite { pc, ..write }),
+        );
+        first.filter(|_| self.break_on_write)
+    }
+
+    pub fn writes(&self) -> &[CodeWrite] {
+        &self.writes
+    }
+
+    pub fn take_writes(&mut self) -> Vec<CodeWrite> {
+        std::mem::take(&mut self.writes)
+    }
+}
+
+impl fmt::Display for CodeWrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04x}: code at {:04x} modified, {:02x} -> {:02x}",
+            self.pc, self.address, self.old, self.new
+        )
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+use std::ops::{Range, RangeInclusive};
+
+/// Something the stack did that a well-behaved program should not.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StackAlert {
+    /// SP went below the bottom of the declared region.
+    Overflow { sp: u16 },
+    /// A pop moved SP above the initial SP, i.e. more was popped than pushed.
+    Underflow { sp: u16 },
+    /// SP was loaded with a value outside the region (SPHL, LXI SP, ...).
+    OutsideRegion { sp: u16 },
+    /// A push wrote over a protected range or over executed code.
+    PushIntoProtected { address: u16 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackEvent {
+    /// Address of the instruction that raised the alert, or for an
+    /// interrupt the address it interrupted.
+    pub pc: u16,
+    /// Vector of the interrupt whose return address push raised the alert.
+    pub interrupt: Option<u8>,
+    pub alert: StackAlert,
+}
+
+#[derive(Debug, Clone)]
+pub struct StackGuardConfig {
+    /// Memory reserved for the stack. `region.end` is the initial SP: the
+    /// stack is empty when SP equals it and full when SP is `region.start`.
+    pub region: Range<u16>,
+    /// Ranges a push must never land on, typically ROM. With coverage
+    /// enabled, pushes over any byte that has been executed are caught as
+    /// well; without it only these ranges count.
+    pub protected: Vec<RangeInclusive<u16>>,
+    /// Return the first alert of an instruction as a `CpuError` instead of
+    /// only recording it.
+    pub break_on_alert: bool,
+}
+
+/// Watches SP and stack writes against a declared stack region.
+///
+/// The guard stays quiet until SP first enters the region, so start-up
+/// code that has not loaded SP yet does not raise alerts.
+pub struct StackGuard {
+    config: StackGuardConfig,
+    armed: bool,
+    /// SP was inside the region after the previous instruction.
+    inside: bool,
+    lowest_sp: u16,
+    pending: Vec<StackAlert>,
+    events: Vec<StackEvent>,
+    /// Alert from an interrupt, held until the CPU can stop on it.
+    interrupt_alert: Option<StackAlert>,
+}
+
+impl StackGuard {
+    pub fn new(config: StackGuardConfig) -> StackGuard {
+        StackGuard {
+            lowest_sp: config.region.end,
+            config,
+            armed: false,
+            inside: false,
+            pending: Vec::new(),
+            events: Vec::new(),
+            interrupt_alert: None,
+        }
+    }
+
+    fn in_region(&self, sp: u16) -> bool {
+        (self.config.region.start..=self.config.region.end).contains(&sp)
+    }
+
+    /// Called before a push stores its two bytes at `sp` and `sp + 1`.
+    /// `over_code` is set when either byte was previously executed.
+    pub fn check_push(&mut self, sp: u16, over_code: bool) {
+        let protected = self.config.protected.iter().find_map(|range| {
+            [sp, sp.wrapping_add(1)]
+                .into_iter()
+                .find(|address| range.contains(address))
+        });
+        if let Some(address) = protected {
+            self.pending.push(StackAlert::PushIntoProtected { address });
+        } else if over_code {
+            self.pending
+                .push(StackAlert::PushIntoProtected { address: sp });
+        }
+        if self.armed && sp < self.config.region.start {
+            self.pending.push(StackAlert::Overflow { sp });
+        }
+    }
+
+    /// Called after a pop with the new SP.
+    pub fn check_pop(&mut self, sp: u16) {
+        if self.armed && sp > self.config.region.end {
+            self.pending.push(StackAlert::Underflow { sp });
+        }
+    }
+
+    /// Called once per instruction with its address and the SP it left
+    /// behind. Returns the first alert raised by the instruction when the
+    /// guard is configured to break.
+    pub fn commit(&mut self, pc: u16, sp: u16) -> Option<StackAlert> {
+        self.record(pc, None, sp)
+    }
+
+    /// Called after an interrupt pushed its return address `pc`, so its
+    /// alerts are not blamed on the handler's first instruction. An alert
+    /// to break on is kept for `take_interrupt_alert`.
+    pub fn commit_interrupt(&mut self, pc: u16, vector: u8, sp: u16) {
+        let alert = self.record(pc, Some(vector), sp);
+        self.interrupt_alert = self.interrupt_alert.take().or(alert);
+    }
+
+    /// The alert to break on that an interrupt raised since the last call.
+    pub fn take_interrupt_alert(&mut self) -> Option<StackAlert> {
+        self.interrupt_alert.take()
+    }
+
+    fn record(&mut self, pc: u16, interrupt: Option<u8>, sp: u16) -> Option<StackAlert> {
+        let inside = self.in_region(sp);
+        if inside {
+            self.armed = true;
+            self.lowest_sp = self.lowest_sp.min(sp);
+        } else if self.inside && self.pending.is_empty() {
+            self.pending.push(StackAlert::OutsideRegion { sp });
+        }
+        self.inside = inside;
+        let first = self.pending.first().cloned();
+        self.events
+            .extend(self.pending.drain(..).map(|alert| StackEvent {
+                pc,
+                interrupt,
+                alert,
+            }));
+        first.filter(|_| self.config.break_on_alert)
+    }
+
+    pub fn events(&self) -> &[StackEvent] {
+        &self.events
+    }
+
+    pub fn take_events(&mut self) -> Vec<StackEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Lowest SP seen inside the region.
+    pub fn high_water_mark(&self) -> u16 {
+        self.lowest_sp
+    }
+
+    /// Deepest stack usage in bytes.
+    pub fn max_depth(&self) -> u16 {
+        self.config.region.end - self.lowest_sp
+    }
+}
+
+impl fmt::Display for StackAlert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StackAlert::Overflow { sp } => write!(f, "stack overflow, SP={:04x}", sp),
+            StackAlert::Underflow { sp } => write!(f, "stack underflow, SP={:04x}", sp),
+            StackAlert::OutsideRegion { sp } => {
+                write!(f, "SP={:04x} left the stack region", sp)
+            }
+            StackAlert::PushIntoProtected { address } => {
+                write!(f, "push into protected memory at {:04x}", address)
+            }
+        }
+    }
+}
+
+impl fmt::Display for StackEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.interrupt {
+            Some(vector) => write!(f, "{:04x}: RST {}: {}", self.pc, vector, self.alert),
+            None => write!(f, "{:04x}: {}", self.pc, self.alert),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+
+/// Address to label map loaded from a symbol file.
+///
+/// Each non-empty line is `<hex address> <name>`; text after `;` or `#` is
+/// ignored. An optional `0x`/`$` prefix or `h` suffix on the address is
+/// accepted, so most assembler listings can be turned into a symbol file
+/// with little editing.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    labels: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Symbols, String> {
+        let mut symbols = Symbols::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split([';', '#']).next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let (Some(address), Some(name)) = (fields.next(), fields.next()) else {
+                return Err(format!("line {}: expected `<address> <name>`", number + 1));
+            };
+            let digits = address
+                .trim_start_matches("0x")
+                .trim_start_matches('$')
+                .trim_end_matches(['h', 'H']);
+            let address = u16::from_str_radix(digits, 16)
+                .map_err(|_| format!("line {}: bad address {}", number + 1, address))?;
+            symbols.insert(address, name);
+        }
+        Ok(symbols)
+    }
+
+    pub fn insert(&mut self, address: u16, name: &str) {
+        self.labels.insert(address, name.to_string());
+    }
+
+    /// The label defined exactly at `address`.
+    pub fn get(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    /// The closest label at or below `address` and the offset from it.
+    pub fn lookup(&self, address: u16) -> Option<(&str, u16)> {
+        self.labels
+            .range(..=address)
+            .next_back()
+            .map(|(start, name)| (name.as_str(), address - start))
+    }
+
+    /// `label` or `label+offset`, or an empty string with no label below.
+    pub fn describe(&self, address: u16) -> String {
+        match self.lookup(address) {
+            Some((name, 0)) => name.to_string(),
+            Some((name, offset)) => format!("{}+{}", name, offset),
+            None => String::new(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.labels
+            .iter()
+            .map(|(address, name)| (*address, name.as_str()))
+    }
+}
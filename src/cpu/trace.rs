@@ -0,0 +1,405 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::ops::{Range, RangeInclusive};
+
+use super::{CpuState, Instruction};
+
+/// One executed instruction with the machine state on either side of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// Number of instructions executed before this one.
+    pub index: u64,
+    pub instruction: Instruction,
+    pub before: CpuState,
+    pub after: CpuState,
+}
+
+/// Receives every executed instruction while installed on a `Cpu`.
+pub trait Tracer {
+    fn trace(&mut self, record: &TraceRecord);
+
+    /// Flushes buffered output and reports the first write error, if any.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Restricts which records reach a tracer.
+#[derive(Debug, Clone, Default)]
+pub struct TraceFilter {
+    /// Only instructions whose address falls in this range.
+    pub pc: Option<RangeInclusive<u16>>,
+    /// Only instructions whose index falls in this window.
+    pub window: Option<Range<u64>>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, record: &TraceRecord) -> bool {
+        self.pc
+            .as_ref()
+            .is_none_or(|range| range.contains(&record.before.pc))
+            && self
+                .window
+                .as_ref()
+                .is_none_or(|window| window.contains(&record.index))
+    }
+}
+
+pub struct FilteredTracer<T: Tracer> {
+    filter: TraceFilter,
+    inner: T,
+}
+
+impl<T: Tracer> FilteredTracer<T> {
+    pub fn new(filter: TraceFilter, inner: T) -> FilteredTracer<T> {
+        FilteredTracer { filter, inner }
+    }
+}
+
+impl<T: Tracer> Tracer for FilteredTracer<T> {
+    fn trace(&mut self, record: &TraceRecord) {
+        if self.filter.matches(record) {
+            self.inner.trace(record);
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Keeps the first write error so it can be reported from `flush`.
+struct Sink<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Sink<W> {
+    fn new(writer: W) -> Sink<W> {
+        Sink {
+            writer,
+            error: None,
+        }
+    }
+
+    fn write(&mut self, result: impl FnOnce(&mut W) -> io::Result<()>) {
+        if self.error.is_none() {
+            if let Err(error) = result(&mut self.writer) {
+                self.error = Some(error);
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+/// One human readable line per instruction.
+pub struct TextTracer<W: Write> {
+    sink: Sink<W>,
+}
+
+impl<W: Write> TextTracer<W> {
+    pub fn new(writer: W) -> TextTracer<W> {
+        TextTracer {
+            sink: Sink::new(writer),
+        }
+    }
+}
+
+impl<W: Write> Tracer for TextTracer<W> {
+    fn trace(&mut self, record: &TraceRecord) {
+        self.sink.write(|writer| {
+            writeln!(
+                writer,
+                "{:>8}  {:04x}  {}  {}",
+                record.index, record.before.pc, record.instruction, record.after
+            )
+        });
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+/// One JSON object per line.
+pub struct JsonTracer<W: Write> {
+    sink: Sink<W>,
+}
+
+impl<W: Write> JsonTracer<W> {
+    pub fn new(writer: W) -> JsonTracer<W> {
+        JsonTracer {
+            sink: Sink::new(writer),
+        }
+    }
+}
+
+fn json_state(state: &CpuState) -> String {
+    let r = &state.registers;
+    format!(
+        "{{\"pc\":{},\"sp\":{},\"a\":{},\"f\":{},\"b\":{},\"c\":{},\"d\":{},\"e\":{},\"h\":{},\"l\":{},\"cycles\":{}}}",
+        state.pc, state.sp, r[0], state.f, r[1], r[2], r[3], r[4], r[5], r[6], state.cycles
+    )
+}
+
+impl<W: Write> Tracer for JsonTracer<W> {
+    fn trace(&mut self, record: &TraceRecord) {
+        let instruction = &record.instruction;
+        let bytes = std::iter::once(instruction.opcode)
+            .chain(instruction.bytes().iter().copied())
+            .map(|byte| byte.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        self.sink.write(|writer| {
+            writeln!(
+                writer,
+                "{{\"index\":{},\"bytes\":[{}],\"mnemonic\":\"{}\",\"before\":{},\"after\":{}}}",
+                record.index,
+                bytes,
+                instruction.mnemonic(),
+                json_state(&record.before),
+                json_state(&record.after)
+            )
+        });
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+const BINARY_MAGIC: &[u8; 4] = b"I80T";
+const BINARY_VERSION: u16 = 1;
+const STATE_SIZE: usize = 20;
+const RECORD_SIZE: usize = 12 + 2 * STATE_SIZE;
+
+/// Compact fixed-size records, read back with `BinaryTraceReader`.
+///
+/// Layout: a `I80T` magic and a little-endian `u16` version, then one
+/// record per instruction holding the index, the instruction bytes and
+/// the state before and after it.
+pub struct BinaryTracer<W: Write> {
+    sink: Sink<W>,
+}
+
+impl<W: Write> BinaryTracer<W> {
+    pub fn new(writer: W) -> BinaryTracer<W> {
+        let mut sink = Sink::new(writer);
+        sink.write(|writer| {
+            writer.write_all(BINARY_MAGIC)?;
+            writer.write_all(&BINARY_VERSION.to_le_bytes())
+        });
+        BinaryTracer { sink }
+    }
+}
+
+fn encode_state(state: &CpuState, out: &mut Vec<u8>) {
+    out.extend_from_slice(&state.pc.to_le_bytes());
+    out.extend_from_slice(&state.sp.to_le_bytes());
+    out.extend_from_slice(&state.registers);
+    out.push(state.f);
+    out.extend_from_slice(&state.cycles.to_le_bytes());
+}
+
+fn decode_state(bytes: &[u8]) -> CpuState {
+    let mut registers = [0; 7];
+    registers.copy_from_slice(&bytes[4..11]);
+    CpuState {
+        pc: u16::from_le_bytes([bytes[0], bytes[1]]),
+        sp: u16::from_le_bytes([bytes[2], bytes[3]]),
+        registers,
+        f: bytes[11],
+        cycles: u64::from_le_bytes(bytes[12..20].try_into().unwrap()),
+    }
+}
+
+impl<W: Write> Tracer for BinaryTracer<W> {
+    fn trace(&mut self, record: &TraceRecord) {
+        let mut out = Vec::with_capacity(RECORD_SIZE);
+        out.extend_from_slice(&record.index.to_le_bytes());
+        out.push(record.instruction.opcode);
+        out.extend_from_slice(&record.instruction.operands);
+        out.push(record.instruction.size);
+        encode_state(&record.before, &mut out);
+        encode_state(&record.after, &mut out);
+        self.sink.write(|writer| writer.write_all(&out));
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+/// Iterates over the records written by a `BinaryTracer`.
+pub struct BinaryTraceReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> BinaryTraceReader<R> {
+    pub fn new(mut reader: R) -> io::Result<BinaryTraceReader<R>> {
+        let mut header = [0; 6];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != BINARY_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a binary trace file",
+            ));
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != BINARY_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported binary trace version {}", version),
+            ));
+        }
+        Ok(BinaryTraceReader { reader })
+    }
+}
+
+impl<R: Read> Iterator for BinaryTraceReader<R> {
+    type Item = io::Result<TraceRecord>;
+
+    /// Ends only at a record boundary; a file cut off inside a record
+    /// yields an `UnexpectedEof` error for it.
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut bytes = [0; RECORD_SIZE];
+        let mut filled = 0;
+        while filled < RECORD_SIZE {
+            match self.reader.read(&mut bytes[filled..]) {
+                Ok(0) if filled == 0 => return None,
+                Ok(0) => {
+                    return Some(Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!(
+                            "truncated trace record: {} of {} bytes",
+                            filled, RECORD_SIZE
+                        ),
+                    )))
+                }
+                Ok(read) => filled += read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Some(Err(error)),
+            }
+        }
+        Some(Ok(TraceRecord {
+            index: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            instruction: Instruction {
+                opcode: bytes[8],
+                operands: [bytes[9], bytes[10]],
+                size: bytes[11],
+            },
+            before: decode_state(&bytes[12..12 + STATE_SIZE]),
+            after: decode_state(&bytes[12 + STATE_SIZE..]),
+        }))
+    }
+}
+
+/// Line layout shared by common reference 8080 emulators, so logs can be
+/// diffed against them. The state is the one *before* the instruction ran:
+///
+/// `PC: 0100, AF: 0002, BC: 0000, DE: 0000, HL: 0000, SP: 0000, CYC: 0 (31 AD 06)`
+pub struct ReferenceTracer<W: Write> {
+    sink: Sink<W>,
+}
+
+impl<W: Write> ReferenceTracer<W> {
+    pub fn new(writer: W) -> ReferenceTracer<W> {
+        ReferenceTracer {
+            sink: Sink::new(writer),
+        }
+    }
+}
+
+impl<W: Write> Tracer for ReferenceTracer<W> {
+    fn trace(&mut self, record: &TraceRecord) {
+        let line = ReferenceLine {
+            state: record.before,
+            bytes: std::iter::once(record.instruction.opcode)
+                .chain(record.instruction.bytes().iter().copied())
+                .collect(),
+        };
+        self.sink.write(|writer| writeln!(writer, "{}", line));
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+/// One line of a reference-layout log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReferenceLine {
+    pub state: CpuState,
+    pub bytes: Vec<u8>,
+}
+
+impl ReferenceLine {
+    /// Parses a line written by `ReferenceTracer` or by another emulator
+    /// using the same layout. The opcode bytes are optional.
+    pub fn parse(line: &str) -> Option<ReferenceLine> {
+        let (fields, bytes) = match line.split_once('(') {
+            Some((fields, bytes)) => (fields, bytes.trim_end().trim_end_matches(')')),
+            None => (line, ""),
+        };
+        let mut state = CpuState::default();
+        for field in fields.split(',') {
+            let (name, value) = field.split_once(':')?;
+            let value = value.trim();
+            let hex = || u16::from_str_radix(value, 16).ok();
+            let [hi, lo] = match name.trim() {
+                "PC" => {
+                    state.pc = hex()?;
+                    continue;
+                }
+                "SP" => {
+                    state.sp = hex()?;
+                    continue;
+                }
+                "CYC" => {
+                    state.cycles = value.parse().ok()?;
+                    continue;
+                }
+                _ => hex()?.to_be_bytes(),
+            };
+            match name.trim() {
+                "AF" => (state.registers[0], state.f) = (hi, lo),
+                "BC" => (state.registers[1], state.registers[2]) = (hi, lo),
+                "DE" => (state.registers[3], state.registers[4]) = (hi, lo),
+                "HL" => (state.registers[5], state.registers[6]) = (hi, lo),
+                _ => return None,
+            }
+        }
+        let bytes = bytes
+            .split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        Some(ReferenceLine { state, bytes })
+    }
+}
+
+impl fmt::Display for ReferenceLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = &self.state.registers;
+        write!(
+            f,
+            "PC: {:04X}, AF: {:02X}{:02X}, BC: {:02X}{:02X}, DE: {:02X}{:02X}, HL: {:02X}{:02X}, SP: {:04X}, CYC: {}",
+            self.state.pc, r[0], self.state.f, r[1], r[2], r[3], r[4], r[5], r[6], self.state.sp, self.state.cycles
+        )?;
+        if !self.bytes.is_empty() {
+            let bytes = self
+                .bytes
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<_>>()
+                .join(" ");
+            write!(f, " ({})", bytes)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::HashSet;
+use std::fmt;
+
+/// Register slots tracked by `InitTracker`, as bits of a mask. The first
+/// seven follow the order of `Cpu::registers`. The flags are one slot, so
+/// an instruction setting any flag initializes all of them.
+pub mod slots {
+    pub const A: u16 = 1 << 0;
+    pub const B: u16 = 1 << 1;
+    pub const C: u16 = 1 << 2;
+    pub const D: u16 = 1 << 3;
+    pub const E: u16 = 1 << 4;
+    pub const H: u16 = 1 << 5;
+    pub const L: u16 = 1 << 6;
+    pub const F: u16 = 1 << 7;
+    pub const SP: u16 = 1 << 8;
+}
+
+const SLOT_NAMES: [&str; 9] = ["A", "B", "C", "D", "E", "H", "L", "F", "SP"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Location {
+    Memory(u16),
+    /// One of the bits in `slots`.
+    Register(u16),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UninitRead {
+    /// Address of the instruction that made the read.
+    pub pc: u16,
+    pub location: Location,
+}
+
+/// Register slots selected by the 3-bit field used in MOV, MVI, INR, DCR
+/// and the ALU group. Code 6 is the memory operand, addressed by HL.
+fn register_field(code: u8) -> u16 {
+    [
+        slots::B,
+        slots::C,
+        slots::D,
+        slots::E,
+        slots::H,
+        slots::L,
+        slots::H | slots::L,
+        slots::A,
+    ][code as usize & 7]
+}
+
+/// Register pair selected by the 2-bit field of LXI, DAD, INX, DCX. With
+/// `psw` set, code 3 is A and the flags (PUSH, POP) instead of SP.
+fn pair_field(code: u8, psw: bool) -> u16 {
+    match code & 3 {
+        0 => slots::B | slots::C,
+        1 => slots::D | slots::E,
+        2 => slots::H | slots::L,
+        _ if psw => slots::A | slots::F,
+        _ => slots::SP,
+    }
+}
+
+/// Register slots an opcode reads and writes, as `(reads, writes)`.
+/// Memory operands are not included; those go through `Cpu::read_byte`.
+pub fn register_usage(opcode: u8) -> (u16, u16) {
+    let dst = (opcode >> 3) & 7;
+    let src = opcode & 7;
+    let pair = (opcode >> 4) & 3;
+    match opcode {
+        0x76 => (0, 0),
+        0x40..=0x7f => {
+            let reads = register_field(src) | if dst == 6 { slots::H | slots::L } else { 0 };
+            let writes = if dst == 6 { 0 } else { register_field(dst) };
+            (reads, writes)
+        }
+        0x80..=0xbf => {
+            // ADC and SBB also consume the carry; CMP leaves A alone
+            let carry = if dst == 1 || dst == 3 { slots::F } else { 0 };
+            let writes = if dst == 7 {
+                slots::F
+            } else {
+                slots::A | slots::F
+            };
+            // SUB A and XRA A are the usual way of clearing A
+            if src == 7 && (dst == 2 || dst == 5) {
+                return (0, writes);
+            }
+            (slots::A | register_field(src) | carry, writes)
+        }
+        0xc6 | 0xd6 | 0xe6 | 0xf6 => (slots::A, slots::A | slots::F),
+        0xce | 0xde => (slots::A | slots::F, slots::A | slots::F),
+        0xee => (slots::A, slots::A | slots::F),
+        0xfe => (slots::A, slots::F),
+        0x02 | 0x12 => (pair_field(pair, false) | slots::A, 0),
+        0x0a | 0x1a => (pair_field(pair, false), slots::A),
+        0x22 => (slots::H | slots::L, 0),
+        0x2a => (0, slots::H | slots::L),
+        0x32 | 0xd3 => (slots::A, 0),
+        0x3a | 0xdb => (0, slots::A),
+        0x07 | 0x0f => (slots::A, slots::A | slots::F),
+        0x17 | 0x1f | 0x27 => (slots::A | slots::F, slots::A | slots::F),
+        0x2f => (slots::A, slots::A),
+        0x37 => (0, slots::F),
+        0x3f => (slots::F, slots::F),
+        0xe3 => (slots::SP | slots::H | slots::L, slots::H | slots::L),
+        0xe9 => (slots::H | slots::L, 0),
+        0xeb => (
+            slots::D | slots::E | slots::H | slots::L,
+            slots::D | slots::E | slots::H | slots::L,
+        ),
+        0xf9 => (slots::H | slots::L, slots::SP),
+        0xcd | 0xc9 => (slots::SP, slots::SP),
+        _ => match opcode & 0xcf {
+            0x01 => (0, pair_field(pair, false)),
+            0x09 => (
+                slots::H | slots::L | pair_field(pair, false),
+                slots::H | slots::L | slots::F,
+            ),
+            0x03 | 0x0b => (pair_field(pair, false), pair_field(pair, false)),
+            0xc5 => (pair_field(pair, true) | slots::SP, slots::SP),
+            0xc1 => (slots::SP, pair_field(pair, true) | slots::SP),
+            _ => match opcode & 0xc7 {
+                0x06 => (
+                    if dst == 6 { slots::H | slots::L } else { 0 },
+                    if dst == 6 { 0 } else { register_field(dst) },
+                ),
+                0x04 | 0x05 => {
+                    let writes = if dst == 6 { 0 } else { register_field(dst) };
+                    (register_field(dst), writes | slots::F)
+                }
+                // Jcc, Ccc and Rcc test a flag; the calls and returns also
+                // use the stack
+                0xc2 => (slots::F, 0),
+                0xc4 | 0xc0 => (slots::F | slots::SP, slots::SP),
+                0xc7 => (slots::SP, slots::SP),
+                _ => (0, 0),
+            },
+        },
+    }
+}
+
+/// Shadow record of which memory bytes and registers have been written
+/// since power-on, used to report reads of undefined values.
+///
+/// Each (pc, location) pair is reported once, so a loop reading the same
+/// uninitialized byte does not flood the log.
+pub struct InitTracker {
+    memory: Vec<bool>,
+    registers: u16,
+    pending: Vec<Location>,
+    seen: HashSet<(u16, Location)>,
+    reads: Vec<UninitRead>,
+}
+
+impl Default for InitTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InitTracker {
+    pub fn new() -> InitTracker {
+        InitTracker {
+            memory: vec![false; 0x10000],
+            registers: 0,
+            pending: Vec::new(),
+            seen: HashSet::new(),
+            reads: Vec::new(),
+        }
+    }
+
+    pub fn mark_memory(&mut self, address: u16) {
+        self.memory[address as usize] = true;
+    }
+
+    pub fn is_memory_initialized(&self, address: u16) -> bool {
+        self.memory[address as usize]
+    }
+
+    pub fn is_register_initialized(&self, slot: u16) -> bool {
+        self.registers & slot == slot
+    }
+
+    pub fn read_memory(&mut self, address: u16) {
+        if !self.memory[address as usize] {
+            self.pending.push(Location::Memory(address));
+        }
+    }
+
+    /// Queues the slots in `reads` that were never written.
+    pub fn read_registers(&mut self, reads: u16) {
+        let missing = reads & !self.registers;
+        for bit in 0..SLOT_NAMES.len() {
+            if missing & (1 << bit) != 0 {
+                self.pending.push(Location::Register(1 << bit));
+            }
+        }
+    }
+
+    pub fn write_registers(&mut self, writes: u16) {
+        self.registers |= writes;
+    }
+
+    /// Files the reads queued by the instruction at `pc`.
+    pub fn commit(&mut self, pc: u16) {
+        for location in std::mem::take(&mut self.pending) {
+            if self.seen.insert((pc, location)) {
+                self.reads.push(UninitRead { pc, location });
+            }
+        }
+    }
+
+    pub fn reads(&self) -> &[UninitRead] {
+        &self.reads
+    }
+
+    pub fn take_reads(&mut self) -> Vec<UninitRead> {
+        std::mem::take(&mut self.reads)
+    }
+}
+
+/// SplitMix64, used to fill memory and registers at power-on. Good enough
+/// to shake out code that depends on zeroed state, and the same seed
+/// always gives the same contents.
+pub struct PowerOnRng(u64);
+
+impl PowerOnRng {
+    pub fn new(seed: u64) -> PowerOnRng {
+        PowerOnRng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn fill(&mut self, buffer: &mut [u8]) {
+        for chunk in buffer.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Memory(address) => write!(f, "memory {:04x}", address),
+            Location::Register(slot) => {
+                write!(f, "register {}", SLOT_NAMES[slot.trailing_zeros() as usize])
+            }
+        }
+    }
+}
+
+impl fmt::Display for UninitRead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04x}: read of uninitialized {}",
+            self.pc, self.location
+        )
+    }
+}
@@ -0,0 +1,5 @@
+pub mod checksum;
+pub mod cpu;
+pub mod machines;
+pub mod romset;
+pub mod tools;
@@ -0,0 +1,399 @@
+//! Midway's Space Invaders board: an 8080 at 2 MHz with 8 KiB of ROM at
+//! 0000, 8 KiB of RAM at 2000 (video memory from 2400) mirrored up to
+//! FFFF, input ports for the controls and DIP switches, and the screen
+//! interrupts: RST 1 when the beam reaches scanline 96 and RST 2 at the
+//! start of vertical blank on scanline 224.
+
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use super::scheduler::{LineInterrupt, Scheduler};
+use super::screen::Screen;
+use super::shift_register::ShiftRegister;
+use crate::cpu::{
+    io::Ports,
+    memory_map::{permissions, Access, MemoryMap, Policy},
+    save_state::StateError,
+    Cpu, CpuError,
+};
+use crate::romset::RomSet;
+
+pub const ROM_SIZE: usize = 0x2000;
+pub const RAM_START: u16 = 0x2000;
+pub const RAM_END: u16 = 0x3fff;
+pub const VRAM_START: u16 = 0x2400;
+/// The picture as the rotated monitor shows it.
+pub const SCREEN_WIDTH: usize = 224;
+pub const SCREEN_HEIGHT: usize = 256;
+pub const CPU_HZ: u64 = 2_000_000;
+pub const FRAMES_PER_SECOND: u64 = 60;
+pub const CYCLES_PER_FRAME: u64 = CPU_HZ / FRAMES_PER_SECOND;
+/// Scanlines per frame, blanking included.
+pub const SCANLINES: u32 = 262;
+/// Visible scanlines, which the rotated monitor shows as columns.
+pub const VISIBLE_SCANLINES: u32 = 224;
+
+pub const INTERRUPTS: [LineInterrupt; 2] = [
+    LineInterrupt {
+        scanline: 96,
+        vector: 1,
+    },
+    LineInterrupt {
+        scanline: VISIBLE_SCANLINES,
+        vector: 2,
+    },
+];
+
+/// The four 2 KiB program ROMs of the Midway set, as MAME names them.
+pub const MANIFEST: &str = "\
+# file       address size crc32    sha1
+invaders.h   0000    0800 734f5ad8 ff6200af4c9110d8181249cbcef1a8a40fa40b7f
+invaders.g   0800    0800 6bfaca4a 16f48649b531bdef8c2d1446c429b5f414524350
+invaders.f   1000    0800 0ccead96 537aef03468f63c5b9e11dd61e253f7ae17d9743
+invaders.e   1800    0800 14e538b0 1d6ca0c99f9df71e2990b610deb9d7da0125e2d8
+";
+
+pub fn rom_set() -> RomSet {
+    RomSet::parse("invaders", MANIFEST).expect("built-in manifest")
+}
+
+/// Bits of input port 1.
+pub mod port1 {
+    pub const COIN: u8 = 0x01;
+    pub const P2_START: u8 = 0x02;
+    pub const P1_START: u8 = 0x04;
+    /// Always reads as 1.
+    pub const ALWAYS_SET: u8 = 0x08;
+    pub const P1_SHOT: u8 = 0x10;
+    pub const P1_LEFT: u8 = 0x20;
+    pub const P1_RIGHT: u8 = 0x40;
+}
+
+/// Bits of input port 2: DIP switches and player two's controls.
+pub mod port2 {
+    /// Ships per game minus three.
+    pub const SHIPS: u8 = 0x03;
+    pub const TILT: u8 = 0x04;
+    /// Bonus ship at 1000 points instead of 1500.
+    pub const EARLY_BONUS: u8 = 0x08;
+    pub const P2_SHOT: u8 = 0x10;
+    pub const P2_LEFT: u8 = 0x20;
+    pub const P2_RIGHT: u8 = 0x40;
+    /// Hides the coin information in the attract screen.
+    pub const NO_COIN_INFO: u8 = 0x80;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Coin,
+    Tilt,
+    P1Start,
+    P1Shot,
+    P1Left,
+    P1Right,
+    P2Start,
+    P2Shot,
+    P2Left,
+    P2Right,
+}
+
+impl Button {
+    /// Input port and bit the button drives.
+    fn bit(self) -> (u8, u8) {
+        match self {
+            Button::Coin => (1, port1::COIN),
+            Button::Tilt => (2, port2::TILT),
+            Button::P1Start => (1, port1::P1_START),
+            Button::P1Shot => (1, port1::P1_SHOT),
+            Button::P1Left => (1, port1::P1_LEFT),
+            Button::P1Right => (1, port1::P1_RIGHT),
+            Button::P2Start => (1, port1::P2_START),
+            Button::P2Shot => (2, port2::P2_SHOT),
+            Button::P2Left => (2, port2::P2_LEFT),
+            Button::P2Right => (2, port2::P2_RIGHT),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MachineError {
+    /// The program ROM is larger than the 8 KiB the board decodes.
+    RomSize(usize),
+}
+
+/// Everything behind the board's IN and OUT ports.
+#[derive(Debug, Clone, Default)]
+pub struct Io {
+    /// Buttons held down, as read from port 1.
+    pub port1: u8,
+    /// DIP switches and player two's buttons, as read from port 2.
+    pub port2: u8,
+    /// At OUT 4, OUT 2 and IN 3.
+    pub shift: ShiftRegister,
+    /// Last values written to the sound latches at ports 3 and 5.
+    pub sound: [u8; 2],
+    /// Writes to the watchdog at port 6.
+    pub watchdog_resets: u64,
+}
+
+impl Ports for Io {
+    fn input(&mut self, port: u8) -> u8 {
+        match port {
+            0 => 0x0e,
+            1 => self.port1 | port1::ALWAYS_SET,
+            2 => self.port2,
+            3 => self.shift.input(port),
+            _ => 0,
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            2 | 4 => self.shift.output(port, value),
+            3 => self.sound[0] = value,
+            5 => self.sound[1] = value,
+            6 => self.watchdog_resets += 1,
+            _ => {}
+        }
+    }
+}
+
+/// Lets the machine keep a handle on the ports it gives the CPU.
+struct SharedIo(Rc<RefCell<Io>>);
+
+impl Ports for SharedIo {
+    fn input(&mut self, port: u8) -> u8 {
+        self.0.borrow_mut().input(port)
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        self.0.borrow_mut().output(port, value)
+    }
+}
+
+pub struct Invaders {
+    cpu: Cpu,
+    io: Rc<RefCell<Io>>,
+    scheduler: Scheduler,
+}
+
+/// ROM read-only, RAM read/write and RAM mirrored over the rest of the
+/// address space. Writes to ROM are dropped silently, as on the board.
+pub fn memory_map() -> MemoryMap {
+    let mut map = MemoryMap::new();
+    map.add_region(
+        "rom",
+        0..=ROM_SIZE as u16 - 1,
+        permissions::READ | permissions::EXECUTE,
+    );
+    map.add_region("ram", RAM_START..=RAM_END, permissions::ALL);
+    map.add_mirror(RAM_END + 1..=0xffff, RAM_START..=RAM_END);
+    for access in [Access::Read, Access::Write, Access::Execute] {
+        map.set_policy(access, Policy::Ignore);
+    }
+    map
+}
+
+/// Decodes video RAM, 1 bit per pixel, into the picture on the monitor.
+/// Every 32 bytes are one 256-pixel scanline, lowest bit first. The CRT is
+/// mounted turned 90 degrees counter-clockwise, so scanlines become
+/// columns from left to right, each drawn from the bottom up.
+pub fn decode_screen(vram: &[u8]) -> Screen {
+    let mut screen = Screen::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    for (index, byte) in vram.iter().enumerate().take(SCREEN_WIDTH * 32) {
+        let x = index / 32;
+        for bit in 0..8 {
+            let y = SCREEN_HEIGHT - 1 - (index % 32 * 8 + bit);
+            screen.set(x, y, byte >> bit & 1 != 0);
+        }
+    }
+    screen
+}
+
+impl Invaders {
+    /// A board with `rom` in its program ROM, run from the block cache.
+    pub fn new(rom: &[u8]) -> Result<Invaders, MachineError> {
+        if rom.len() > ROM_SIZE {
+            return Err(MachineError::RomSize(rom.len()));
+        }
+        let io = Rc::new(RefCell::new(Io::default()));
+        let mut cpu = Cpu::new();
+        cpu.enable_block_cache();
+        cpu.load_rom(rom);
+        cpu.set_memory_map(memory_map());
+        cpu.set_ports(Box::new(SharedIo(io.clone())));
+        Ok(Invaders {
+            cpu,
+            io,
+            scheduler: Scheduler::new(CYCLES_PER_FRAME, SCANLINES, &INTERRUPTS),
+        })
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    pub fn io(&self) -> std::cell::Ref<'_, Io> {
+        self.io.borrow()
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.set_button(button, true);
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.set_button(button, false);
+    }
+
+    fn set_button(&mut self, button: Button, down: bool) {
+        let (port, bit) = button.bit();
+        let mut io = self.io.borrow_mut();
+        let value = match port {
+            1 => &mut io.port1,
+            _ => &mut io.port2,
+        };
+        match down {
+            true => *value |= bit,
+            false => *value &= !bit,
+        }
+    }
+
+    /// Sets the DIP switch bits of port 2 (`port2::SHIPS`,
+    /// `port2::EARLY_BONUS` and `port2::NO_COIN_INFO`).
+    pub fn set_dip_switches(&mut self, switches: u8) {
+        let mask = port2::SHIPS | port2::EARLY_BONUS | port2::NO_COIN_INFO;
+        let mut io = self.io.borrow_mut();
+        io.port2 = io.port2 & !mask | switches & mask;
+    }
+
+    /// Saves the CPU and the shift register. Inputs and sound latches are
+    /// not part of the state; the beam position follows from the cycles.
+    pub fn save_state(&self) -> Vec<u8> {
+        self.cpu.save_state(&[&self.io.borrow().shift])
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut shift = self.io.borrow().shift;
+        self.cpu.load_state(state, &mut [&mut shift])?;
+        self.io.borrow_mut().shift = shift;
+        self.scheduler.sync(self.cpu.cycles());
+        Ok(())
+    }
+
+    /// What the monitor shows right now.
+    pub fn screen(&self) -> Screen {
+        decode_screen(&self.cpu.memory()[VRAM_START as usize..=RAM_END as usize])
+    }
+
+    /// Frame the beam is in, counting from 0. Also the number of whole
+    /// frames run.
+    pub fn frame(&self) -> u64 {
+        self.scheduler.frame(self.cpu.cycles())
+    }
+
+    /// Scanline the beam is on, 0 to `SCANLINES - 1`.
+    pub fn scanline(&self) -> u32 {
+        self.scheduler.scanline(self.cpu.cycles())
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    /// Runs to the end of the current frame.
+    pub fn run_frame(&mut self) -> Result<(), CpuError> {
+        let end = (self.frame() + 1) * CYCLES_PER_FRAME;
+        self.scheduler.run(&mut self.cpu, end)
+    }
+
+    /// Runs for at least `cycles` more cycles.
+    pub fn run_cycles(&mut self, cycles: u64) -> Result<(), CpuError> {
+        let until = self.cpu.cycles() + cycles;
+        self.scheduler.run(&mut self.cpu, until)
+    }
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MachineError::RomSize(size) => write!(
+                f,
+                "ROM is {} bytes, the board only has {} bytes of ROM",
+                size, ROM_SIZE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MachineError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The pixel that lights up for `bit` of video RAM byte `index`.
+    fn lit_pixel(index: usize, bit: u8) -> (usize, usize) {
+        let mut vram = vec![0; SCREEN_WIDTH * 32];
+        vram[index] = 1 << bit;
+        let screen = decode_screen(&vram);
+        assert_eq!(screen.lit(), 1);
+        let pixel = screen.pixels.iter().position(|lit| *lit).unwrap();
+        (pixel % SCREEN_WIDTH, pixel / SCREEN_WIDTH)
+    }
+
+    #[test]
+    fn screen_is_rotated_counter_clockwise() {
+        // The first scanline starts bottom left and runs up the left edge
+        assert_eq!(lit_pixel(0, 0), (0, 255));
+        assert_eq!(lit_pixel(0, 7), (0, 248));
+        assert_eq!(lit_pixel(31, 7), (0, 0));
+        // The next one is the next column
+        assert_eq!(lit_pixel(32, 0), (1, 255));
+        assert_eq!(lit_pixel(0x2d4, 3), (22, 92));
+        assert_eq!(lit_pixel(SCREEN_WIDTH * 32 - 1, 7), (223, 0));
+    }
+
+    #[test]
+    fn screen_reads_video_ram() {
+        let mut invaders = Invaders::new(&[0x76]).unwrap();
+        assert_eq!(invaders.screen().lit(), 0);
+        // MVI A,01; STA 2400 written straight into RAM and run
+        let program = [0x3e, 0x01, 0x32, 0x00, 0x24, 0x76];
+        invaders.cpu_mut().load_rom(&program);
+        invaders.run_cycles(30).unwrap();
+        let screen = invaders.screen();
+        assert_eq!(screen.lit(), 1);
+        assert!(screen.get(0, SCREEN_HEIGHT - 1));
+    }
+
+    #[test]
+    fn rom_is_read_only_and_ram_is_mirrored() {
+        #[rustfmt::skip]
+        let program = [
+            0x3e, 0x5a,       // 0000: MVI A,5a
+            0x32, 0x00, 0x01, // 0002: STA 0100
+            0x32, 0xff, 0x1f, // 0005: STA 1fff
+            0x32, 0x10, 0x40, // 0008: STA 4010
+            0x32, 0x23, 0x61, // 000b: STA 6123
+            0x3e, 0x00,       // 000e: MVI A,00
+            0x3a, 0x10, 0x40, // 0010: LDA 4010
+            0x76,             // 0013: HLT
+        ];
+        let mut invaders = Invaders::new(&program).unwrap();
+        invaders.run_cycles(200).unwrap();
+        let cpu = invaders.cpu();
+        assert!(cpu.halted());
+        // Both ends of ROM keep their contents
+        assert_eq!(cpu.memory()[0x0100], 0x00);
+        assert_eq!(cpu.memory()[0x1fff], 0x00);
+        // Stores above RAM land 2000 bytes lower, and loads read them back
+        assert_eq!(cpu.memory()[0x2010], 0x5a);
+        assert_eq!(cpu.memory()[0x2123], 0x5a);
+        assert_eq!(cpu.memory()[0x4010], 0x00);
+        assert_eq!(cpu.memory()[0x6123], 0x00);
+        assert_eq!(cpu.state().registers[0], 0x5a);
+    }
+}
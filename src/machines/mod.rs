@@ -0,0 +1,4 @@
+pub mod invaders;
+pub mod scheduler;
+pub mod screen;
+pub mod shift_register;
@@ -0,0 +1,121 @@
+//! Raster timing for boards whose interrupts come from the video beam.
+//!
+//! Time is the CPU's cycle counter: frame `n` starts at cycle
+//! `n * cycles_per_frame` and its scanlines are spread evenly across it.
+//! The scheduler runs the CPU up to the cycle each line interrupt is due
+//! and raises it there, so it lands on the instruction boundary where the
+//! beam reaches that line.
+
+use crate::cpu::{Cpu, CpuError, RunExit};
+
+/// An interrupt raised every frame when the beam reaches `scanline`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineInterrupt {
+    pub scanline: u32,
+    /// Run as `RST vector`.
+    pub vector: u8,
+}
+
+pub struct Scheduler {
+    cycles_per_frame: u64,
+    scanlines: u32,
+    /// Sorted by scanline.
+    interrupts: Vec<LineInterrupt>,
+    /// Frame and index into `interrupts` of the next one due.
+    next_frame: u64,
+    next_index: usize,
+    raised: u64,
+    dropped: u64,
+}
+
+impl Scheduler {
+    pub fn new(cycles_per_frame: u64, scanlines: u32, interrupts: &[LineInterrupt]) -> Scheduler {
+        let mut interrupts = interrupts.to_vec();
+        interrupts.sort_by_key(|interrupt| interrupt.scanline);
+        let mut scheduler = Scheduler {
+            cycles_per_frame,
+            scanlines,
+            interrupts,
+            next_frame: 0,
+            next_index: 0,
+            raised: 0,
+            dropped: 0,
+        };
+        scheduler.sync(0);
+        scheduler
+    }
+
+    /// Cycle within a frame at which the beam reaches `scanline`.
+    pub fn line_cycle(&self, scanline: u32) -> u64 {
+        scanline as u64 * self.cycles_per_frame / self.scanlines as u64
+    }
+
+    /// Frame the beam is in at `cycles`, counting from 0.
+    pub fn frame(&self, cycles: u64) -> u64 {
+        cycles / self.cycles_per_frame
+    }
+
+    /// Scanline the beam is on at `cycles`.
+    pub fn scanline(&self, cycles: u64) -> u32 {
+        let offset = cycles % self.cycles_per_frame;
+        (offset * self.scanlines as u64 / self.cycles_per_frame) as u32
+    }
+
+    /// Cycle the next interrupt is due at, if there are any.
+    pub fn next_interrupt(&self) -> Option<u64> {
+        let interrupt = self.interrupts.get(self.next_index)?;
+        Some(self.next_frame * self.cycles_per_frame + self.line_cycle(interrupt.scanline))
+    }
+
+    /// Picks the first interrupt due at or after `cycles`, for when the CPU
+    /// jumped in time, as after loading a save state.
+    pub fn sync(&mut self, cycles: u64) {
+        self.next_frame = self.frame(cycles);
+        self.next_index = 0;
+        while self.next_interrupt().is_some_and(|due| due < cycles) {
+            self.advance();
+        }
+    }
+
+    fn advance(&mut self) {
+        self.next_index += 1;
+        if self.next_index == self.interrupts.len() {
+            self.next_index = 0;
+            self.next_frame += 1;
+        }
+    }
+
+    /// Runs `cpu` until its cycle counter reaches `until`, raising every
+    /// interrupt that falls due on the way. A halted CPU idles until the
+    /// next one.
+    pub fn run(&mut self, cpu: &mut Cpu, until: u64) -> Result<(), CpuError> {
+        while cpu.cycles() < until {
+            let due = self.next_interrupt().unwrap_or(u64::MAX);
+            let target = due.min(until);
+            while cpu.cycles() < target {
+                if cpu.run(target, &[])? == RunExit::Halted {
+                    cpu.step()?;
+                }
+            }
+            if cpu.cycles() >= due {
+                let vector = self.interrupts[self.next_index].vector;
+                match cpu.interrupt(vector) {
+                    true => self.raised += 1,
+                    false => self.dropped += 1,
+                }
+                self.advance();
+            }
+        }
+        Ok(())
+    }
+
+    /// Interrupts the CPU accepted.
+    pub fn raised(&self) -> u64 {
+        self.raised
+    }
+
+    /// Interrupts that fell due while the CPU had them disabled.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
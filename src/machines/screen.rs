@@ -0,0 +1,177 @@
+//! A monochrome frame as seen on the monitor, and plain writers for it.
+//! PPM is the simplest thing any image viewer opens; PNG is for places that
+//! want it, written uncompressed so it needs nothing beyond CRC-32 and
+//! Adler-32.
+
+use std::io::{self, Write};
+
+use crate::checksum::{adler32, crc32_update};
+
+const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+/// Largest stored deflate block.
+const STORED_BLOCK: usize = 0xffff;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screen {
+    pub width: usize,
+    pub height: usize,
+    /// Row-major, true where the pixel is lit.
+    pub pixels: Vec<bool>,
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize) -> Screen {
+        Screen {
+            width,
+            height,
+            pixels: vec![false; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, lit: bool) {
+        self.pixels[y * self.width + x] = lit;
+    }
+
+    /// Lit pixels, for a quick check that anything was drawn.
+    pub fn lit(&self) -> usize {
+        self.pixels.iter().filter(|lit| **lit).count()
+    }
+
+    /// 8-bit grey rows, white on black.
+    fn rows(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        self.pixels.chunks(self.width).map(|row| {
+            row.iter()
+                .map(|lit| if *lit { 0xff } else { 0x00 })
+                .collect()
+        })
+    }
+
+    /// Binary PPM (P6).
+    pub fn write_ppm(&self, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for row in self.rows() {
+            let rgb = row.iter().flat_map(|grey| [*grey; 3]).collect::<Vec<_>>();
+            out.write_all(&rgb)?;
+        }
+        Ok(())
+    }
+
+    /// 8-bit greyscale PNG.
+    pub fn write_png(&self, out: &mut dyn Write) -> io::Result<()> {
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // Bit depth 8, greyscale, deflate, adaptive filtering, no interlace
+        header.extend_from_slice(&[8, 0, 0, 0, 0]);
+
+        // Every row starts with its filter type, 0 for none
+        let mut raw = Vec::with_capacity((self.width + 1) * self.height);
+        for row in self.rows() {
+            raw.push(0);
+            raw.extend_from_slice(&row);
+        }
+
+        out.write_all(PNG_SIGNATURE)?;
+        write_chunk(out, b"IHDR", &header)?;
+        write_chunk(out, b"IDAT", &zlib_stored(&raw))?;
+        write_chunk(out, b"IEND", &[])
+    }
+}
+
+fn write_chunk(out: &mut dyn Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = crc32_update(crc32_update(0, kind), data);
+    out.write_all(&crc.to_be_bytes())
+}
+
+/// A zlib stream of stored deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // 32 KiB window, no preset dictionary, check bits making 0x7801 % 31 == 0
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[0x01, 0x00, 0x00, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let length = block.len() as u16;
+        stream.push(last as u8);
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::crc32;
+    use crate::romset::inflate::inflate;
+
+    fn checkerboard() -> Screen {
+        let mut screen = Screen::new(3, 2);
+        screen.set(0, 0, true);
+        screen.set(2, 0, true);
+        screen.set(1, 1, true);
+        screen
+    }
+
+    #[test]
+    fn ppm() {
+        let mut out = Vec::new();
+        checkerboard().write_ppm(&mut out).unwrap();
+        let header = b"P6\n3 2\n255\n";
+        assert_eq!(&out[..header.len()], header);
+        let pixels = &out[header.len()..];
+        assert_eq!(pixels.len(), 3 * 2 * 3);
+        assert_eq!(pixels[..6], [0xff, 0xff, 0xff, 0, 0, 0]);
+        assert_eq!(pixels[12..15], [0xff; 3]);
+    }
+
+    #[test]
+    fn png() {
+        let mut out = Vec::new();
+        checkerboard().write_png(&mut out).unwrap();
+        assert_eq!(&out[..8], PNG_SIGNATURE);
+
+        // Chunks as (kind, data), checking each CRC on the way
+        let mut chunks = Vec::new();
+        let mut rest = &out[8..];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (kind, data) = (&rest[4..8], &rest[8..8 + length]);
+            let crc = u32::from_be_bytes(rest[8 + length..12 + length].try_into().unwrap());
+            assert_eq!(crc, crc32(&rest[4..8 + length]));
+            chunks.push((kind, data));
+            rest = &rest[12 + length..];
+        }
+        let kinds = chunks.iter().map(|(kind, _)| *kind).collect::<Vec<_>>();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 3, 0, 0, 0, 2, 8, 0, 0, 0, 0]);
+
+        let zlib = chunks[1].1;
+        assert_eq!(u16::from_be_bytes([zlib[0], zlib[1]]) % 31, 0);
+        let raw = inflate(&zlib[2..zlib.len() - 4]).unwrap();
+        assert_eq!(raw, [0, 0xff, 0, 0xff, 0, 0, 0xff, 0]);
+        assert_eq!(zlib[zlib.len() - 4..], adler32(&raw).to_be_bytes());
+    }
+
+    #[test]
+    fn png_splits_large_images_into_stored_blocks() {
+        let screen = Screen::new(400, 400);
+        let mut out = Vec::new();
+        screen.write_png(&mut out).unwrap();
+        let length = u32::from_be_bytes(out[33..37].try_into().unwrap()) as usize;
+        let zlib = &out[41..41 + length];
+        let raw = inflate(&zlib[2..zlib.len() - 4]).unwrap();
+        assert_eq!(raw.len(), 401 * 400);
+    }
+}
@@ -0,0 +1,220 @@
+//! The 16-bit shift register of Midway's 8080 boards (Space Invaders,
+//! Gun Fight, Boot Hill and others). Bytes written to it shift in from the
+//! top; reads return 8 bits from a window that the offset moves, which lets
+//! the CPU draw sprites at any pixel position without shifting them itself.
+
+use crate::cpu::{io::Ports, save_state::DeviceState, save_state::StateError};
+
+const STATE_SIZE: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShiftRegister {
+    value: u16,
+    offset: u8,
+    data_port: u8,
+    offset_port: u8,
+    result_port: u8,
+}
+
+impl Default for ShiftRegister {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShiftRegister {
+    /// Wired as on Space Invaders: OUT 4 shifts in data, OUT 2 sets the
+    /// offset and IN 3 reads the result.
+    pub fn new() -> ShiftRegister {
+        ShiftRegister::with_ports(4, 2, 3)
+    }
+
+    /// For boards that decode the register at other ports.
+    pub fn with_ports(data_port: u8, offset_port: u8, result_port: u8) -> ShiftRegister {
+        ShiftRegister {
+            value: 0,
+            offset: 0,
+            data_port,
+            offset_port,
+            result_port,
+        }
+    }
+
+    /// Shifts `byte` into the high half; the old high half drops to the
+    /// low half.
+    pub fn write_data(&mut self, byte: u8) {
+        self.value = (byte as u16) << 8 | self.value >> 8;
+    }
+
+    /// Only the low 3 bits are wired.
+    pub fn set_offset(&mut self, offset: u8) {
+        self.offset = offset & 7;
+    }
+
+    /// The 8 bits starting `offset` bits below the top of the register.
+    pub fn result(&self) -> u8 {
+        (self.value >> (8 - self.offset)) as u8
+    }
+
+    pub fn value(&self) -> u16 {
+        self.value
+    }
+
+    pub fn offset(&self) -> u8 {
+        self.offset
+    }
+}
+
+/// Answers only its own ports: other reads return 0 and other writes are
+/// ignored, so a board can pass every port through it.
+impl Ports for ShiftRegister {
+    fn input(&mut self, port: u8) -> u8 {
+        match port == self.result_port {
+            true => self.result(),
+            false => 0,
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        if port == self.data_port {
+            self.write_data(value);
+        } else if port == self.offset_port {
+            self.set_offset(value);
+        }
+    }
+}
+
+impl DeviceState for ShiftRegister {
+    fn name(&self) -> &str {
+        "shift_register"
+    }
+
+    fn save_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.value.to_le_bytes());
+        out.push(self.offset);
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let [low, high, offset] = data[..] else {
+            return Err(StateError::Device {
+                name: self.name().to_string(),
+                message: format!("expected {} bytes, got {}", STATE_SIZE, data.len()),
+            });
+        };
+        if offset > 7 {
+            return Err(StateError::Device {
+                name: self.name().to_string(),
+                message: format!("offset {} out of range", offset),
+            });
+        }
+        self.value = u16::from_le_bytes([low, high]);
+        self.offset = offset;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::Cpu;
+
+    #[test]
+    fn shifts_in_from_the_top() {
+        let mut shift = ShiftRegister::new();
+        shift.write_data(0xab);
+        assert_eq!(shift.value(), 0xab00);
+        shift.write_data(0xcd);
+        assert_eq!(shift.value(), 0xcdab);
+        shift.write_data(0x12);
+        assert_eq!(shift.value(), 0x12cd);
+    }
+
+    #[test]
+    fn offset_moves_the_window() {
+        let mut shift = ShiftRegister::new();
+        shift.write_data(0b1010_0101);
+        shift.write_data(0b1100_0011);
+        let expected = [
+            0b1100_0011,
+            0b1000_0111,
+            0b0000_1110,
+            0b0001_1101,
+            0b0011_1010,
+            0b0111_0100,
+            0b1110_1001,
+            0b1101_0010,
+        ];
+        for (offset, expected) in expected.into_iter().enumerate() {
+            shift.set_offset(offset as u8);
+            assert_eq!(shift.result(), expected, "offset {}", offset);
+        }
+    }
+
+    #[test]
+    fn offset_keeps_three_bits() {
+        let mut shift = ShiftRegister::new();
+        shift.set_offset(0xfa);
+        assert_eq!(shift.offset(), 2);
+    }
+
+    #[test]
+    fn ports() {
+        let mut shift = ShiftRegister::new();
+        shift.output(4, 0xff);
+        shift.output(4, 0x00);
+        shift.output(2, 3);
+        assert_eq!(shift.input(3), 0x07);
+        // Other ports neither read the register nor change it
+        assert_eq!(shift.input(4), 0);
+        shift.output(3, 0x55);
+        shift.output(5, 0x55);
+        assert_eq!((shift.value(), shift.offset()), (0x00ff, 3));
+
+        let mut moved = ShiftRegister::with_ports(1, 7, 2);
+        moved.output(1, 0x01);
+        moved.output(7, 1);
+        assert_eq!(moved.input(2), 0x02);
+        assert_eq!(moved.input(3), 0);
+    }
+
+    #[test]
+    fn driven_by_the_cpu() {
+        #[rustfmt::skip]
+        let program = [
+            0x3e, 0xf0, 0xd3, 0x04, // MVI A,f0; OUT 4
+            0x3e, 0x0f, 0xd3, 0x04, // MVI A,0f; OUT 4
+            0x3e, 0x04, 0xd3, 0x02, // MVI A,04; OUT 2
+            0xdb, 0x03,             // IN 3
+        ];
+        let mut cpu = Cpu::new();
+        cpu.load_rom(&program);
+        cpu.set_ports(Box::new(ShiftRegister::new()));
+        for _ in 0..7 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.state().registers[0], 0xff);
+    }
+
+    #[test]
+    fn save_state_round_trip() {
+        let mut shift = ShiftRegister::new();
+        shift.write_data(0x34);
+        shift.write_data(0x12);
+        shift.set_offset(5);
+        let cpu = Cpu::new();
+        let state = cpu.save_state(&[&shift]);
+
+        let mut restored = ShiftRegister::new();
+        Cpu::new().load_state(&state, &mut [&mut restored]).unwrap();
+        assert_eq!(restored, shift);
+        assert_eq!(restored.result(), shift.result());
+    }
+
+    #[test]
+    fn rejects_bad_state() {
+        let mut shift = ShiftRegister::new();
+        assert!(shift.load_state(&[0, 0]).is_err());
+        assert!(shift.load_state(&[0, 0, 8]).is_err());
+        assert!(shift.load_state(&[0, 0, 7]).is_ok());
+    }
+}
@@ -1,16 +1,219 @@
-use std::{fs::File, io::Read};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+    process,
+};
 
-use cpu::Cpu;
-mod cpu;
+use intel8080::{
+    cpu::{
+        input_log::InputLog,
+        memory_map::{permissions, Access, MemoryMap, Policy},
+        rewind::{RewindConfig, Stop},
+        stack_guard::StackGuardConfig,
+        symbols::Symbols,
+        trace::{
+            BinaryTracer, FilteredTracer, JsonTracer, ReferenceTracer, TextTracer, TraceFilter,
+            Tracer,
+        },
+        Cpu,
+    },
+    machines::invaders::{self, Invaders},
+    romset::RomSet,
+    tools::{
+        snapshot_diff::{self, Region, Snapshot},
+        trace_diff::{self, DiffOptions, DiffResult},
+    },
+};
 
-fn main() {
-    // env::set_var("RUST_BACKTRACE", "1");
-    let mut state = Cpu::new();
+const DEFAULT_HISTORY: usize = 32;
+const PROFILE_ROWS: usize = 50;
+const DEFAULT_REWIND_BUDGET: usize = 16 << 20;
+const DEFAULT_SCREENSHOT_FRAMES: u64 = 300;
 
-    let args = std::env::args().collect::<Vec<String>>();
-    let file_path = &args[1];
-    println!("reading file path: {}", file_path);
-    let mut _file: File = match File::open(&file_path) {
+fn option<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+        .map(String::as_str)
+}
+
+fn parse_number(value: &str) -> Option<u64> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+/// Parses `start-end` into its two numbers.
+fn parse_range(value: &str) -> Option<(u64, u64)> {
+    let (start, end) = value.split_once('-')?;
+    Some((parse_number(start)?, parse_number(end)?))
+}
+
+fn tracer(args: &[String]) -> Option<Box<dyn Tracer>> {
+    let format = option(args, "--trace")?;
+    let writer: Box<dyn Write> = match option(args, "--trace-out") {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).unwrap_or_else(|error| panic!("Error: {}", error)),
+        )),
+        None => Box::new(io::stdout()),
+    };
+    let filter = TraceFilter {
+        pc: option(args, "--trace-pc")
+            .and_then(parse_range)
+            .map(|(start, end)| start as u16..=end as u16),
+        window: option(args, "--trace-window")
+            .and_then(parse_range)
+            .map(|(start, end)| start..end),
+    };
+    Some(match format {
+        "text" => Box::new(FilteredTracer::new(filter, TextTracer::new(writer))),
+        "json" => Box::new(FilteredTracer::new(filter, JsonTracer::new(writer))),
+        "binary" => Box::new(FilteredTracer::new(filter, BinaryTracer::new(writer))),
+        "reference" => Box::new(FilteredTracer::new(filter, ReferenceTracer::new(writer))),
+        _ => panic!("Error: unknown trace format {}", format),
+    })
+}
+
+fn write_report(path: &str, report: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>) {
+    let mut out =
+        BufWriter::new(File::create(path).unwrap_or_else(|error| panic!("Error: {}", error)));
+    if let Err(error) = report(&mut out).and_then(|_| out.flush()) {
+        eprintln!("Error: writing {}: {}", path, error);
+    }
+}
+
+fn read_text(path: &str) -> String {
+    std::fs::read_to_string(path).unwrap_or_else(|error| panic!("Error: {}: {}", path, error))
+}
+
+/// `trace-diff <left> <right> [--context N] [--ignore-cycles]`
+fn run_trace_diff(args: &[String]) {
+    let (Some(left), Some(right)) = (args.get(2), args.get(3)) else {
+        panic!("Error: usage: trace-diff <left> <right> [--context N] [--ignore-cycles]");
+    };
+    let options = DiffOptions {
+        context: option(args, "--context")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DiffOptions::default().context),
+        ignore_cycles: args.iter().any(|arg| arg == "--ignore-cycles"),
+    };
+    let left = trace_diff::parse_log(&read_text(left));
+    let right = trace_diff::parse_log(&read_text(right));
+    match trace_diff::diff(&left, &right, &options) {
+        DiffResult::NotAligned => {
+            eprintln!("Error: the logs never reach a common PC");
+            process::exit(2);
+        }
+        DiffResult::Identical { compared } => println!("{} lines match", compared),
+        DiffResult::EndOfLog {
+            compared,
+            ended,
+            last,
+            next,
+        } => {
+            println!(
+                "{} lines match, then the {} log ends at line {}; the other goes on:",
+                compared, ended, last.number
+            );
+            println!("  {:>6}  {}", next.number, next.line);
+            process::exit(1);
+        }
+        DiffResult::Mismatch(mismatch) => {
+            println!("{}", mismatch);
+            process::exit(1);
+        }
+    }
+}
+
+/// `snapshot-diff <left> <right> [--region name=start-end]... [--gap N]`
+fn run_snapshot_diff(args: &[String]) {
+    let (Some(left), Some(right)) = (args.get(2), args.get(3)) else {
+        panic!("Error: usage: snapshot-diff <left> <right> [--region name=start-end]... [--gap N]");
+    };
+    let load = |path: &str| {
+        let data = std::fs::read(path).unwrap_or_else(|error| panic!("Error: {}: {}", path, error));
+        Snapshot::load(&data).unwrap_or_else(|error| panic!("Error: {}: {}", path, error))
+    };
+    let regions = args
+        .windows(2)
+        .filter(|pair| pair[0] == "--region")
+        .map(|pair| {
+            Region::parse(&pair[1])
+                .unwrap_or_else(|| panic!("Error: bad region {}, expected name=start-end", pair[1]))
+        })
+        .collect::<Vec<_>>();
+    let options = snapshot_diff::DiffOptions {
+        gap: option(args, "--gap")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(snapshot_diff::DiffOptions::default().gap),
+    };
+    let diff = snapshot_diff::diff(&load(left), &load(right), &regions, &options);
+    if diff.is_empty() {
+        println!("snapshots are identical");
+        return;
+    }
+    if let Err(error) = diff.write_report(&mut io::stdout(), &regions) {
+        eprintln!("Error: {}", error);
+    }
+    process::exit(1);
+}
+
+/// The set named by `--manifest <file>`, or the Invaders set.
+fn rom_set(args: &[String]) -> RomSet {
+    let Some(path) = option(args, "--manifest") else {
+        return invaders::rom_set();
+    };
+    let text =
+        std::fs::read_to_string(path).unwrap_or_else(|error| panic!("Error: {}: {}", path, error));
+    let name = Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    RomSet::parse(&name, &text).unwrap_or_else(|error| panic!("Error: {}: {}", path, error))
+}
+
+/// `romset <directory|zip> [--manifest file] [--out image]`
+fn run_romset(args: &[String]) {
+    let Some(path) = args.get(2) else {
+        panic!("Error: usage: romset <directory|zip> [--manifest file] [--out image]");
+    };
+    let set = rom_set(args);
+    let dumps = set.read(Path::new(path)).unwrap_or_else(|error| {
+        eprintln!("Error: {}: {}", set.name, error);
+        process::exit(1);
+    });
+    for chip in &set.chips {
+        println!(
+            "{:<16} {:04x}-{:04x} {:08x} ok",
+            chip.name,
+            chip.address,
+            chip.address as usize + chip.size - 1,
+            chip.crc32
+        );
+    }
+    if let Some(out) = option(args, "--out") {
+        std::fs::write(out, set.image(&dumps))
+            .unwrap_or_else(|error| panic!("Error: {}: {}", out, error));
+        println!("image written to {}", out);
+    }
+}
+
+/// The program to run: a single image, or a directory or zip of chip dumps
+/// put together according to the ROM set.
+fn read_program(file_path: &str, args: &[String]) -> Vec<u8> {
+    let path = Path::new(file_path);
+    let is_zip = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+    if path.is_dir() || is_zip {
+        let set = rom_set(args);
+        return set
+            .load(path)
+            .unwrap_or_else(|error| panic!("Error: {}: {}", set.name, error));
+    }
+    let mut _file: File = match File::open(file_path) {
         Ok(file) => file,
         Err(error) => {
             panic!("Error: {}", error);
@@ -19,6 +222,288 @@ fn main() {
 
     let mut buffer = Vec::new();
     _ = _file.read_to_end(&mut buffer);
+    buffer
+}
+
+/// `screenshot <program|directory|zip> <out.png|out.ppm> [--frames N]`
+fn run_screenshot(args: &[String]) {
+    let (Some(program), Some(out)) = (args.get(2), args.get(3)) else {
+        panic!("Error: usage: screenshot <program|directory|zip> <out.png|out.ppm> [--frames N]");
+    };
+    let frames = option(args, "--frames")
+        .and_then(parse_number)
+        .unwrap_or(DEFAULT_SCREENSHOT_FRAMES);
+    let rom = read_program(program, args);
+    let mut machine = Invaders::new(&rom).unwrap_or_else(|error| panic!("Error: {}", error));
+    for _ in 0..frames {
+        if let Err(error) = machine.run_frame() {
+            eprintln!("Error: {} in frame {}", error, machine.frame());
+            process::exit(1);
+        }
+    }
+    let screen = machine.screen();
+    let file = File::create(out).unwrap_or_else(|error| panic!("Error: {}: {}", out, error));
+    let mut writer = BufWriter::new(file);
+    let result = match Path::new(out)
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("ppm") => screen.write_ppm(&mut writer),
+        _ => screen.write_png(&mut writer),
+    };
+    if let Err(error) = result.and_then(|_| writer.flush()) {
+        panic!("Error: {}: {}", out, error);
+    }
+    println!(
+        "{} frames, {} pixels lit, written to {}",
+        frames,
+        screen.lit(),
+        out
+    );
+}
+
+fn main() {
+    // env::set_var("RUST_BACKTRACE", "1");
+    let mut state = Cpu::new();
+
+    let args = std::env::args().collect::<Vec<String>>();
+    if args.get(1).map(String::as_str) == Some("trace-diff") {
+        return run_trace_diff(&args);
+    }
+    if args.get(1).map(String::as_str) == Some("snapshot-diff") {
+        return run_snapshot_diff(&args);
+    }
+    if args.get(1).map(String::as_str) == Some("romset") {
+        return run_romset(&args);
+    }
+    if args.get(1).map(String::as_str) == Some("screenshot") {
+        return run_screenshot(&args);
+    }
+    let file_path = &args[1];
+    let history = match args.iter().position(|arg| arg == "--history") {
+        Some(index) => args
+            .get(index + 1)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_HISTORY),
+        None => 0,
+    };
+    println!("reading file path: {}", file_path);
+    let buffer = read_program(file_path, &args);
+    if let Some(seed) = option(&args, "--power-on-seed").and_then(parse_number) {
+        state.power_on(seed);
+    }
+    if args.iter().any(|arg| arg == "--uninit") {
+        state.enable_init_tracker();
+    }
     state.load_rom(&buffer);
-    state.read();
+    if let Some(path) = option(&args, "--load-state") {
+        let data = std::fs::read(path).unwrap_or_else(|error| panic!("Error: {}: {}", path, error));
+        if let Err(error) = state.load_state(&data, &mut []) {
+            panic!("Error: {}: {}", path, error);
+        }
+    }
+    // --record <path> logs inputs, --replay <path> plays such a log back
+    if option(&args, "--record").is_some() {
+        state.start_recording(&buffer);
+    }
+    if let Some(path) = option(&args, "--replay") {
+        let data = std::fs::read(path).unwrap_or_else(|error| panic!("Error: {}: {}", path, error));
+        let log = InputLog::parse(&data)
+            .and_then(|log| log.check_rom(&buffer).map(|_| log))
+            .unwrap_or_else(|error| panic!("Error: {}: {}", path, error));
+        state.start_replay(log);
+    }
+    state.enable_history(history);
+    if let Some(tracer) = tracer(&args) {
+        state.set_tracer(tracer);
+    }
+    if option(&args, "--profile").is_some() {
+        state.enable_profiler();
+    }
+    if option(&args, "--call-profile").is_some() || option(&args, "--folded").is_some() {
+        state.enable_call_profiler();
+    }
+    // --protect ignore|log|trap: the loaded ROM becomes read/execute only
+    // and everything above it read/write only
+    if let Some(policy) = option(&args, "--protect") {
+        let policy = match policy {
+            "ignore" => Policy::Ignore,
+            "log" => Policy::Log,
+            "trap" => Policy::Trap,
+            _ => panic!("Error: unknown protection policy {}", policy),
+        };
+        // An empty ROM leaves no rom region and a 64 KiB one no ram region
+        let rom_end = buffer.len().min(0x10000);
+        let mut map = MemoryMap::new();
+        if rom_end > 0 {
+            map.add_region(
+                "rom",
+                0..=(rom_end - 1) as u16,
+                permissions::READ | permissions::EXECUTE,
+            );
+        }
+        if rom_end < 0x10000 {
+            map.add_region(
+                "ram",
+                rom_end as u16..=0xffff,
+                permissions::READ | permissions::WRITE,
+            );
+        }
+        for access in [Access::Read, Access::Write, Access::Execute] {
+            map.set_policy(access, policy);
+        }
+        state.set_memory_map(map);
+    }
+    // --stack-guard <bottom>-<initial SP>, with the loaded ROM protected
+    if let Some((bottom, top)) = option(&args, "--stack-guard").and_then(parse_range) {
+        // Clamped like --protect, so a 64 KiB ROM covers all of memory
+        let rom_end = buffer.len().min(0x10000);
+        let protected = match rom_end {
+            0 => vec![],
+            end => vec![0..=(end - 1) as u16],
+        };
+        state.enable_stack_guard(StackGuardConfig {
+            region: bottom as u16..top as u16,
+            protected,
+            break_on_alert: args.iter().any(|arg| arg == "--stack-break"),
+        });
+    }
+    // --self-modify, plus --self-modify-break to stop on the first one
+    let self_modify_break = args.iter().any(|arg| arg == "--self-modify-break");
+    if self_modify_break || args.iter().any(|arg| arg == "--self-modify") {
+        state.enable_self_modify_detector(self_modify_break);
+    }
+    // --rewind <interval> [--rewind-budget <bytes>]
+    if let Some(interval) = option(&args, "--rewind").and_then(parse_number) {
+        state.enable_rewind(RewindConfig {
+            interval,
+            budget: option(&args, "--rewind-budget")
+                .and_then(parse_number)
+                .map_or(DEFAULT_REWIND_BUDGET, |budget| budget as usize),
+        });
+    }
+    if option(&args, "--coverage").is_some() || option(&args, "--lcov").is_some() {
+        state.enable_coverage();
+    }
+    // --cycles <n> runs until the cycle counter reaches n, from the block
+    // cache with --blocks
+    if args.iter().any(|arg| arg == "--blocks") {
+        state.enable_block_cache();
+    }
+    let steps = option(&args, "--steps").and_then(parse_number);
+    let cycles = option(&args, "--cycles").and_then(parse_number);
+    let result = match (steps, cycles) {
+        (Some(steps), _) => (0..steps).try_for_each(|_| state.step()),
+        (None, Some(cycles)) => state.run(cycles, &[]).map(|_| ()),
+        (None, None) => state.read(),
+    };
+    // --reverse-steps <n>: step back after the run and show where it ends up
+    if let Some(steps) = option(&args, "--reverse-steps").and_then(parse_number) {
+        for _ in 0..steps {
+            if let Err(error) = state.reverse_step() {
+                eprintln!("Error: {}", error);
+                break;
+            }
+        }
+        println!("{} {}", state.instructions(), state.state());
+    }
+    // --reverse-break <addr> and --reverse-watch <addr>, each repeatable:
+    // run backwards to the last time one of them hit
+    let stops = args
+        .windows(2)
+        .filter_map(|pair| {
+            let stop = match pair[0].as_str() {
+                "--reverse-break" => Stop::Breakpoint,
+                "--reverse-watch" => Stop::Watchpoint,
+                _ => return None,
+            };
+            let address =
+                parse_number(&pair[1]).unwrap_or_else(|| panic!("Error: bad address {}", pair[1]));
+            Some(stop(address as u16))
+        })
+        .collect::<Vec<_>>();
+    if !stops.is_empty() {
+        match state.reverse_continue(&stops) {
+            Ok(true) => println!("{} {}", state.instructions(), state.state()),
+            Ok(false) => println!("no stop hit back to instruction {}", state.instructions()),
+            Err(error) => eprintln!("Error: {}", error),
+        }
+    }
+    if let (Some(path), Some(log)) = (option(&args, "--record"), state.take_recording()) {
+        if let Err(error) = std::fs::write(path, log.to_bytes()) {
+            eprintln!("Error: writing {}: {}", path, error);
+        }
+    }
+    if let Some(path) = option(&args, "--save-state") {
+        if let Err(error) = std::fs::write(path, state.save_state(&[])) {
+            eprintln!("Error: writing {}: {}", path, error);
+        }
+    }
+    if let Some(mut tracer) = state.take_tracer() {
+        if let Err(error) = tracer.flush() {
+            eprintln!("Error: writing trace: {}", error);
+        }
+    }
+    let symbols = option(&args, "--symbols").map(|path| {
+        Symbols::parse(&read_text(path)).unwrap_or_else(|error| panic!("Error: {}", error))
+    });
+    if let (Some(path), Some(profiler)) = (option(&args, "--profile"), state.profiler()) {
+        write_report(path, |out| {
+            profiler.report(out, state.memory(), symbols.as_ref(), PROFILE_ROWS)
+        });
+    }
+    if let Some(call_profiler) = state.call_profiler() {
+        if let Some(path) = option(&args, "--call-profile") {
+            write_report(path, |out| call_profiler.report(out, symbols.as_ref()));
+        }
+        if let Some(path) = option(&args, "--folded") {
+            write_report(path, |out| {
+                call_profiler.write_folded(out, symbols.as_ref())
+            });
+        }
+    }
+    if let Some(coverage) = state.coverage() {
+        let rom = 0..buffer.len();
+        if let Some(path) = option(&args, "--coverage") {
+            write_report(path, |out| {
+                coverage.write_listing(out, state.memory(), rom.clone(), symbols.as_ref())
+            });
+        }
+        if let Some(path) = option(&args, "--lcov") {
+            let symbols = symbols.clone().unwrap_or_default();
+            write_report(path, |out| {
+                coverage.write_lcov(out, state.memory(), rom.clone(), &symbols, file_path)?;
+                coverage.write_summary(&mut io::stdout(), state.memory(), rom, &symbols)
+            });
+        }
+    }
+    if let Some(guard) = state.stack_guard() {
+        println!(
+            "stack: max depth {} bytes, lowest SP {:04x}",
+            guard.max_depth(),
+            guard.high_water_mark()
+        );
+        for event in guard.events() {
+            println!("stack alert {}", event);
+        }
+    }
+    if let Some(tracker) = state.init_tracker() {
+        for read in tracker.reads() {
+            println!("uninit {}", read);
+        }
+    }
+    if let Some(detector) = state.self_modify_detector() {
+        for write in detector.writes() {
+            println!("self-modify {}", write);
+        }
+    }
+    if let Some(map) = state.memory_map() {
+        for violation in map.violations() {
+            println!("protection {}", violation);
+        }
+    }
+    if let Err(error) = result {
+        eprintln!("Error: {}", error);
+        process::exit(1);
+    }
 }
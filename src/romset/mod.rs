@@ -0,0 +1,302 @@
+//! ROM sets: the chip dumps a board needs, where each one is loaded and
+//! what a good dump hashes to. Sets load from a directory of dumps or a zip
+//! of them, and are checked before they are put together into one image.
+//!
+//! Manifests are text, one chip per line:
+//!
+//! ```text
+//! # file        address size crc32    sha1
+//! invaders.h    0000    0800 734f5ad8 ff6200af4c9110d8181249cbcef1a8a40fa40b7f
+//! ```
+//!
+//! Addresses and sizes are hex. Blank lines and `#` comments are skipped.
+
+pub mod inflate;
+pub mod zip;
+
+use std::{fmt, fs, path::Path};
+
+use crate::checksum::{crc32, hex, sha1};
+use zip::ZipError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chip {
+    /// File name of the dump, matched case-insensitively.
+    pub name: String,
+    pub address: u16,
+    pub size: usize,
+    pub crc32: u32,
+    pub sha1: [u8; 20],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomSet {
+    pub name: String,
+    pub chips: Vec<Chip>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomSetError {
+    Manifest {
+        line: usize,
+        message: String,
+    },
+    Io {
+        path: String,
+        message: String,
+    },
+    Zip {
+        path: String,
+        error: ZipError,
+    },
+    /// Dumps not found in the directory or archive.
+    Missing(Vec<String>),
+    Size {
+        chip: String,
+        expected: usize,
+        actual: usize,
+    },
+    Crc32 {
+        chip: String,
+        expected: u32,
+        actual: u32,
+    },
+    Sha1 {
+        chip: String,
+        expected: [u8; 20],
+        actual: [u8; 20],
+    },
+}
+
+fn parse_sha1(text: &str) -> Option<[u8; 20]> {
+    if text.len() != 40 || !text.is_ascii() {
+        return None;
+    }
+    let mut digest = [0; 20];
+    for (byte, pair) in digest.iter_mut().zip(text.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+impl Chip {
+    fn parse(line: &str) -> Result<Chip, String> {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let [name, address, size, crc, digest] = fields[..] else {
+            return Err(format!(
+                "expected `file address size crc32 sha1`, got {} fields",
+                fields.len()
+            ));
+        };
+        let chip = Chip {
+            name: name.to_string(),
+            address: u16::from_str_radix(address, 16)
+                .map_err(|_| format!("bad address {}", address))?,
+            size: usize::from_str_radix(size, 16).map_err(|_| format!("bad size {}", size))?,
+            crc32: u32::from_str_radix(crc, 16).map_err(|_| format!("bad CRC-32 {}", crc))?,
+            sha1: parse_sha1(digest).ok_or(format!("bad SHA-1 {}", digest))?,
+        };
+        if chip.size == 0 || chip.address as usize + chip.size > 0x10000 {
+            return Err(format!(
+                "{} does not fit the address space at {:04x}",
+                chip.name, chip.address
+            ));
+        }
+        Ok(chip)
+    }
+
+    /// Checks a dump of this chip.
+    pub fn verify(&self, data: &[u8]) -> Result<(), RomSetError> {
+        if data.len() != self.size {
+            return Err(RomSetError::Size {
+                chip: self.name.clone(),
+                expected: self.size,
+                actual: data.len(),
+            });
+        }
+        let actual = crc32(data);
+        if actual != self.crc32 {
+            return Err(RomSetError::Crc32 {
+                chip: self.name.clone(),
+                expected: self.crc32,
+                actual,
+            });
+        }
+        let actual = sha1(data);
+        if actual != self.sha1 {
+            return Err(RomSetError::Sha1 {
+                chip: self.name.clone(),
+                expected: self.sha1,
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl RomSet {
+    pub fn parse(name: &str, manifest: &str) -> Result<RomSet, RomSetError> {
+        let mut chips = Vec::new();
+        for (index, line) in manifest.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let chip = Chip::parse(line).map_err(|message| RomSetError::Manifest {
+                line: index + 1,
+                message,
+            })?;
+            chips.push(chip);
+        }
+        if chips.is_empty() {
+            return Err(RomSetError::Manifest {
+                line: 0,
+                message: "no chips listed".to_string(),
+            });
+        }
+        Ok(RomSet {
+            name: name.to_string(),
+            chips,
+        })
+    }
+
+    /// Size of the image the chips make up, from address 0.
+    pub fn image_size(&self) -> usize {
+        self.chips
+            .iter()
+            .map(|chip| chip.address as usize + chip.size)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Reads the dumps from `path`, a directory or a zip, and checks them.
+    /// Returned in manifest order.
+    pub fn read(&self, path: &Path) -> Result<Vec<Vec<u8>>, RomSetError> {
+        let files = match path.is_dir() {
+            true => self.read_dir(path)?,
+            false => self.read_zip(path)?,
+        };
+        let missing = self
+            .chips
+            .iter()
+            .zip(&files)
+            .filter(|(_, file)| file.is_none())
+            .map(|(chip, _)| chip.name.clone())
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(RomSetError::Missing(missing));
+        }
+        let dumps = files.into_iter().flatten().collect::<Vec<_>>();
+        for (chip, data) in self.chips.iter().zip(&dumps) {
+            chip.verify(data)?;
+        }
+        Ok(dumps)
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<Option<Vec<u8>>>, RomSetError> {
+        let entries = fs::read_dir(path).map_err(|error| RomSetError::Io {
+            path: path.display().to_string(),
+            message: error.to_string(),
+        })?;
+        let mut files = vec![None; self.chips.len()];
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some(index) = self.chip_index(&name) else {
+                continue;
+            };
+            let data = fs::read(entry.path()).map_err(|error| RomSetError::Io {
+                path: entry.path().display().to_string(),
+                message: error.to_string(),
+            })?;
+            files[index] = Some(data);
+        }
+        Ok(files)
+    }
+
+    fn read_zip(&self, path: &Path) -> Result<Vec<Option<Vec<u8>>>, RomSetError> {
+        let archive = fs::read(path).map_err(|error| RomSetError::Io {
+            path: path.display().to_string(),
+            message: error.to_string(),
+        })?;
+        // Dumps are matched by file name, wherever they are in the zip
+        let chip = |name: &str| self.chip_index(name.rsplit('/').next().unwrap_or_default());
+        let entries =
+            zip::read(&archive, |name| chip(name).is_some()).map_err(|error| RomSetError::Zip {
+                path: path.display().to_string(),
+                error,
+            })?;
+        let mut files = vec![None; self.chips.len()];
+        for entry in entries {
+            if let Some(index) = chip(&entry.name) {
+                files[index] = Some(entry.data);
+            }
+        }
+        Ok(files)
+    }
+
+    fn chip_index(&self, file_name: &str) -> Option<usize> {
+        self.chips
+            .iter()
+            .position(|chip| chip.name.eq_ignore_ascii_case(file_name))
+    }
+
+    /// Lays `dumps`, in manifest order, out at their load addresses. Gaps
+    /// between chips read as 0.
+    pub fn image(&self, dumps: &[Vec<u8>]) -> Vec<u8> {
+        let mut image = vec![0; self.image_size()];
+        for (chip, data) in self.chips.iter().zip(dumps) {
+            let start = chip.address as usize;
+            image[start..start + data.len()].copy_from_slice(data);
+        }
+        image
+    }
+
+    /// Reads, checks and lays out the set at `path` in one go.
+    pub fn load(&self, path: &Path) -> Result<Vec<u8>, RomSetError> {
+        self.read(path).map(|dumps| self.image(&dumps))
+    }
+}
+
+impl fmt::Display for RomSetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomSetError::Manifest { line, message } => {
+                write!(f, "manifest line {}: {}", line, message)
+            }
+            RomSetError::Io { path, message } => write!(f, "{}: {}", path, message),
+            RomSetError::Zip { path, error } => write!(f, "{}: {}", path, error),
+            RomSetError::Missing(chips) => write!(f, "missing ROM dumps: {}", chips.join(", ")),
+            RomSetError::Size {
+                chip,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: bad dump, expected {} bytes, got {}",
+                chip, expected, actual
+            ),
+            RomSetError::Crc32 {
+                chip,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: bad dump, expected CRC-32 {:08x}, got {:08x}",
+                chip, expected, actual
+            ),
+            RomSetError::Sha1 {
+                chip,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: bad dump, expected SHA-1 {}, got {}",
+                chip,
+                hex(expected),
+                hex(actual)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RomSetError {}
@@ -0,0 +1,144 @@
+//! Reads the files out of a zip archive. Only stored and deflated entries
+//! are supported, which covers every ROM set dump seen in practice; zip64
+//! and encryption are not.
+
+use std::fmt;
+
+use super::inflate::{inflate, InflateError};
+use crate::checksum::crc32;
+
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+const DIRECTORY_ENTRY: u32 = 0x0201_4b50;
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const END_OF_DIRECTORY_SIZE: usize = 22;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZipError {
+    /// No end of central directory record was found.
+    NotZip,
+    Truncated,
+    UnsupportedMethod {
+        name: String,
+        method: u16,
+    },
+    Inflate {
+        name: String,
+        error: InflateError,
+    },
+    /// An entry's contents do not match the CRC-32 stored with it.
+    ChecksumMismatch {
+        name: String,
+        expected: u32,
+        actual: u32,
+    },
+}
+
+/// One file of the archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, ZipError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or(ZipError::Truncated)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, ZipError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or(ZipError::Truncated)
+}
+
+fn slice(data: &[u8], offset: usize, length: usize) -> Result<&[u8], ZipError> {
+    data.get(offset..offset + length).ok_or(ZipError::Truncated)
+}
+
+/// The files in `archive` whose name `wanted` accepts, decompressed and
+/// checked against their CRC-32. Other files are never decompressed, so
+/// an unsupported method or a damaged entry elsewhere does not matter.
+/// Directories are skipped.
+pub fn read(archive: &[u8], wanted: impl Fn(&str) -> bool) -> Result<Vec<Entry>, ZipError> {
+    // The end record sits at the very end, unless a comment follows it
+    let end = (0..=archive.len().saturating_sub(END_OF_DIRECTORY_SIZE))
+        .rev()
+        .find(|&offset| u32_at(archive, offset) == Ok(END_OF_DIRECTORY))
+        .ok_or(ZipError::NotZip)?;
+    let count = u16_at(archive, end + 10)? as usize;
+    let mut offset = u32_at(archive, end + 16)? as usize;
+
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        if u32_at(archive, offset)? != DIRECTORY_ENTRY {
+            return Err(ZipError::Truncated);
+        }
+        let method = u16_at(archive, offset + 10)?;
+        let expected = u32_at(archive, offset + 16)?;
+        let compressed_size = u32_at(archive, offset + 20)? as usize;
+        let name_length = u16_at(archive, offset + 28)? as usize;
+        let extra_length = u16_at(archive, offset + 30)? as usize;
+        let comment_length = u16_at(archive, offset + 32)? as usize;
+        let local = u32_at(archive, offset + 42)? as usize;
+        let name = String::from_utf8_lossy(slice(archive, offset + 46, name_length)?).into_owned();
+        offset += 46 + name_length + extra_length + comment_length;
+        if name.ends_with('/') || !wanted(&name) {
+            continue;
+        }
+
+        if u32_at(archive, local)? != LOCAL_HEADER {
+            return Err(ZipError::Truncated);
+        }
+        let start = local
+            + 30
+            + u16_at(archive, local + 26)? as usize
+            + u16_at(archive, local + 28)? as usize;
+        let compressed = slice(archive, start, compressed_size)?;
+        let data = match method {
+            STORED => compressed.to_vec(),
+            DEFLATED => inflate(compressed).map_err(|error| ZipError::Inflate {
+                name: name.clone(),
+                error,
+            })?,
+            _ => return Err(ZipError::UnsupportedMethod { name, method }),
+        };
+        let actual = crc32(&data);
+        if actual != expected {
+            return Err(ZipError::ChecksumMismatch {
+                name,
+                expected,
+                actual,
+            });
+        }
+        entries.push(Entry { name, data });
+    }
+    Ok(entries)
+}
+
+impl fmt::Display for ZipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZipError::NotZip => write!(f, "not a zip archive"),
+            ZipError::Truncated => write!(f, "zip archive is truncated or corrupt"),
+            ZipError::UnsupportedMethod { name, method } => {
+                write!(f, "{}: unsupported compression method {}", name, method)
+            }
+            ZipError::Inflate { name, error } => write!(f, "{}: {}", name, error),
+            ZipError::ChecksumMismatch {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: CRC-32 mismatch in archive, expected {:08x}, got {:08x}",
+                name, expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ZipError {}
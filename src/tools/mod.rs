@@ -0,0 +1,13 @@
+pub mod snapshot_diff;
+pub mod trace_diff;
+
+/// Flag names and their bits in F, in the order the diffs report them.
+const FLAGS: [(&str, u8); 5] = [
+    ("S", 0x80),
+    ("Z", 0x40),
+    ("AC", 0x10),
+    ("P", 0x04),
+    ("CY", 0x01),
+];
+/// Names of `CpuState::registers`, in order.
+const REGISTER_NAMES: [&str; 7] = ["A", "B", "C", "D", "E", "H", "L"];
@@ -0,0 +1,278 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use super::{FLAGS, REGISTER_NAMES};
+use crate::cpu::{
+    save_state::{self, StateError, MAGIC},
+    CpuState,
+};
+
+const BYTES_PER_LINE: usize = 16;
+
+/// One side of the comparison: a save state, or a raw memory dump that has
+/// no registers.
+pub struct Snapshot {
+    pub state: Option<CpuState>,
+    pub memory: Vec<u8>,
+}
+
+impl Snapshot {
+    /// Treats `data` as a save state when it starts with the save state
+    /// magic, and as a memory dump loaded at address 0 otherwise.
+    pub fn load(data: &[u8]) -> Result<Snapshot, StateError> {
+        if !data.starts_with(MAGIC) {
+            return Ok(Snapshot {
+                state: None,
+                memory: data.to_vec(),
+            });
+        }
+        let (cpu, _) = save_state::parse(data)?;
+        Ok(Snapshot {
+            state: Some(cpu.state),
+            memory: cpu.memory.to_vec(),
+        })
+    }
+}
+
+/// A named address range used to group memory changes.
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub name: String,
+    pub range: RangeInclusive<u16>,
+}
+
+impl Region {
+    /// Parses `name=start-end` with hexadecimal addresses.
+    pub fn parse(value: &str) -> Option<Region> {
+        let (name, range) = value.split_once('=')?;
+        let (start, end) = range.split_once('-')?;
+        let parse =
+            |value: &str| u16::from_str_radix(value.trim().trim_start_matches("0x"), 16).ok();
+        Some(Region {
+            name: name.to_string(),
+            range: parse(start)?..=parse(end)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterChange {
+    pub name: &'static str,
+    pub left: u64,
+    pub right: u64,
+}
+
+/// A run of changed bytes. Short stretches of equal bytes between changes
+/// are folded in so related fields stay together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryChange {
+    pub start: usize,
+    pub left: Vec<u8>,
+    pub right: Vec<u8>,
+    /// Name of the region the run lies in, if any.
+    pub region: Option<String>,
+}
+
+pub struct DiffOptions {
+    /// Equal bytes allowed inside one change before it is split.
+    pub gap: usize,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions { gap: 4 }
+    }
+}
+
+pub struct SnapshotDiff {
+    pub registers: Vec<RegisterChange>,
+    pub memory: Vec<MemoryChange>,
+}
+
+fn register_changes(left: &CpuState, right: &CpuState) -> Vec<RegisterChange> {
+    let mut fields = vec![
+        ("PC", left.pc as u64, right.pc as u64),
+        ("SP", left.sp as u64, right.sp as u64),
+    ];
+    for (index, name) in REGISTER_NAMES.iter().enumerate() {
+        fields.push((
+            name,
+            left.registers[index] as u64,
+            right.registers[index] as u64,
+        ));
+    }
+    for (name, mask) in FLAGS {
+        fields.push((
+            name,
+            (left.f & mask != 0) as u64,
+            (right.f & mask != 0) as u64,
+        ));
+    }
+    fields.push(("CYC", left.cycles, right.cycles));
+    fields
+        .into_iter()
+        .filter(|(_, left, right)| left != right)
+        .map(|(name, left, right)| RegisterChange { name, left, right })
+        .collect()
+}
+
+fn region_of(regions: &[Region], address: usize) -> Option<&str> {
+    regions
+        .iter()
+        .find(|region| region.range.contains(&(address as u16)))
+        .map(|region| region.name.as_str())
+}
+
+/// Compares two snapshots. Registers are only compared when both sides
+/// have them; memory is compared over the shorter of the two.
+pub fn diff(
+    left: &Snapshot,
+    right: &Snapshot,
+    regions: &[Region],
+    options: &DiffOptions,
+) -> SnapshotDiff {
+    let registers = match (&left.state, &right.state) {
+        (Some(left), Some(right)) => register_changes(left, right),
+        _ => Vec::new(),
+    };
+    let length = left.memory.len().min(right.memory.len());
+    let mut memory: Vec<MemoryChange> = Vec::new();
+    let mut last_changed = None;
+    for address in 0..length {
+        if left.memory[address] == right.memory[address] {
+            continue;
+        }
+        let region = region_of(regions, address);
+        // Equal bytes are only folded in when they lie in the same region
+        let extend = last_changed.is_some_and(|last: usize| {
+            address - last <= options.gap + 1
+                && (last + 1..address).all(|gap| region_of(regions, gap) == region)
+        }) && memory
+            .last()
+            .is_some_and(|change| change.region.as_deref() == region);
+        if extend {
+            let change = memory.last_mut().unwrap();
+            let end = change.start + change.left.len();
+            change.left.extend_from_slice(&left.memory[end..=address]);
+            change.right.extend_from_slice(&right.memory[end..=address]);
+        } else {
+            memory.push(MemoryChange {
+                start: address,
+                left: vec![left.memory[address]],
+                right: vec![right.memory[address]],
+                region: region.map(str::to_string),
+            });
+        }
+        last_changed = Some(address);
+    }
+    SnapshotDiff { registers, memory }
+}
+
+fn write_bytes(out: &mut impl Write, label: &str, address: usize, bytes: &[u8]) -> io::Result<()> {
+    let hex = bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let ascii = bytes
+        .iter()
+        .map(|byte| match byte {
+            0x20..=0x7e => *byte as char,
+            _ => '.',
+        })
+        .collect::<String>();
+    writeln!(
+        out,
+        "  {:04x} {:<6}{:<width$}  |{}|",
+        address,
+        label,
+        hex,
+        ascii,
+        width = BYTES_PER_LINE * 3 - 1
+    )
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.memory.is_empty()
+    }
+
+    /// Register changes, then memory changes grouped by region in the
+    /// order the regions were given, unnamed memory last. Each change shows
+    /// both sides in hex and ASCII.
+    pub fn write_report(&self, out: &mut impl Write, regions: &[Region]) -> io::Result<()> {
+        if !self.registers.is_empty() {
+            writeln!(out, "registers:")?;
+            for change in &self.registers {
+                writeln!(out, "  {}", change)?;
+            }
+        }
+        let groups = regions
+            .iter()
+            .map(|region| Some(region.name.clone()))
+            .chain([None]);
+        for group in groups {
+            let changes = self
+                .memory
+                .iter()
+                .filter(|change| change.region == group)
+                .collect::<Vec<_>>();
+            if changes.is_empty() {
+                continue;
+            }
+            let changed = changes
+                .iter()
+                .map(|change| {
+                    change
+                        .left
+                        .iter()
+                        .zip(&change.right)
+                        .filter(|(left, right)| left != right)
+                        .count()
+                })
+                .sum::<usize>();
+            writeln!(
+                out,
+                "{} ({} bytes changed):",
+                group.as_deref().unwrap_or("memory"),
+                changed
+            )?;
+            for change in changes {
+                writeln!(out, "{}", change)?;
+                for offset in (0..change.left.len()).step_by(BYTES_PER_LINE) {
+                    let end = (offset + BYTES_PER_LINE).min(change.left.len());
+                    let address = change.start + offset;
+                    write_bytes(out, "left", address, &change.left[offset..end])?;
+                    write_bytes(out, "right", address, &change.right[offset..end])?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RegisterChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            "PC" | "SP" => write!(f, "{}: {:04x} -> {:04x}", self.name, self.left, self.right),
+            "CYC" => write!(f, "{}: {} -> {}", self.name, self.left, self.right),
+            _ if REGISTER_NAMES.contains(&self.name) => {
+                write!(f, "{}: {:02x} -> {:02x}", self.name, self.left, self.right)
+            }
+            _ => write!(f, "{}: {} -> {}", self.name, self.left, self.right),
+        }
+    }
+}
+
+impl fmt::Display for MemoryChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04x}-{:04x} ({} bytes)",
+            self.start,
+            self.start + self.left.len() - 1,
+            self.left.len()
+        )
+    }
+}
@@ -0,0 +1,207 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{FLAGS, REGISTER_NAMES};
+use crate::cpu::trace::ReferenceLine;
+
+pub struct DiffOptions {
+    /// Lines to show before and after the first mismatch.
+    pub context: usize,
+    /// Cycle counts differ between emulators that count branch timing
+    /// differently; this skips the CYC column.
+    pub ignore_cycles: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            context: 5,
+            ignore_cycles: false,
+        }
+    }
+}
+
+/// A parsed log line together with its 1-based line number in the file.
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub number: usize,
+    pub line: ReferenceLine,
+}
+
+pub fn parse_log(text: &str) -> Vec<LogLine> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            ReferenceLine::parse(line).map(|line| LogLine {
+                number: index + 1,
+                line,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct Mismatch {
+    pub field: String,
+    pub left: LogLine,
+    pub right: LogLine,
+    /// Matching lines leading up to the mismatch.
+    pub context: Vec<(LogLine, LogLine)>,
+    /// Lines following the mismatch in each log. The logs may have gone
+    /// separate ways by then, so they are kept apart.
+    pub left_after: Vec<LogLine>,
+    pub right_after: Vec<LogLine>,
+}
+
+/// One of the two logs being compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+#[derive(Debug)]
+pub enum DiffResult {
+    /// The logs never reach a common PC.
+    NotAligned,
+    /// Every aligned line matched; `compared` lines were checked.
+    Identical {
+        compared: usize,
+    },
+    /// Every aligned line matched, but the `ended` log stops at `last` while
+    /// the other one goes on with `next`.
+    EndOfLog {
+        compared: usize,
+        ended: Side,
+        last: LogLine,
+        next: LogLine,
+    },
+    Mismatch(Mismatch),
+}
+
+/// Finds the first pair of lines, one from each log, that share a PC.
+/// Logs often start at different points (reset vector vs. program entry).
+fn align(left: &[LogLine], right: &[LogLine]) -> Option<(usize, usize)> {
+    let mut first_seen = HashMap::new();
+    for (index, line) in right.iter().enumerate() {
+        first_seen.entry(line.line.state.pc).or_insert(index);
+    }
+    left.iter()
+        .enumerate()
+        .find_map(|(index, line)| Some((index, *first_seen.get(&line.line.state.pc)?)))
+}
+
+fn first_difference(
+    left: &ReferenceLine,
+    right: &ReferenceLine,
+    options: &DiffOptions,
+) -> Option<String> {
+    let (l, r) = (&left.state, &right.state);
+    if l.pc != r.pc {
+        return Some("PC".to_string());
+    }
+    if l.registers[0] != r.registers[0] {
+        return Some("A".to_string());
+    }
+    if let Some((name, _)) = FLAGS.iter().find(|(_, mask)| l.f & mask != r.f & mask) {
+        return Some(format!("flag {}", name));
+    }
+    if let Some(index) =
+        (1..REGISTER_NAMES.len()).find(|&index| l.registers[index] != r.registers[index])
+    {
+        return Some(REGISTER_NAMES[index].to_string());
+    }
+    if l.sp != r.sp {
+        return Some("SP".to_string());
+    }
+    if !options.ignore_cycles && l.cycles != r.cycles {
+        return Some("CYC".to_string());
+    }
+    None
+}
+
+pub fn diff(left: &[LogLine], right: &[LogLine], options: &DiffOptions) -> DiffResult {
+    let Some((left_start, right_start)) = align(left, right) else {
+        return DiffResult::NotAligned;
+    };
+    let pairs = left[left_start..].iter().zip(&right[right_start..]);
+    for (compared, (l, r)) in pairs.enumerate() {
+        if let Some(field) = first_difference(&l.line, &r.line, options) {
+            let skip = compared.saturating_sub(options.context);
+            let context = left[left_start + skip..left_start + compared]
+                .iter()
+                .cloned()
+                .zip(
+                    right[right_start + skip..right_start + compared]
+                        .iter()
+                        .cloned(),
+                )
+                .collect();
+            let after = |log: &[LogLine], index: usize| {
+                log.iter()
+                    .skip(index + 1)
+                    .take(options.context)
+                    .cloned()
+                    .collect()
+            };
+            return DiffResult::Mismatch(Mismatch {
+                field,
+                left: l.clone(),
+                right: r.clone(),
+                context,
+                left_after: after(left, left_start + compared),
+                right_after: after(right, right_start + compared),
+            });
+        }
+    }
+    let (left_rest, right_rest) = (left.len() - left_start, right.len() - right_start);
+    let compared = left_rest.min(right_rest);
+    let (ended, short, long) = match left_rest.cmp(&right_rest) {
+        Ordering::Equal => return DiffResult::Identical { compared },
+        Ordering::Less => (Side::Left, &left[left_start..], &right[right_start..]),
+        Ordering::Greater => (Side::Right, &right[right_start..], &left[left_start..]),
+    };
+    DiffResult::EndOfLog {
+        compared,
+        ended,
+        last: short[compared - 1].clone(),
+        next: long[compared].clone(),
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Side::Left => "left",
+            Side::Right => "right",
+        })
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "first mismatch: {} (left line {}, right line {})",
+            self.field, self.left.number, self.right.number
+        )?;
+        for (left, right) in &self.context {
+            writeln!(f, "  {:>6}  {}", left.number, left.line)?;
+            if left.line != right.line {
+                writeln!(f, "  {:>6}  {}", right.number, right.line)?;
+            }
+        }
+        writeln!(f, "< {:>6}  {}", self.left.number, self.left.line)?;
+        write!(f, "> {:>6}  {}", self.right.number, self.right.line)?;
+        for index in 0..self.left_after.len().max(self.right_after.len()) {
+            if let Some(left) = self.left_after.get(index) {
+                write!(f, "\n< {:>6}  {}", left.number, left.line)?;
+            }
+            if let Some(right) = self.right_after.get(index) {
+                write!(f, "\n> {:>6}  {}", right.number, right.line)?;
+            }
+        }
+        Ok(())
+    }
+}
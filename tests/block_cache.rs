@@ -0,0 +1,181 @@
+//! Running from the block cache must end in the same state as stepping one
+//! instruction at a time, including when code rewrites itself.
+
+use intel8080::cpu::{Cpu, CpuError};
+use intel8080::machines::{
+    invaders::{self, Io, CYCLES_PER_FRAME, INTERRUPTS, SCANLINES},
+    scheduler::Scheduler,
+};
+
+/// Steps while `cycles() < until`, which `run` promises to match.
+fn step_until(cpu: &mut Cpu, until: u64) -> Result<(), CpuError> {
+    while cpu.cycles() < until && !cpu.halted() {
+        cpu.step()?;
+    }
+    Ok(())
+}
+
+fn assert_same(stepped: &Cpu, cached: &Cpu, context: &str) {
+    assert_eq!(stepped.state(), cached.state(), "{}", context);
+    assert_eq!(stepped.instructions(), cached.instructions(), "{}", context);
+    assert_eq!(stepped.halted(), cached.halted(), "{}", context);
+    assert_eq!(stepped.memory(), cached.memory(), "{}", context);
+}
+
+/// Runs `program` both ways up to `until` in `chunks` calls.
+fn compare(program: &[u8], until: u64, chunks: u64) {
+    let mut stepped = Cpu::new();
+    let mut cached = Cpu::new();
+    cached.enable_block_cache();
+    stepped.load_rom(program);
+    cached.load_rom(program);
+    for chunk in 1..=chunks {
+        let target = until * chunk / chunks;
+        step_until(&mut stepped, target).unwrap();
+        cached.run(target, &[]).unwrap();
+        assert_same(&stepped, &cached, &format!("cycle {}", target));
+    }
+}
+
+#[test]
+fn write_ahead_in_the_running_block() {
+    // The STA patches the operand of the MVI two instructions later, in the
+    // block that is executing
+    #[rustfmt::skip]
+    let program = [
+        0x06, 0x00,       // 0000: MVI B,00
+        0x3a, 0x0a, 0x00, // 0002: LDA 000a
+        0x3c,             // INR A
+        0x32, 0x0a, 0x00, // STA 000a
+        0x0e, 0x00,       // 0009: MVI C,00
+        0x79,             // MOV A,C
+        0x80,             // ADD B
+        0x47,             // MOV B,A
+        0xc3, 0x02, 0x00, // JMP 0002
+    ];
+    compare(&program, 200_000, 7);
+}
+
+#[test]
+fn write_into_another_cached_block() {
+    // The loop body at 0010 runs from the cache, then the outer loop
+    // rewrites its opcode between INR C and DCR C
+    #[rustfmt::skip]
+    let program = [
+        0x31, 0x00, 0x02, // 0000: LXI SP,0200
+        0xcd, 0x10, 0x00, // 0003: CALL 0010
+        0x3a, 0x10, 0x00, // LDA 0010
+        0xee, 0x01,       // XRI 01
+        0x32, 0x10, 0x00, // STA 0010
+        0xc3, 0x03, 0x00, // JMP 0003
+        0x00,
+        0x0c,             // 0010: INR C (DCR C once patched)
+        0x79,             // MOV A,C
+        0x32, 0x00, 0x01, // STA 0100
+        0xc9,             // RET
+    ];
+    compare(&program, 100_000, 13);
+}
+
+#[test]
+fn random_memory() {
+    // Garbage code writes all over itself until it hits an unknown opcode
+    for seed in 0..300 {
+        let mut stepped = Cpu::new();
+        let mut cached = Cpu::new();
+        cached.enable_block_cache();
+        stepped.power_on(seed);
+        cached.power_on(seed);
+        let left = step_until(&mut stepped, 20_000);
+        let right = cached.run(20_000, &[]).map(|_| ());
+        assert_eq!(
+            left.map_err(|error| error.to_string()),
+            right.map_err(|error| error.to_string()),
+            "seed {}",
+            seed
+        );
+        assert_same(&stepped, &cached, &format!("seed {}", seed));
+    }
+}
+
+#[test]
+fn invaders_with_interrupts() {
+    let rom = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/ROM/invaders.concatenated"
+    ))
+    .unwrap();
+    // The same board without the block cache
+    let mut stepped = Cpu::new();
+    stepped.load_rom(&rom);
+    stepped.set_memory_map(invaders::memory_map());
+    stepped.set_ports(Box::new(Io::default()));
+    let mut scheduler = Scheduler::new(CYCLES_PER_FRAME, SCANLINES, &INTERRUPTS);
+
+    let mut machine = invaders::Invaders::new(&rom).unwrap();
+    assert!(machine.cpu().block_cache().is_some());
+    for frame in 1..=120 {
+        scheduler
+            .run(&mut stepped, frame * CYCLES_PER_FRAME)
+            .unwrap();
+        machine.run_frame().unwrap();
+        assert_same(&stepped, machine.cpu(), &format!("frame {}", frame));
+    }
+    assert_eq!(scheduler.raised(), machine.scheduler().raised());
+    assert!(machine.scheduler().raised() > 200);
+    assert!(machine.screen().lit() > 0);
+}
+
+/// Runs `program` to each of `targets` both ways, each from a fresh CPU,
+/// and checks the cached run skipped idle passes.
+fn compare_idle(program: &[u8], targets: impl Iterator<Item = u64>) {
+    for until in targets {
+        let mut stepped = Cpu::new();
+        let mut cached = Cpu::new();
+        cached.enable_block_cache();
+        stepped.load_rom(program);
+        cached.load_rom(program);
+        step_until(&mut stepped, until).unwrap();
+        cached.run(until, &[]).unwrap();
+        assert_same(&stepped, &cached, &format!("until {}", until));
+        assert_eq!(stepped.cycles(), cached.cycles(), "until {}", until);
+        if until > 1_000 {
+            assert!(cached.block_cache().unwrap().idle_cycles() > 0);
+        }
+    }
+}
+
+/// `until` on, just before and just after every multiple of `pass` from
+/// `start`, where the skip count `(until - cycles - 1) / pass` changes.
+fn boundaries(start: u64, pass: u64) -> impl Iterator<Item = u64> {
+    [1, 2, 3, 50, 1_000, 12_345]
+        .into_iter()
+        .flat_map(move |passes| {
+            let edge = start + passes * pass;
+            [edge - 1, edge, edge + 1]
+        })
+}
+
+#[test]
+fn idle_jump_to_self() {
+    // LXI SP,0100 takes 10 cycles, then JMP $ takes 10 per pass
+    let program = [0x31, 0x00, 0x01, 0xc3, 0x03, 0x00];
+    compare_idle(&program, boundaries(10, 10));
+    compare_idle(&program, 0..40);
+}
+
+#[test]
+fn idle_flag_polling() {
+    // MVI A,00 then LDA 0080; ORA A; JZ 0002 at 27 cycles per pass, the
+    // first pass settling the flags
+    #[rustfmt::skip]
+    let program = [
+        0x3e, 0x00,       // 0000: MVI A,00
+        0x3a, 0x80, 0x00, // 0002: LDA 0080
+        0xb7,             // ORA A
+        0xca, 0x02, 0x00, // JZ 0002
+    ];
+    compare_idle(&program, boundaries(7, 27));
+    compare_idle(&program, boundaries(7 + 27, 27));
+    compare_idle(&program, 0..100);
+}
@@ -0,0 +1,35 @@
+//! The call profiler sees interrupt handlers as calls to their RST vector.
+
+use intel8080::cpu::Cpu;
+
+#[test]
+fn interrupt_enters_the_handler() {
+    #[rustfmt::skip]
+    let program = [
+        0x31, 0x00, 0x01, // 0000: LXI SP,0100
+        0xfb,             // EI
+        0xc3, 0x04, 0x00, // 0004: JMP 0004
+        0x00,
+        0x00,             // 0008: NOP
+        0xfb,             // EI
+        0xc9,             // RET
+    ];
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&program);
+    cpu.enable_call_profiler();
+    for _ in 0..3 {
+        cpu.step().unwrap();
+    }
+    assert!(cpu.interrupt(1));
+    let profiler = cpu.call_profiler().unwrap();
+    assert_eq!(profiler.call_stack(), vec![0x0008]);
+    assert_eq!(profiler.functions()[&0x0008].calls, 1);
+
+    for _ in 0..3 {
+        cpu.step().unwrap();
+    }
+    let profiler = cpu.call_profiler().unwrap();
+    assert!(profiler.call_stack().is_empty());
+    // RST 11, NOP 4, EI 4 and RET 10
+    assert_eq!(profiler.functions()[&0x0008].inclusive, 29);
+}
@@ -0,0 +1,95 @@
+//! Coverage listings and LCOV output report every executed instruction,
+//! including code entered through the operand of another.
+
+use intel8080::cpu::{symbols::Symbols, Cpu};
+
+/// Runs `program` to its HLT with coverage enabled.
+fn covered(program: &[u8]) -> Cpu {
+    let mut cpu = Cpu::new();
+    cpu.load_rom(program);
+    cpu.enable_coverage();
+    while !cpu.halted() {
+        cpu.step().unwrap();
+    }
+    cpu
+}
+
+/// The address column of each listing line.
+fn listed(cpu: &Cpu, range: std::ops::Range<usize>) -> Vec<String> {
+    let mut out = Vec::new();
+    let coverage = cpu.coverage().unwrap();
+    coverage
+        .write_listing(&mut out, cpu.memory(), range, None)
+        .unwrap();
+    String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|line| line[..10].to_string())
+        .collect()
+}
+
+fn lcov_lines(cpu: &Cpu, range: std::ops::Range<usize>) -> Vec<String> {
+    let mut symbols = Symbols::new();
+    symbols.insert(0x0000, "start");
+    let mut out = Vec::new();
+    cpu.coverage()
+        .unwrap()
+        .write_lcov(&mut out, cpu.memory(), range, &symbols, "test.asm")
+        .unwrap();
+    String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .filter(|line| line.starts_with("DA:") || line.starts_with("L"))
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn hits_and_misses() {
+    #[rustfmt::skip]
+    let program = [
+        0x3e, 0x01,       // 0000: MVI A,01
+        0xb7,             // ORA A
+        0xc2, 0x08, 0x00, // JNZ 0008
+        0x3c,             // 0006: INR A
+        0x3c,             // INR A
+        0x76,             // 0008: HLT
+    ];
+    let cpu = covered(&program);
+    assert_eq!(
+        listed(&cpu, 0..9),
+        [
+            "0000  X---",
+            "0002  X---",
+            "0003  X---",
+            "0006  ----",
+            "0007  ----",
+            "0008  X---",
+        ]
+    );
+    assert_eq!(
+        lcov_lines(&cpu, 0..9),
+        ["DA:0,1", "DA:2,1", "DA:3,1", "DA:6,0", "DA:7,0", "DA:8,1", "LF:6", "LH:4"]
+    );
+}
+
+#[test]
+fn jump_into_an_operand() {
+    // The operand of the never executed MVI is run as INR A
+    #[rustfmt::skip]
+    let program = [
+        0xc3, 0x04, 0x00, // 0000: JMP 0004
+        0x3e, 0x3c,       // 0003: MVI A,3c
+        0x76,             // 0005: HLT
+    ];
+    let cpu = covered(&program);
+    assert_eq!(cpu.state().registers[0], 1);
+    assert_eq!(
+        listed(&cpu, 0..6),
+        ["0000  X---", "0003  ----", "0004  X---", "0005  X---"]
+    );
+    assert_eq!(
+        lcov_lines(&cpu, 0..6),
+        ["DA:0,1", "DA:3,0", "DA:4,1", "DA:5,1", "LF:4", "LH:3"]
+    );
+}
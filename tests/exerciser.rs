@@ -0,0 +1,103 @@
+//! Runs a CP/M CPU exerciser such as TST8080.COM, CPUTEST.COM or
+//! 8080EXER.COM from `ROM/exerciser.com`, or from `$EXERCISER` when set, and
+//! fails if it reports an error. Skipped when neither file exists. The long
+//! exercisers take minutes; run them with `cargo test --release`.
+
+use std::{cell::RefCell, rc::Rc};
+
+use intel8080::cpu::{io::Ports, Cpu, RunExit};
+
+/// Enough for 8080EXER, which is by far the longest.
+const CYCLE_LIMIT: u64 = 100_000_000_000;
+const BDOS: usize = 0xf000;
+
+/// Page zero: 0000 jumps to a boot stub that starts the program the first
+/// time and halts on the warm boot it ends with; 0005 jumps to the BDOS
+/// stub, whose address doubles as the top of the TPA.
+#[rustfmt::skip]
+const PAGE_ZERO: &[u8] = &[
+    0xc3, 0x40, 0x00, // 0000: JMP 0040
+    0x00, 0x00,
+    0xc3, 0x00, 0xf0, // 0005: JMP f000
+];
+
+#[rustfmt::skip]
+const BOOT: &[u8] = &[
+    0x21, 0x4d, 0x00, // 0040: LXI H,004d
+    0x7e,             // MOV A,M
+    0xb7,             // ORA A
+    0xc2, 0x4e, 0x00, // JNZ 004e
+    0x34,             // INR M
+    0xc3, 0x00, 0x01, // JMP 0100
+    0x00,             // 004c: unused
+    0x00,             // 004d: booted flag
+    0x76,             // 004e: HLT
+];
+
+/// BDOS functions 2 (character in E) and 9 (string at DE up to `$`),
+/// printed through OUT 1.
+#[rustfmt::skip]
+const BDOS_STUB: &[u8] = &[
+    0x79,             // f000: MOV A,C
+    0xfe, 0x02,       // CPI 2
+    0xca, 0x15, 0xf0, // JZ f015
+    0xfe, 0x09,       // CPI 9
+    0xc0,             // RNZ
+    0x1a,             // f009: LDAX D
+    0xfe, 0x24,       // CPI '$'
+    0xc8,             // RZ
+    0xd3, 0x01,       // OUT 1
+    0x13,             // INX D
+    0xc3, 0x09, 0xf0, // JMP f009
+    0x00, 0x00,
+    0x7b,             // f015: MOV A,E
+    0xd3, 0x01,       // OUT 1
+    0xc9,             // RET
+];
+
+struct Console(Rc<RefCell<String>>);
+
+impl Ports for Console {
+    fn input(&mut self, _port: u8) -> u8 {
+        0
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        if port == 1 {
+            self.0.borrow_mut().push(value as char);
+        }
+    }
+}
+
+#[test]
+fn exerciser() {
+    let path = std::env::var("EXERCISER").unwrap_or(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/ROM/exerciser.com"
+    )
+    .to_string());
+    let Ok(program) = std::fs::read(&path) else {
+        eprintln!("exerciser: skipped, {} not found", path);
+        return;
+    };
+    let mut image = vec![0; 0x10000];
+    image[..PAGE_ZERO.len()].copy_from_slice(PAGE_ZERO);
+    image[0x40..0x40 + BOOT.len()].copy_from_slice(BOOT);
+    image[0x100..0x100 + program.len()].copy_from_slice(&program);
+    image[BDOS..BDOS + BDOS_STUB.len()].copy_from_slice(BDOS_STUB);
+
+    let output = Rc::new(RefCell::new(String::new()));
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&image);
+    cpu.set_ports(Box::new(Console(output.clone())));
+    let exit = cpu.run(CYCLE_LIMIT, &[]).unwrap();
+    let output = output.borrow();
+    println!("{}", output);
+    assert_eq!(exit, RunExit::Halted, "did not finish: {}", output);
+    let upper = output.to_uppercase();
+    assert!(
+        !upper.contains("ERROR") && !upper.contains("FAIL"),
+        "{}",
+        output
+    );
+}
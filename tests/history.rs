@@ -0,0 +1,52 @@
+//! An unknown opcode is reported with the instructions that led up to it,
+//! oldest first, however often the history ring has wrapped.
+
+use intel8080::cpu::{Cpu, CpuError, ErrorKind};
+
+/// Six INR A, then the unknown opcode ed at 0006.
+fn fault(capacity: usize) -> CpuError {
+    let mut program = vec![0x3c; 6];
+    program.push(0xed);
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&program);
+    cpu.enable_history(capacity);
+    loop {
+        if let Err(error) = cpu.step() {
+            return error;
+        }
+    }
+}
+
+/// PC and A before each instruction in the history.
+fn trail(error: &CpuError) -> Vec<(u16, u8)> {
+    error
+        .history
+        .iter()
+        .map(|entry| (entry.state.pc, entry.state.registers[0]))
+        .collect()
+}
+
+#[test]
+fn unknown_opcode_carries_history() {
+    let error = fault(8);
+    assert_eq!(error.kind, ErrorKind::UnknownOpcode(0xed));
+    assert_eq!(error.pc, 0x0006);
+    assert_eq!(
+        trail(&error),
+        [(0, 0), (1, 1), (2, 2), (3, 3), (4, 4), (5, 5)]
+    );
+    assert!(error
+        .history
+        .iter()
+        .all(|entry| entry.instruction.opcode == 0x3c));
+    assert!(error.to_string().contains("last 6 instructions:"));
+}
+
+#[test]
+fn history_ring_wraps() {
+    // Six instructions go round each of these rings at least once
+    assert_eq!(trail(&fault(4)), [(2, 2), (3, 3), (4, 4), (5, 5)]);
+    assert_eq!(trail(&fault(3)), [(3, 3), (4, 4), (5, 5)]);
+    assert_eq!(trail(&fault(1)), [(5, 5)]);
+    assert!(fault(0).history.is_empty());
+}
@@ -0,0 +1,107 @@
+//! A recorded session replays to the same state without the ports or the
+//! interrupt source, and damaged logs are refused.
+
+use intel8080::cpu::{
+    input_log::{InputKind, InputLog, LogError},
+    io::Ports,
+    Cpu,
+};
+
+/// Sums IN 01 into C; the RST 1 handler counts interrupts in D.
+const PROGRAM: &[(u16, &[u8])] = &[
+    (0x0000, &[0x31, 0x00, 0x01]), // LXI SP,0100
+    (0x0003, &[0xfb]),             // EI
+    (0x0004, &[0xc3, 0x10, 0x00]), // JMP 0010
+    (0x0008, &[0x14, 0xfb, 0xc9]), // RST 1: INR D; EI; RET
+    (0x0010, &[0xdb, 0x01]),       // IN 01
+    (0x0012, &[0x81]),             // ADD C
+    (0x0013, &[0x4f]),             // MOV C,A
+    (0x0014, &[0xc3, 0x10, 0x00]), // JMP 0010
+];
+const STEPS: usize = 1_000;
+
+struct Counter(u8);
+
+impl Ports for Counter {
+    fn input(&mut self, _port: u8) -> u8 {
+        self.0 = self.0.wrapping_add(7);
+        self.0
+    }
+
+    fn output(&mut self, _port: u8, _value: u8) {}
+}
+
+fn rom() -> Vec<u8> {
+    let mut image = vec![0; 0x20];
+    for (address, code) in PROGRAM {
+        let address = *address as usize;
+        image[address..address + code.len()].copy_from_slice(code);
+    }
+    image
+}
+
+/// Runs with live ports and an interrupt every 97 steps.
+fn record() -> (Cpu, InputLog) {
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&rom());
+    cpu.set_ports(Box::new(Counter(0)));
+    cpu.start_recording(&rom());
+    for step in 1..=STEPS {
+        cpu.step().unwrap();
+        if step % 97 == 0 {
+            cpu.interrupt(1);
+        }
+    }
+    let log = cpu.take_recording().unwrap();
+    (cpu, log)
+}
+
+#[test]
+fn replay_reaches_the_recorded_state() {
+    let (recorded, log) = record();
+    let interrupts = log
+        .events
+        .iter()
+        .filter(|event| matches!(event.kind, InputKind::Interrupt { .. }))
+        .count();
+    assert!(interrupts > 5);
+    assert_eq!(recorded.state().registers[3] as usize, interrupts);
+
+    let parsed = InputLog::parse(&log.to_bytes()).unwrap();
+    assert_eq!(parsed, log);
+    parsed.check_rom(&rom()).unwrap();
+
+    // No ports and no interrupt calls: everything comes from the log
+    let mut replayed = Cpu::new();
+    replayed.load_rom(&rom());
+    replayed.start_replay(parsed);
+    for _ in 0..STEPS {
+        replayed.step().unwrap();
+    }
+    assert_eq!(replayed.state(), recorded.state());
+    assert_eq!(replayed.memory(), recorded.memory());
+    assert!(replayed.replayer().unwrap().finished());
+}
+
+#[test]
+fn damaged_logs_are_refused() {
+    let (_, log) = record();
+    let bytes = log.to_bytes();
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    assert_eq!(InputLog::parse(&bad_magic), Err(LogError::BadMagic));
+
+    assert_eq!(InputLog::parse(&bytes[..10]), Err(LogError::Truncated));
+    assert_eq!(
+        InputLog::parse(&bytes[..bytes.len() - 1]),
+        Err(LogError::Truncated)
+    );
+
+    let mut other_rom = rom();
+    other_rom[0x12] = 0x80;
+    assert!(matches!(
+        log.check_rom(&other_rom),
+        Err(LogError::RomMismatch { .. })
+    ));
+}
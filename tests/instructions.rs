@@ -0,0 +1,121 @@
+//! Behaviour of individual instructions, checked one program at a time.
+
+use intel8080::cpu::Cpu;
+
+const CY: u8 = 0x01;
+
+/// Runs `program` from 0000 for `steps` instructions.
+fn run(program: &[u8], steps: usize) -> Cpu {
+    let mut cpu = Cpu::new();
+    cpu.load_rom(program);
+    for _ in 0..steps {
+        cpu.step().unwrap();
+    }
+    cpu
+}
+
+/// A and CY after `MVI A,value`, setting CY to `carry`, then `opcode`.
+fn rotate(opcode: u8, value: u8, carry: bool) -> (u8, bool) {
+    // STC, then CMC to clear it again when needed
+    let set_carry: &[u8] = match carry {
+        true => &[0x37, 0x00],
+        false => &[0x37, 0x3f],
+    };
+    let mut program = vec![0x3e, value];
+    program.extend_from_slice(set_carry);
+    program.push(opcode);
+    let state = run(&program, 4).state();
+    (state.registers[0], state.f & CY != 0)
+}
+
+#[test]
+fn ral_rotates_through_carry() {
+    assert_eq!(rotate(0x17, 0x80, false), (0x00, true));
+    assert_eq!(rotate(0x17, 0x80, true), (0x01, true));
+    assert_eq!(rotate(0x17, 0x01, false), (0x02, false));
+    assert_eq!(rotate(0x17, 0x01, true), (0x03, false));
+    assert_eq!(rotate(0x17, 0xff, true), (0xff, true));
+    assert_eq!(rotate(0x17, 0x7f, false), (0xfe, false));
+}
+
+#[test]
+fn rar_rotates_through_carry() {
+    assert_eq!(rotate(0x1f, 0x01, false), (0x00, true));
+    assert_eq!(rotate(0x1f, 0x01, true), (0x80, true));
+    assert_eq!(rotate(0x1f, 0x80, false), (0x40, false));
+    assert_eq!(rotate(0x1f, 0x80, true), (0xc0, false));
+    assert_eq!(rotate(0x1f, 0xff, true), (0xff, true));
+    assert_eq!(rotate(0x1f, 0xfe, false), (0x7f, false));
+}
+
+/// Cycles per opcode from the 8080 data sheet, with conditional calls and
+/// returns not taken. Opcodes the CPU does not decode are skipped.
+#[rustfmt::skip]
+const CYCLES: [u64; 256] = [
+    4, 10,  7,  5,  5,  5,  7,  4,  4, 10,  7,  5,  5,  5,  7,  4, // 00
+    4, 10,  7,  5,  5,  5,  7,  4,  4, 10,  7,  5,  5,  5,  7,  4, // 10
+    4, 10, 16,  5,  5,  5,  7,  4,  4, 10, 16,  5,  5,  5,  7,  4, // 20
+    4, 10, 13,  5, 10, 10, 10,  4,  4, 10, 13,  5,  5,  5,  7,  4, // 30
+    5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5, // 40
+    5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5, // 50
+    5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5, // 60
+    7,  7,  7,  7,  7,  7,  7,  7,  5,  5,  5,  5,  5,  5,  7,  5, // 70
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 80
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 90
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // a0
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // b0
+    5, 10, 10, 10, 11, 11,  7, 11,  5, 10, 10, 10, 11, 17,  7, 11, // c0
+    5, 10, 10, 10, 11, 11,  7, 11,  5, 10, 10, 10, 11, 17,  7, 11, // d0
+    5, 10, 10, 18, 11, 11,  7, 11,  5,  5, 10,  4, 11, 17,  7, 11, // e0
+    5, 10, 10,  4, 11, 11,  7, 11,  5,  5, 10,  4, 11, 17,  7, 11, // f0
+];
+
+/// Cycles `opcode` takes with every flag clear or every flag set.
+fn cycles(opcode: u8, flags_set: bool) -> Option<u64> {
+    let mut image = vec![0; 0x200];
+    // LXI SP,0100; POP PSW; then the opcode with operands 0000
+    image[..5].copy_from_slice(&[0x31, 0x00, 0x01, 0xf1, opcode]);
+    image[0x100] = if flags_set { 0xd7 } else { 0x02 };
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&image);
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    let start = cpu.cycles();
+    cpu.step().ok()?;
+    Some(cpu.cycles() - start)
+}
+
+#[test]
+fn cycles_per_opcode() {
+    let mut checked = 0;
+    for opcode in 0..=255u8 {
+        for flags_set in [false, true] {
+            let Some(actual) = cycles(opcode, flags_set) else {
+                continue;
+            };
+            // Conditions are NZ, Z, NC, C, PO, PE, P, M: the odd ones hold
+            // when the flags are set
+            let conditional = opcode >= 0xc0 && matches!(opcode & 0x07, 0 | 4);
+            let taken = conditional && (opcode >> 3 & 1 == 1) == flags_set;
+            let expected = CYCLES[opcode as usize] + if taken { 6 } else { 0 };
+            assert_eq!(
+                actual, expected,
+                "opcode {:02x}, flags {}",
+                opcode,
+                if flags_set { "set" } else { "clear" }
+            );
+            checked += 1;
+        }
+    }
+    assert_eq!(checked, 244 * 2);
+}
+
+#[test]
+fn lhld_reads_little_endian_address() {
+    let mut program = vec![0; 0x1236];
+    program[..3].copy_from_slice(&[0x2a, 0x34, 0x12]); // LHLD 1234
+    program[0x1234..].copy_from_slice(&[0xcd, 0xab]);
+    let state = run(&program, 1).state();
+    // H and L
+    assert_eq!((state.registers[5], state.registers[6]), (0xab, 0xcd));
+}
@@ -0,0 +1,147 @@
+//! Lazy flag evaluation must be indistinguishable from computing the flags
+//! after every instruction.
+
+use intel8080::cpu::Cpu;
+
+const HALF_FRAME: u64 = 16_667;
+
+/// Opcodes that set or read flags, plus enough data movement to feed them.
+const FLAG_OPCODES: &[u8] = &[
+    0x80, 0x81, 0x87, 0x88, 0x8a, 0x8f, 0x90, 0x93, 0x97, 0x98, 0x9c, 0x9f, // ADD/ADC/SUB/SBB
+    0xa0, 0xa7, 0xa8, 0xaf, 0xb0, 0xb7, 0xb8, 0xbf, // ANA/XRA/ORA/CMP
+    0xc6, 0xce, 0xd6, 0xde, 0xe6, 0xee, 0xf6, 0xfe, // immediates
+    0x04, 0x05, 0x0c, 0x0d, 0x3c, 0x3d, // INR/DCR
+    0x07, 0x0f, 0x17, 0x1f, 0x27, 0x2f, 0x37, 0x3f, // rotates, DAA, CMA, STC, CMC
+    0x09, 0x19, 0x29, // DAD
+    0xf5, 0xf1, 0xc5, 0xc1, // PUSH/POP PSW, B
+    0x78, 0x47, 0x4f, 0x57, 0x3e, 0x06, // MOV, MVI
+];
+const JUMPS: &[u8] = &[0xc2, 0xca, 0xd2, 0xda, 0xe2, 0xea, 0xf2, 0xfa];
+
+/// SplitMix64, so the tests need no dependencies.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.next() as u8
+    }
+}
+
+/// A random program of flag-heavy instructions that loops forever.
+/// Conditional jumps skip a few NOPs forward; the last instruction jumps
+/// back to just after the stack set-up.
+fn flag_program(seed: u64, length: usize) -> Vec<u8> {
+    let mut rng = Rng(seed);
+    let mut program = vec![0x31, 0x00, 0x80]; // LXI SP,8000
+    while program.len() < length {
+        if rng.next().is_multiple_of(8) {
+            let opcode = JUMPS[rng.next() as usize % JUMPS.len()];
+            let skipped = rng.next() as usize % 4;
+            let target = (program.len() + 3 + skipped) as u16;
+            program.push(opcode);
+            program.extend_from_slice(&target.to_le_bytes());
+            program.extend(std::iter::repeat_n(0x00, skipped));
+            continue;
+        }
+        let opcode = FLAG_OPCODES[rng.next() as usize % FLAG_OPCODES.len()];
+        program.push(opcode);
+        if matches!(
+            opcode,
+            0xc6 | 0xce | 0xd6 | 0xde | 0xe6 | 0xee | 0xf6 | 0xfe | 0x3e | 0x06
+        ) {
+            program.push(rng.byte());
+        }
+    }
+    program.extend_from_slice(&[0xc3, 0x03, 0x00]); // JMP 0003
+    program
+}
+
+fn cpus(lazy_seed: Option<u64>) -> (Cpu, Cpu) {
+    let mut eager = Cpu::new();
+    let mut lazy = Cpu::new();
+    lazy.enable_lazy_flags();
+    if let Some(seed) = lazy_seed {
+        eager.power_on(seed);
+        lazy.power_on(seed);
+    }
+    (eager, lazy)
+}
+
+/// Steps both CPUs in lock step and fails on the first difference.
+fn compare(eager: &mut Cpu, lazy: &mut Cpu, steps: u64, interrupts: bool) {
+    let mut next_interrupt = HALF_FRAME;
+    let mut vector = 1;
+    for step in 0..steps {
+        let (left, right) = (eager.step(), lazy.step());
+        assert_eq!(
+            left.as_ref().map_err(ToString::to_string),
+            right.as_ref().map_err(ToString::to_string),
+            "step {}",
+            step
+        );
+        assert_eq!(eager.state(), lazy.state(), "step {}", step);
+        if left.is_err() {
+            break;
+        }
+        if interrupts && eager.cycles() >= next_interrupt {
+            eager.interrupt(vector);
+            lazy.interrupt(vector);
+            vector ^= 3;
+            next_interrupt += HALF_FRAME;
+        }
+    }
+    assert_eq!(eager.memory(), lazy.memory());
+}
+
+#[test]
+fn random_flag_programs() {
+    for seed in 0..200 {
+        let program = flag_program(seed, 0x200);
+        let (mut eager, mut lazy) = cpus(None);
+        eager.load_rom(&program);
+        lazy.load_rom(&program);
+        compare(&mut eager, &mut lazy, 5_000, false);
+    }
+}
+
+#[test]
+fn random_memory() {
+    // Garbage memory and registers run until an unknown opcode
+    for seed in 0..500 {
+        let (mut eager, mut lazy) = cpus(Some(seed));
+        compare(&mut eager, &mut lazy, 2_000, false);
+    }
+}
+
+#[test]
+fn invaders() {
+    let rom = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/ROM/invaders.concatenated"
+    ))
+    .unwrap();
+    let (mut eager, mut lazy) = cpus(None);
+    eager.load_rom(&rom);
+    lazy.load_rom(&rom);
+    compare(&mut eager, &mut lazy, 2_000_000, true);
+}
+
+#[test]
+fn save_state_and_blocks() {
+    let program = flag_program(7, 0x400);
+    let (mut eager, mut lazy) = cpus(None);
+    for cpu in [&mut eager, &mut lazy] {
+        cpu.enable_block_cache();
+        cpu.load_rom(&program);
+        cpu.run(1_000_000, &[]).unwrap();
+    }
+    assert_eq!(eager.save_state(&[]), lazy.save_state(&[]));
+}
@@ -0,0 +1,117 @@
+//! Writes to read-only regions under each policy, mirrored loads and
+//! stores, and addresses no region covers.
+
+use intel8080::cpu::{
+    memory_map::{permissions, Access, MemoryMap, Policy, Violation},
+    Cpu, CpuError, ErrorKind,
+};
+
+#[rustfmt::skip]
+const PROGRAM: [u8; 17] = [
+    0x3e, 0x55,       // 0000: MVI A,55
+    0x32, 0x20, 0x00, // 0002: STA 0020
+    0x32, 0x01, 0x40, // 0005: STA 4001
+    0x32, 0x00, 0x80, // 0008: STA 8000
+    0x3e, 0x00,       // 000b: MVI A,00
+    0x3a, 0x01, 0x44, // 000d: LDA 4401
+    0x76,             // HLT
+];
+
+/// ROM at 0000, 1 KiB of RAM at 2000 mirrored over 4000-47ff, nothing
+/// mapped above.
+fn map(policy: Policy) -> MemoryMap {
+    let mut map = MemoryMap::new();
+    map.add_region(
+        "rom",
+        0x0000..=0x00ff,
+        permissions::READ | permissions::EXECUTE,
+    );
+    map.add_region(
+        "ram",
+        0x2000..=0x23ff,
+        permissions::READ | permissions::WRITE,
+    );
+    map.add_mirror(0x4000..=0x47ff, 0x2000..=0x23ff);
+    for access in [Access::Read, Access::Write, Access::Execute] {
+        map.set_policy(access, policy);
+    }
+    map
+}
+
+fn run(policy: Policy) -> (Cpu, Result<(), CpuError>) {
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&PROGRAM);
+    cpu.set_memory_map(map(policy));
+    let mut result = Ok(());
+    while !cpu.halted() && result.is_ok() {
+        result = cpu.step();
+    }
+    (cpu, result)
+}
+
+fn rom_write() -> Violation {
+    Violation {
+        pc: 0x0002,
+        address: 0x0020,
+        access: Access::Write,
+        region: "rom".to_string(),
+    }
+}
+
+#[test]
+fn read_only_write_under_each_policy() {
+    let (cpu, result) = run(Policy::Ignore);
+    assert!(result.is_ok());
+    assert!(cpu.memory_map().unwrap().violations().is_empty());
+    assert_eq!(cpu.memory()[0x0020], 0x00);
+
+    let (cpu, result) = run(Policy::Log);
+    assert!(result.is_ok());
+    assert!(cpu.halted());
+    assert_eq!(cpu.memory_map().unwrap().violations(), [rom_write()]);
+    assert_eq!(cpu.memory()[0x0020], 0x00);
+
+    let (cpu, result) = run(Policy::Trap);
+    let error = result.unwrap_err();
+    assert_eq!(error.kind, ErrorKind::Protection(rom_write()));
+    assert!(!cpu.halted());
+    assert_eq!(cpu.memory()[0x0020], 0x00);
+}
+
+#[test]
+fn mirrored_and_unmapped_accesses() {
+    let map = map(Policy::Trap);
+    assert_eq!(map.translate(0x4001), 0x2001);
+    assert_eq!(map.translate(0x4401), 0x2001);
+    assert_eq!(map.translate(0x47ff), 0x23ff);
+    assert_eq!(map.translate(0x4800), 0x4800);
+    assert_eq!(map.translate(0x2001), 0x2001);
+
+    // With the ROM write dropped silently the rest of the program runs
+    let (cpu, result) = run(Policy::Ignore);
+    result.unwrap();
+    // The store through the mirror lands in RAM and the load reads it back
+    assert_eq!(cpu.memory()[0x2001], 0x55);
+    assert_eq!(cpu.memory()[0x4001], 0x00);
+    assert_eq!(cpu.state().registers[0], 0x55);
+    // Nothing maps 8000, so the store goes through
+    assert_eq!(cpu.memory()[0x8000], 0x55);
+    let map = cpu.memory_map().unwrap();
+    assert!(map.region(0x8000).is_none());
+    assert!(map.allows(0x8000, Access::Write));
+}
+
+#[test]
+fn unmapped_store_does_not_trap() {
+    let mut cpu = Cpu::new();
+    // STA 8000; HLT, with the program itself in unmapped memory
+    cpu.load_rom(&[0x32, 0x00, 0x80, 0x76]);
+    let mut map = MemoryMap::new();
+    map.add_region("rom", 0x1000..=0x1fff, permissions::READ);
+    map.set_policy(Access::Write, Policy::Trap);
+    cpu.set_memory_map(map);
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    assert!(cpu.halted());
+    assert!(cpu.memory_map().unwrap().violations().is_empty());
+}
@@ -0,0 +1,62 @@
+//! Execution counts and cycles per address and per opcode on a loop whose
+//! timing is known by hand.
+
+use intel8080::cpu::{profiler::Counter, Cpu};
+
+fn counter(count: u64, cycles: u64) -> Counter {
+    Counter { count, cycles }
+}
+
+#[test]
+fn counts_a_known_loop() {
+    // Three passes; the call is taken on the first two
+    #[rustfmt::skip]
+    let program = [
+        0x31, 0x00, 0x01, // 0000: LXI SP,0100
+        0x06, 0x03,       // 0003: MVI B,03
+        0x05,             // 0005: DCR B
+        0xc4, 0x0d, 0x00, // 0006: CNZ 000d
+        0xc2, 0x05, 0x00, // 0009: JNZ 0005
+        0x76,             // 000c: HLT
+        0xc9,             // 000d: RET
+    ];
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&program);
+    cpu.enable_profiler();
+    while !cpu.halted() {
+        cpu.step().unwrap();
+    }
+    let profiler = cpu.profiler().unwrap();
+
+    let addresses = [
+        (0x0000, counter(1, 10)),
+        (0x0003, counter(1, 7)),
+        (0x0005, counter(3, 15)),
+        // 17 twice when taken, 11 when not
+        (0x0006, counter(3, 45)),
+        (0x0009, counter(3, 30)),
+        (0x000c, counter(1, 7)),
+        (0x000d, counter(2, 20)),
+    ];
+    for (address, expected) in addresses {
+        assert_eq!(profiler.address(address), expected, "{:04x}", address);
+    }
+    assert_eq!(profiler.address(0x0001), Counter::default());
+
+    assert_eq!(profiler.opcode(0xc4), counter(3, 45));
+    assert_eq!(profiler.opcode(0xc9), counter(2, 20));
+    assert_eq!(profiler.opcode(0x05), counter(3, 15));
+    assert_eq!(profiler.total(), counter(14, 134));
+    assert_eq!(profiler.total().cycles, cpu.cycles());
+
+    let hottest = profiler
+        .hottest_addresses()
+        .into_iter()
+        .map(|(address, _)| address)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        hottest,
+        [0x0006, 0x0009, 0x000d, 0x0005, 0x0000, 0x0003, 0x000c]
+    );
+    assert_eq!(profiler.hottest_opcodes()[0], (0xc4, counter(3, 45)));
+}
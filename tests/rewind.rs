@@ -0,0 +1,151 @@
+//! Rewinding must land on exactly the state the original run passed
+//! through, interrupts and port reads included.
+
+use intel8080::cpu::{io::Ports, rewind::RewindConfig, rewind::Stop, Cpu, CpuState};
+
+/// Main loop stores each IN 1 and halts until the next interrupt; RST 1
+/// counts interrupts at 0081.
+#[rustfmt::skip]
+const PROGRAM: &[(u16, &[u8])] = &[
+    (0x0000, &[
+        0x31, 0x00, 0x01, // LXI SP,0100
+        0xfb,             // EI
+        0xc3, 0x10, 0x00, // JMP 0010
+    ]),
+    (0x0008, &[
+        0xc3, 0x20, 0x00, // JMP 0020
+    ]),
+    (0x0010, &[
+        0xdb, 0x01,       // IN 1
+        0x32, 0x80, 0x00, // STA 0080
+        0x76,             // HLT
+        0xc3, 0x10, 0x00, // JMP 0010
+    ]),
+    (0x0020, &[
+        0xf5,             // PUSH PSW
+        0x3a, 0x81, 0x00, // LDA 0081
+        0x3c,             // INR A
+        0x32, 0x81, 0x00, // 0025: STA 0081
+        0xf1,             // POP PSW
+        0xfb,             // EI
+        0xc9,             // RET
+    ]),
+];
+
+/// Everything the program can change. It never touches memory above its
+/// stack, so comparing the first page stands in for a whole save state.
+#[derive(Debug, PartialEq, Eq)]
+struct Moment {
+    state: CpuState,
+    instructions: u64,
+    interrupts_enabled: bool,
+    halted: bool,
+    page_zero: Vec<u8>,
+}
+
+impl Moment {
+    fn of(cpu: &Cpu) -> Moment {
+        Moment {
+            state: cpu.state(),
+            instructions: cpu.instructions(),
+            interrupts_enabled: cpu.interrupts_enabled(),
+            halted: cpu.halted(),
+            page_zero: cpu.memory()[..0x100].to_vec(),
+        }
+    }
+}
+
+/// Returns a different value on every read, so a replay that went to the
+/// live ports would not match.
+struct Counter(u8);
+
+impl Ports for Counter {
+    fn input(&mut self, _port: u8) -> u8 {
+        self.0 = self.0.wrapping_add(3);
+        self.0
+    }
+
+    fn output(&mut self, _port: u8, _value: u8) {}
+}
+
+fn machine() -> Cpu {
+    let mut image = vec![0; 0x30];
+    for (address, code) in PROGRAM {
+        let address = *address as usize;
+        image[address..address + code.len()].copy_from_slice(code);
+    }
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&image);
+    cpu.set_ports(Box::new(Counter(0)));
+    cpu.enable_rewind(RewindConfig {
+        interval: 16,
+        budget: 1 << 20,
+    });
+    cpu
+}
+
+/// Steps `iterations` times, raising RST 1 every fifth, and returns the
+/// state right after each instruction, indexed by instruction count.
+fn drive(cpu: &mut Cpu, iterations: usize) -> Vec<Moment> {
+    let mut states = vec![Moment::of(cpu)];
+    for iteration in 0..iterations {
+        if iteration % 5 == 0 {
+            cpu.interrupt(1);
+        }
+        let before = cpu.instructions();
+        cpu.step().unwrap();
+        if cpu.instructions() != before {
+            states.push(Moment::of(cpu));
+        }
+    }
+    states
+}
+
+#[test]
+fn rewinds_across_interrupts() {
+    let mut cpu = machine();
+    let states = drive(&mut cpu, 400);
+    assert!(cpu.memory()[0x81] > 10, "handler never ran");
+    let newest = states.len() as u64 - 1;
+    for target in [newest - 1, newest - 20, newest - 77, 150, 40, 3] {
+        cpu.rewind_to(target).unwrap();
+        assert_eq!(cpu.instructions(), target);
+        assert_eq!(
+            Moment::of(&cpu),
+            states[target as usize],
+            "instruction {}",
+            target
+        );
+    }
+}
+
+#[test]
+fn continues_after_rewinding() {
+    let mut cpu = machine();
+    let original = drive(&mut cpu, 400);
+    cpu.rewind_to(100).unwrap();
+    // Run on with inputs the original run did not see
+    cpu.set_ports(Box::new(Counter(0x80)));
+    let resumed = drive(&mut cpu, 300);
+    assert_ne!(resumed.last(), original.get(100 + resumed.len() - 1));
+    for offset in [resumed.len() - 1, resumed.len() - 30, 1] {
+        let target = 100 + offset as u64;
+        cpu.rewind_to(target).unwrap();
+        assert_eq!(Moment::of(&cpu), resumed[offset], "instruction {}", target);
+    }
+    // Inputs logged before the first rewind still replay as recorded
+    cpu.rewind_to(60).unwrap();
+    assert_eq!(Moment::of(&cpu), original[60]);
+}
+
+#[test]
+fn reverse_continue_stops_in_the_handler() {
+    let mut cpu = machine();
+    let states = drive(&mut cpu, 400);
+    let count = cpu.memory()[0x81];
+    assert!(cpu.reverse_continue(&[Stop::Watchpoint(0x81)]).unwrap());
+    let index = cpu.instructions() as usize;
+    assert_eq!(Moment::of(&cpu), states[index]);
+    assert_eq!(cpu.memory()[0x81], count - 1);
+    assert_eq!(cpu.state().pc, 0x25);
+}
@@ -0,0 +1,197 @@
+//! Known answers for the checksums, the inflater and the zip reader, and
+//! ROM sets loaded from the dumps in `ROM/`.
+
+use std::path::{Path, PathBuf};
+
+use intel8080::checksum::{adler32, crc32, hex, sha1};
+use intel8080::machines::invaders;
+use intel8080::romset::{
+    inflate::{inflate, InflateError},
+    zip::{self, ZipError},
+    RomSet, RomSetError,
+};
+
+/// Made by Python's zipfile: `set/a.bin` stored, `b.bin` deflated and
+/// `notes.txt` compressed with bzip2, which the reader does not support.
+const ARCHIVE: &str = "\
+504b0304140000000000000021001155d7990b0000000b000000090000007365742f612e62696e73746f\
+7265642064617461504b030414000000080000002100853c36550e0000004800000005000000622e6269\
+6e4b494dcb492c494d5148a18c0100504b03042e0000000c000000210042c38a78340000003c00000009\
+0000006e6f7465732e747874425a68393141592653597e6d177e00000e99804000100010204010200020\
+a54320c038989d4d4d4d4f8bb9229c28483f368bbf00504b01021403140000000000000021001155d799\
+0b0000000b0000000900000000000000000000008001000000007365742f612e62696e504b0102140314\
+000000080000002100853c36550e00000048000000050000000000000000000000800132000000622e62\
+696e504b01022e032e0000000c000000210042c38a78340000003c000000090000000000000000000000\
+8001630000006e6f7465732e747874504b05060000000003000300a1000000be0000000000";
+
+/// The two readable files of `ARCHIVE`.
+const MANIFEST: &str = "\
+a.bin 0000 000b 99d75511 b01d01d8418edb1a6dea4ab5a7d11cdc40648d68
+b.bin 0100 0048 55363c85 88f37197db78e5331127098c3328c1d98c497dea
+";
+
+fn unhex(text: &str) -> Vec<u8> {
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&text[index..index + 2], 16).unwrap())
+        .collect()
+}
+
+fn rom_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("ROM")
+}
+
+/// A scratch directory of its own for each test.
+fn scratch(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("intel8080-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+#[test]
+fn crc32_vectors() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    assert_eq!(
+        crc32(b"The quick brown fox jumps over the lazy dog"),
+        0x414fa339
+    );
+}
+
+#[test]
+fn sha1_vectors() {
+    let vectors: [(&[u8], &str); 3] = [
+        (b"", "da39a3ee5e6b4b0d3255bfef95601890afd80709"),
+        (b"abc", "a9993e364706816aba3e25717850c26c9cd0d89d"),
+        (
+            b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1",
+        ),
+    ];
+    for (data, digest) in vectors {
+        assert_eq!(hex(&sha1(data)), digest);
+    }
+    assert_eq!(
+        hex(&sha1(&vec![b'a'; 1_000_000])),
+        "34aa973cd4c4daa4f61eeb2bdbad27316534016f"
+    );
+}
+
+#[test]
+fn adler32_vectors() {
+    assert_eq!(adler32(b""), 1);
+    assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+}
+
+#[test]
+fn inflate_stored_block() {
+    let stream = [0x01, 0x05, 0x00, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o'];
+    assert_eq!(inflate(&stream).unwrap(), b"hello");
+    let mut bad_length = stream;
+    bad_length[3] = 0xfb;
+    assert_eq!(inflate(&bad_length), Err(InflateError::BadStoredLength));
+    assert_eq!(inflate(&stream[..7]), Err(InflateError::Truncated));
+}
+
+#[test]
+fn inflate_fixed_huffman_block() {
+    // zlib with Z_FIXED; the repeats come out as back-references
+    let stream = unhex("cb48cdc9c9d751c840a214caf38b725200");
+    assert_eq!(stream[0] >> 1 & 3, 1, "not a fixed Huffman block");
+    assert_eq!(inflate(&stream).unwrap(), b"hello, hello, hello world");
+}
+
+#[test]
+fn zip_reads_wanted_entries() {
+    let archive = unhex(ARCHIVE);
+    let entries = zip::read(&archive, |name| name.ends_with(".bin")).unwrap();
+    let files = entries
+        .iter()
+        .map(|entry| (entry.name.as_str(), entry.data.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        files,
+        [
+            ("set/a.bin", b"stored data".to_vec()),
+            ("b.bin", b"deflated ".repeat(8)),
+        ]
+    );
+    // The bzip2 entry only fails once it is asked for
+    assert_eq!(
+        zip::read(&archive, |_| true),
+        Err(ZipError::UnsupportedMethod {
+            name: "notes.txt".to_string(),
+            method: 12,
+        })
+    );
+    assert_eq!(zip::read(&archive, |_| false), Ok(vec![]));
+    assert_eq!(
+        zip::read(b"not a zip at all, just text", |_| true),
+        Err(ZipError::NotZip)
+    );
+}
+
+#[test]
+fn zip_checks_crc32() {
+    let mut archive = unhex(ARCHIVE);
+    // The first byte of the stored data of set/a.bin
+    archive[0x27] ^= 0x20;
+    let Err(ZipError::ChecksumMismatch { name, expected, .. }) =
+        zip::read(&archive, |name| name == "set/a.bin")
+    else {
+        panic!("expected a CRC-32 mismatch");
+    };
+    assert_eq!((name.as_str(), expected), ("set/a.bin", 0x99d75511));
+    assert!(zip::read(&archive, |name| name == "b.bin").is_ok());
+}
+
+#[test]
+fn romset_from_zip() {
+    let set = RomSet::parse("test", MANIFEST).unwrap();
+    let dir = scratch("romset-zip");
+    let path = dir.join("test.zip");
+    std::fs::write(&path, unhex(ARCHIVE)).unwrap();
+    let image = set.load(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(image.len(), 0x148);
+    assert_eq!(&image[..0x0b], b"stored data");
+    assert!(image[0x0b..0x100].iter().all(|byte| *byte == 0));
+    assert_eq!(&image[0x100..], b"deflated ".repeat(8));
+}
+
+#[test]
+fn invaders_from_dumps() {
+    let image = invaders::rom_set().load(&rom_dir()).unwrap();
+    let concatenated = std::fs::read(rom_dir().join("invaders.concatenated")).unwrap();
+    assert_eq!(image, concatenated);
+}
+
+#[test]
+fn invaders_missing_and_bad_dumps() {
+    let set = invaders::rom_set();
+    let dir = scratch("romset-bad");
+    for name in ["invaders.h", "invaders.g", "invaders.f"] {
+        std::fs::copy(rom_dir().join(name), dir.join(name)).unwrap();
+    }
+    let missing = set.load(&dir);
+
+    let mut dump = std::fs::read(rom_dir().join("invaders.e")).unwrap();
+    dump[0x100] ^= 0xff;
+    std::fs::write(dir.join("INVADERS.E"), &dump).unwrap();
+    let bad = set.load(&dir);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        missing,
+        Err(RomSetError::Missing(vec!["invaders.e".to_string()]))
+    );
+    assert_eq!(
+        bad,
+        Err(RomSetError::Crc32 {
+            chip: "invaders.e".to_string(),
+            expected: 0x14e538b0,
+            actual: crc32(&dump),
+        })
+    );
+}
@@ -0,0 +1,158 @@
+//! Save states round-trip exactly, and a state that is damaged or that a
+//! device refuses leaves the CPU and every device as they were.
+
+use intel8080::cpu::{
+    save_state::{DeviceState, StateError, HEADER_SIZE},
+    Cpu,
+};
+use intel8080::machines::shift_register::ShiftRegister;
+
+/// A device that takes any one byte except 0xff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Latch(u8);
+
+impl DeviceState for Latch {
+    fn name(&self) -> &str {
+        "latch"
+    }
+
+    fn save_state(&self, out: &mut Vec<u8>) {
+        out.push(self.0);
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        match data {
+            [value] if *value != 0xff => {
+                self.0 = *value;
+                Ok(())
+            }
+            _ => Err(StateError::Device {
+                name: self.name().to_string(),
+                message: "bad latch".to_string(),
+            }),
+        }
+    }
+}
+
+/// A CPU some way into a program that touches registers, flags, the stack
+/// and memory.
+fn running_cpu() -> Cpu {
+    #[rustfmt::skip]
+    let program = [
+        0x31, 0x00, 0x02, // LXI SP,0200
+        0x21, 0x34, 0x12, // LXI H,1234
+        0x3e, 0x80,       // MVI A,80
+        0x87,             // ADD A
+        0xf5,             // PUSH PSW
+        0x32, 0x00, 0x03, // STA 0300
+        0xfb,             // EI
+        0x76,             // HLT
+    ];
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&program);
+    for _ in 0..9 {
+        cpu.step().unwrap();
+    }
+    cpu
+}
+
+fn shift() -> ShiftRegister {
+    let mut shift = ShiftRegister::new();
+    shift.write_data(0x5a);
+    shift.set_offset(3);
+    shift
+}
+
+#[test]
+fn round_trip() {
+    let cpu = running_cpu();
+    let state = cpu.save_state(&[&shift(), &Latch(7)]);
+
+    let mut restored = Cpu::new();
+    let mut restored_shift = ShiftRegister::new();
+    let mut latch = Latch(0);
+    restored
+        .load_state(&state, &mut [&mut restored_shift, &mut latch])
+        .unwrap();
+    assert_eq!(restored.state(), cpu.state());
+    assert_eq!(restored.instructions(), cpu.instructions());
+    assert_eq!(restored.interrupts_enabled(), cpu.interrupts_enabled());
+    assert_eq!(restored.halted(), cpu.halted());
+    assert!(restored.halted() && restored.interrupts_enabled());
+    assert_eq!(restored.memory(), cpu.memory());
+    assert_eq!((restored_shift, latch), (shift(), Latch(7)));
+    assert_eq!(restored.save_state(&[&restored_shift, &latch]), state);
+}
+
+/// Loads `state` into a fresh CPU and devices, checks that it failed with
+/// `expected` and that nothing changed.
+fn assert_rejected(state: &[u8], expected: StateError) {
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&[0x3e, 0x42]);
+    cpu.step().unwrap();
+    let before = cpu.save_state(&[]);
+    let mut shift_register = ShiftRegister::new();
+    shift_register.write_data(0x99);
+    let mut latch = Latch(1);
+    assert_eq!(
+        cpu.load_state(state, &mut [&mut shift_register, &mut latch]),
+        Err(expected)
+    );
+    assert_eq!(cpu.save_state(&[]), before);
+    assert_eq!(shift_register.value(), 0x9900);
+    assert_eq!(latch, Latch(1));
+}
+
+#[test]
+fn rejects_bad_magic() {
+    let mut state = running_cpu().save_state(&[&shift(), &Latch(7)]);
+    state[0] = b'X';
+    assert_rejected(&state, StateError::BadMagic);
+}
+
+#[test]
+fn rejects_other_versions() {
+    let mut state = running_cpu().save_state(&[&shift(), &Latch(7)]);
+    state[4..6].copy_from_slice(&2u16.to_le_bytes());
+    assert_rejected(&state, StateError::UnsupportedVersion(2));
+}
+
+#[test]
+fn rejects_bad_checksum() {
+    let mut state = running_cpu().save_state(&[&shift(), &Latch(7)]);
+    let expected = u32::from_le_bytes(state[10..14].try_into().unwrap());
+    state[HEADER_SIZE + 100] ^= 0x01;
+    let StateError::ChecksumMismatch { actual, .. } =
+        Cpu::new().load_state(&state, &mut []).unwrap_err()
+    else {
+        panic!("expected a checksum mismatch");
+    };
+    assert_ne!(actual, expected);
+    assert_rejected(&state, StateError::ChecksumMismatch { expected, actual });
+}
+
+#[test]
+fn rejects_truncated() {
+    let state = running_cpu().save_state(&[&shift(), &Latch(7)]);
+    assert_rejected(&state[..HEADER_SIZE - 1], StateError::Truncated);
+    assert_rejected(&state[..state.len() - 1], StateError::Truncated);
+}
+
+#[test]
+fn rejects_missing_device() {
+    let state = running_cpu().save_state(&[&shift()]);
+    assert_rejected(&state, StateError::MissingDevice("latch".to_string()));
+}
+
+#[test]
+fn device_refusal_changes_nothing() {
+    // The shift register loads first, then the latch refuses its section
+    let state = running_cpu().save_state(&[&shift(), &Latch(0xff)]);
+    assert_rejected(
+        &state,
+        StateError::Device {
+            name: "latch".to_string(),
+            message: "bad latch".to_string(),
+        },
+    );
+}
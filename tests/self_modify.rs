@@ -0,0 +1,52 @@
+//! Stores into bytes that already ran as code are reported; stores into
+//! plain data are not.
+
+use intel8080::cpu::{self_modify::CodeWrite, Cpu, CpuError, ErrorKind};
+
+#[rustfmt::skip]
+const PROGRAM: [u8; 10] = [
+    0x3e, 0x3c,       // 0000: MVI A,3c
+    0x32, 0x00, 0x20, // 0002: STA 2000
+    0x3c,             // 0005: INR A
+    0x32, 0x01, 0x00, // 0006: STA 0001
+    0x76,             // 0009: HLT
+];
+
+const PATCH: CodeWrite = CodeWrite {
+    pc: 0x0006,
+    address: 0x0001,
+    old: 0x3c,
+    new: 0x3d,
+};
+
+fn run(break_on_write: bool) -> (Cpu, Result<(), CpuError>) {
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&PROGRAM);
+    cpu.enable_self_modify_detector(break_on_write);
+    let mut result = Ok(());
+    while !cpu.halted() && result.is_ok() {
+        result = cpu.step();
+    }
+    (cpu, result)
+}
+
+#[test]
+fn store_into_code_is_reported() {
+    let (cpu, result) = run(false);
+    result.unwrap();
+    let detector = cpu.self_modify_detector().unwrap();
+    // The data store at 0002 is not there
+    assert_eq!(detector.writes(), [PATCH]);
+    assert!(detector.is_executed(0x0001));
+    assert!(!detector.is_executed(0x2000));
+    assert_eq!(cpu.memory()[0x2000], 0x3c);
+    assert_eq!(cpu.memory()[0x0001], 0x3d);
+}
+
+#[test]
+fn breaks_on_the_first_code_store() {
+    let (cpu, result) = run(true);
+    assert_eq!(result.unwrap_err().kind, ErrorKind::SelfModify(PATCH));
+    assert!(!cpu.halted());
+    assert_eq!(cpu.state().pc, 0x0009);
+}
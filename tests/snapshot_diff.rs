@@ -0,0 +1,125 @@
+//! Register, flag and memory differences between two save states, with
+//! memory grouped by region.
+
+use intel8080::cpu::Cpu;
+use intel8080::tools::snapshot_diff::{diff, DiffOptions, MemoryChange, Region, Snapshot};
+
+/// Stores A at 2000, 2003, 2007 and 200a, with A and CY set from `a` and
+/// `carry`.
+fn snapshot(a: u8, carry: bool) -> Snapshot {
+    #[rustfmt::skip]
+    let program = [
+        0x3e, a,                             // 0000: MVI A,a
+        if carry { 0x37 } else { 0x00 },     // STC or NOP
+        0x32, 0x00, 0x20,                    // STA 2000
+        0x32, 0x03, 0x20,                    // STA 2003
+        0x32, 0x07, 0x20,                    // STA 2007
+        0x32, 0x0a, 0x20,                    // STA 200a
+        0x76,                                // HLT
+    ];
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&program);
+    while !cpu.halted() {
+        cpu.step().unwrap();
+    }
+    Snapshot::load(&cpu.save_state(&[])).unwrap()
+}
+
+fn region(name: &str, range: std::ops::RangeInclusive<u16>) -> Region {
+    Region {
+        name: name.to_string(),
+        range,
+    }
+}
+
+fn change(start: usize, length: usize, region: Option<&str>) -> (usize, usize, Option<String>) {
+    (start, length, region.map(str::to_string))
+}
+
+fn changes(memory: &[MemoryChange]) -> Vec<(usize, usize, Option<String>)> {
+    memory
+        .iter()
+        .map(|change| (change.start, change.left.len(), change.region.clone()))
+        .collect()
+}
+
+#[test]
+fn registers_and_flags() {
+    let found = diff(
+        &snapshot(0x00, false),
+        &snapshot(0x42, true),
+        &[],
+        &DiffOptions::default(),
+    );
+    let registers = found
+        .registers
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    assert_eq!(registers, ["A: 00 -> 42", "CY: 0 -> 1"]);
+    assert!(diff(
+        &snapshot(0x42, true),
+        &snapshot(0x42, true),
+        &[],
+        &DiffOptions::default()
+    )
+    .is_empty());
+}
+
+#[test]
+fn memory_ranges_by_region() {
+    let regions = [
+        region("rom", 0x0000..=0x00ff),
+        region("vars", 0x2000..=0x2003),
+        region("idle", 0x2008..=0x2009),
+    ];
+    let found = diff(
+        &snapshot(0x00, false),
+        &snapshot(0x42, true),
+        &regions,
+        &DiffOptions::default(),
+    );
+    assert_eq!(
+        changes(&found.memory),
+        [
+            // The MVI operand and STC
+            change(0x0001, 2, Some("rom")),
+            // Equal bytes within the gap are folded in
+            change(0x2000, 4, Some("vars")),
+            // Not folded across the unchanged idle region
+            change(0x2007, 1, None),
+            change(0x200a, 1, None),
+        ]
+    );
+    assert_eq!(found.memory[1].left, [0, 0, 0, 0]);
+    assert_eq!(found.memory[1].right, [0x42, 0, 0, 0x42]);
+
+    let mut report = Vec::new();
+    found.write_report(&mut report, &regions).unwrap();
+    let report = String::from_utf8(report).unwrap();
+    let headings = report
+        .lines()
+        .filter(|line| line.ends_with(':'))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        headings,
+        [
+            "registers:",
+            "rom (2 bytes changed):",
+            "vars (2 bytes changed):",
+            "memory (2 bytes changed):",
+        ]
+    );
+
+    // Without regions every change is unnamed and the gap joins them
+    let found = diff(
+        &snapshot(0x00, false),
+        &snapshot(0x42, true),
+        &[],
+        &DiffOptions::default(),
+    );
+    assert_eq!(
+        changes(&found.memory),
+        [change(0x0001, 2, None), change(0x2000, 11, None)]
+    );
+}
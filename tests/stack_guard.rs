@@ -0,0 +1,87 @@
+//! Stack alerts raised by an interrupt's push are reported against the
+//! interrupt, not the handler's first instruction, and `--stack-guard`
+//! protects all of a ROM that fills memory.
+
+use std::process::Command;
+
+use intel8080::cpu::{
+    stack_guard::{StackAlert, StackEvent, StackGuardConfig},
+    Cpu, ErrorKind,
+};
+
+/// Runs with a full stack until RST 1 pushes below the stack region.
+fn overflow(break_on_alert: bool) -> Cpu {
+    #[rustfmt::skip]
+    let program = [
+        0x31, 0x00, 0x01, // 0000: LXI SP,0100
+        0xfb,             // EI
+        0xc3, 0x04, 0x00, // 0004: JMP 0004
+        0x00,
+        0xfb,             // 0008: EI
+        0xc9,             // RET
+    ];
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&program);
+    cpu.enable_stack_guard(StackGuardConfig {
+        region: 0x0100..0x0200,
+        protected: vec![],
+        break_on_alert,
+    });
+    for _ in 0..3 {
+        cpu.step().unwrap();
+    }
+    assert!(cpu.interrupt(1));
+    cpu
+}
+
+#[test]
+fn interrupt_push_is_its_own_source() {
+    let mut cpu = overflow(false);
+    cpu.step().unwrap();
+    let expected = StackEvent {
+        pc: 0x0004,
+        interrupt: Some(1),
+        alert: StackAlert::Overflow { sp: 0x00fe },
+    };
+    assert_eq!(expected.to_string(), "0004: RST 1: stack overflow, SP=00fe");
+    assert_eq!(cpu.stack_guard().unwrap().events(), [expected]);
+}
+
+#[test]
+fn breaks_before_the_handler_runs() {
+    let mut cpu = overflow(true);
+    let error = cpu.step().unwrap_err();
+    assert_eq!(
+        error.kind,
+        ErrorKind::Stack(StackAlert::Overflow { sp: 0x00fe })
+    );
+    assert_eq!(cpu.state().pc, 0x0008);
+    assert!(!cpu.interrupts_enabled());
+    // The alert is reported once; the handler runs on the next step
+    cpu.step().unwrap();
+    assert!(cpu.interrupts_enabled());
+}
+
+#[test]
+fn full_size_rom_is_protected() {
+    let mut rom = vec![0; 0x10000];
+    rom[..4].copy_from_slice(&[
+        0x31, 0x00, 0x90, // 0000: LXI SP,9000
+        0xc5, //             PUSH B
+    ]);
+    let path = std::env::temp_dir().join(format!("intel8080-stack-rom-{}", std::process::id()));
+    std::fs::write(&path, &rom).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_Intel8080"))
+        .arg(&path)
+        .args(["--stack-guard", "0x8000-0x9000", "--steps", "2"])
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(
+        stdout.contains("stack alert 0003: push into protected memory at 8ffe"),
+        "{}",
+        stdout
+    );
+}
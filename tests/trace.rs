@@ -0,0 +1,162 @@
+//! The text, JSON and binary tracers, reading binary traces back and
+//! filtering records by address and instruction window.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use intel8080::cpu::trace::{
+    BinaryTraceReader, BinaryTracer, FilteredTracer, JsonTracer, TextTracer, TraceFilter,
+    TraceRecord, Tracer,
+};
+use intel8080::cpu::Cpu;
+
+#[rustfmt::skip]
+const COUNTDOWN: [u8; 7] = [
+    0x3e, 0x05,       // 0000: MVI A,05
+    0x3d,             // 0002: DCR A
+    0xc2, 0x02, 0x00, // JNZ 0002
+    0x76,             // HLT
+];
+
+/// A writer the test can still read after handing it to a tracer.
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Keeps every record it is given.
+#[derive(Clone, Default)]
+struct Collect(Rc<RefCell<Vec<TraceRecord>>>);
+
+impl Tracer for Collect {
+    fn trace(&mut self, record: &TraceRecord) {
+        self.0.borrow_mut().push(record.clone());
+    }
+}
+
+/// Runs `COUNTDOWN` to its HLT under `tracer`.
+fn trace(tracer: impl Tracer + 'static) {
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&COUNTDOWN);
+    cpu.set_tracer(Box::new(tracer));
+    while !cpu.halted() {
+        cpu.step().unwrap();
+    }
+    cpu.take_tracer().unwrap().flush().unwrap();
+}
+
+fn records() -> Vec<TraceRecord> {
+    let records = Collect::default();
+    trace(records.clone());
+    records.0.take()
+}
+
+fn text_of(out: Shared) -> String {
+    String::from_utf8(out.0.take()).unwrap()
+}
+
+#[test]
+fn every_instruction_is_traced() {
+    let records = records();
+    // MVI, five rounds of DCR and JNZ, HLT
+    assert_eq!(records.len(), 12);
+    let indices = records.iter().map(|record| record.index);
+    assert!(indices.eq(0..12));
+    let first = &records[0];
+    assert_eq!((first.before.pc, first.after.pc), (0x0000, 0x0002));
+    assert_eq!(first.after.registers[0], 5);
+    assert_eq!(records[11].instruction.opcode, 0x76);
+}
+
+#[test]
+fn text_lines() {
+    let out = Shared::default();
+    trace(TextTracer::new(out.clone()));
+    let text = text_of(out);
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 12);
+    assert!(
+        lines[0].starts_with("       0  0000  3e 05"),
+        "{}",
+        lines[0]
+    );
+    assert!(lines[0].ends_with("A: 05 F: 02 B: 00 C: 00 D: 00 E: 00 H: 00 L: 00 SP: 0000 CYC: 7"));
+    assert!(lines[11].starts_with("      11  0006  76"), "{}", lines[11]);
+}
+
+#[test]
+fn json_lines() {
+    let out = Shared::default();
+    trace(JsonTracer::new(out.clone()));
+    let text = text_of(out);
+    assert_eq!(text.lines().count(), 12);
+    // DCR A from 5 sets AC: no borrow out of bit 3
+    assert_eq!(
+        text.lines().nth(1),
+        Some(concat!(
+            "{\"index\":1,\"bytes\":[61],\"mnemonic\":\"DCR_A\",",
+            "\"before\":{\"pc\":2,\"sp\":0,\"a\":5,\"f\":2,\"b\":0,\"c\":0,\"d\":0,\"e\":0,\"h\":0,\"l\":0,\"cycles\":7},",
+            "\"after\":{\"pc\":3,\"sp\":0,\"a\":4,\"f\":18,\"b\":0,\"c\":0,\"d\":0,\"e\":0,\"h\":0,\"l\":0,\"cycles\":12}}"
+        ))
+    );
+}
+
+fn binary() -> Vec<u8> {
+    let out = Shared::default();
+    trace(BinaryTracer::new(out.clone()));
+    out.0.take()
+}
+
+#[test]
+fn binary_round_trip() {
+    let read = BinaryTraceReader::new(binary().as_slice())
+        .unwrap()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(read, records());
+}
+
+#[test]
+fn binary_truncated_record() {
+    let data = binary();
+    let mut reader = BinaryTraceReader::new(&data[..data.len() - 5]).unwrap();
+    for _ in 0..11 {
+        reader.next().unwrap().unwrap();
+    }
+    let error = reader.next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    assert!(reader.next().is_none());
+
+    let mut bad_magic = data.clone();
+    bad_magic[0] = b'X';
+    let error = BinaryTraceReader::new(bad_magic.as_slice()).err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn filter_by_address_and_window() {
+    let records = Collect::default();
+    let filter = TraceFilter {
+        pc: Some(0x0002..=0x0002),
+        window: Some(0..6),
+    };
+    trace(FilteredTracer::new(filter, records.clone()));
+    let indices = records
+        .0
+        .take()
+        .iter()
+        .map(|record| record.index)
+        .collect::<Vec<_>>();
+    // DCR A is every other instruction from index 1
+    assert_eq!(indices, [1, 3, 5]);
+}
@@ -0,0 +1,102 @@
+//! The first mismatch between two reference logs comes with context from
+//! both sides of it, and a log that stops early is reported as such.
+
+use intel8080::cpu::trace::ReferenceLine;
+use intel8080::tools::trace_diff::{
+    diff, parse_log, DiffOptions, DiffResult, LogLine, Mismatch, Side,
+};
+
+/// A log of `INR A` from A = 0, with B set to 1 from line `diverge` on.
+fn log(lines: usize, diverge: usize) -> String {
+    (0..lines)
+        .map(|index| {
+            let b = (index + 1 >= diverge) as u8;
+            format!(
+                "PC: {:04x}, AF: {:02x}02, BC: {:02x}00, DE: 0000, HL: 0000, SP: 0000, CYC: {} (3C)\n",
+                index,
+                index,
+                b,
+                index * 5
+            )
+        })
+        .collect()
+}
+
+fn mismatch(left: &str, right: &str, context: usize) -> Mismatch {
+    let options = DiffOptions {
+        context,
+        ..DiffOptions::default()
+    };
+    match diff(&parse_log(left), &parse_log(right), &options) {
+        DiffResult::Mismatch(mismatch) => mismatch,
+        other => panic!("expected a mismatch, got {:?}", other),
+    }
+}
+
+fn numbers(lines: &[LogLine]) -> Vec<usize> {
+    lines.iter().map(|line| line.number).collect()
+}
+
+#[test]
+fn context_after_the_mismatch() {
+    let found = mismatch(&log(20, usize::MAX), &log(20, 8), 3);
+    assert_eq!(found.field, "B");
+    assert_eq!((found.left.number, found.right.number), (8, 8));
+    let before = found.context.iter().map(|(left, _)| left.number);
+    assert_eq!(before.collect::<Vec<_>>(), [5, 6, 7]);
+    assert_eq!(numbers(&found.left_after), [9, 10, 11]);
+    assert_eq!(numbers(&found.right_after), [9, 10, 11]);
+
+    let text = found.to_string();
+    assert_eq!(text.lines().count(), 1 + 3 + 2 + 6);
+    let last = format!(">     11  {}", found.right_after[2].line);
+    assert_eq!(text.lines().last(), Some(last.as_str()));
+}
+
+#[test]
+fn context_stops_at_the_end_of_each_log() {
+    let found = mismatch(&log(10, usize::MAX), &log(12, 9), 5);
+    assert_eq!(numbers(&found.left_after), [10]);
+    assert_eq!(numbers(&found.right_after), [10, 11, 12]);
+    assert!(mismatch(&log(10, usize::MAX), &log(10, 10), 5)
+        .left_after
+        .is_empty());
+}
+
+#[test]
+fn one_log_ends_first() {
+    let options = DiffOptions::default();
+    for (left, right, ended, last, next) in [(8, 12, Side::Left, 8, 9), (12, 8, Side::Right, 8, 9)]
+    {
+        let result = diff(
+            &parse_log(&log(left, usize::MAX)),
+            &parse_log(&log(right, usize::MAX)),
+            &options,
+        );
+        let DiffResult::EndOfLog {
+            compared,
+            ended: side,
+            last: last_line,
+            next: next_line,
+        } = result
+        else {
+            panic!("expected the end of a log, got {:?}", result);
+        };
+        assert_eq!((compared, side), (8, ended));
+        assert_eq!((last_line.number, next_line.number), (last, next));
+    }
+    let same = diff(
+        &parse_log(&log(8, usize::MAX)),
+        &parse_log(&log(8, usize::MAX)),
+        &options,
+    );
+    assert!(matches!(same, DiffResult::Identical { compared: 8 }));
+}
+
+#[test]
+fn reference_line_round_trip() {
+    let text = "PC: 0100, AF: 0002, BC: 0000, DE: 0000, HL: 0000, SP: 0000, CYC: 0 (31 AD 06)";
+    let line = ReferenceLine::parse(text).unwrap();
+    assert_eq!(line.bytes, [0x31, 0xad, 0x06]);
+    assert_eq!(line.to_string(), text);
+}
@@ -0,0 +1,60 @@
+//! Reads of memory and registers that were never written are reported,
+//! reads after a write are not, and power-on garbage depends only on the
+//! seed.
+
+use intel8080::cpu::{
+    uninit::{slots, Location, UninitRead},
+    Cpu,
+};
+
+#[test]
+fn read_before_write_is_reported() {
+    #[rustfmt::skip]
+    let program = [
+        0x31, 0x00, 0x01, // 0000: LXI SP,0100
+        0x3a, 0x00, 0x20, // 0003: LDA 2000
+        0x32, 0x01, 0x20, // 0006: STA 2001
+        0x3a, 0x01, 0x20, // 0009: LDA 2001
+        0x78,             // 000c: MOV A,B
+        0x3a, 0x00, 0x20, // 000d: LDA 2000
+        0xc3, 0x03, 0x00, // 0010: JMP 0003
+    ];
+    let mut cpu = Cpu::new();
+    cpu.enable_init_tracker();
+    cpu.load_rom(&program);
+    // Twice round the loop
+    for _ in 0..13 {
+        cpu.step().unwrap();
+    }
+    let read = |pc, location| UninitRead { pc, location };
+    // The ROM counts as written, and each read is reported once per PC
+    assert_eq!(
+        cpu.init_tracker().unwrap().reads(),
+        [
+            read(0x0003, Location::Memory(0x2000)),
+            read(0x000c, Location::Register(slots::B)),
+            read(0x000d, Location::Memory(0x2000)),
+        ]
+    );
+    let tracker = cpu.init_tracker().unwrap();
+    assert!(tracker.is_memory_initialized(0x2001));
+    assert!(!tracker.is_memory_initialized(0x2000));
+    assert!(tracker.is_register_initialized(slots::A | slots::SP));
+    assert!(!tracker.is_register_initialized(slots::B));
+}
+
+#[test]
+fn power_on_is_deterministic() {
+    let power_on = |seed| {
+        let mut cpu = Cpu::new();
+        cpu.power_on(seed);
+        cpu
+    };
+    let (first, again, other) = (power_on(42), power_on(42), power_on(43));
+    assert_eq!(first.state(), again.state());
+    assert_eq!(first.memory(), again.memory());
+    assert_ne!(first.memory(), other.memory());
+    assert_ne!(first.state(), other.state());
+    assert!(first.memory().iter().any(|byte| *byte != 0));
+    assert_ne!(first.memory(), Cpu::new().memory());
+}